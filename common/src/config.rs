@@ -1,3 +1,4 @@
+use chrono::FixedOffset;
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 
@@ -8,6 +9,10 @@ pub struct ServerConfig {
     pub server: ServerSettings,
     pub paths: PathConfig,
     pub database: DatabaseConfig,
+    #[serde(default)]
+    pub zone: ZoneConfig,
+    #[serde(default)]
+    pub gm: GmConfig,
     #[serde(rename = "banners")]
     pub banners: Vec<Banner>,
 }
@@ -39,6 +44,65 @@ pub struct DatabaseConfig {
     pub path: PathBuf,
 }
 
+/// Time settings of the zone this server runs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ZoneConfig {
+    /// UTC offset of the zone's local time, e.g. "+08:00"
+    pub time_zone: String,
+    /// Local hour at which the server day rolls over
+    pub reset_hour: u32,
+    /// First day of the server week (0 = Sunday .. 6 = Saturday)
+    pub week_start_day: u32,
+}
+
+impl Default for ZoneConfig {
+    fn default() -> Self {
+        Self {
+            time_zone: "+00:00".to_string(),
+            reset_hour: 5,
+            week_start_day: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GmConfig {
+    /// Key expected in the `x-admin-key` header of admin api calls, empty disables the api
+    pub admin_api_key: String,
+    /// Port the gameserver takes the admin api commands forwarded by the sdkserver on
+    pub admin_port: u16,
+}
+
+impl Default for GmConfig {
+    fn default() -> Self {
+        Self {
+            admin_api_key: String::new(),
+            admin_port: 23302,
+        }
+    }
+}
+
+impl ZoneConfig {
+    pub fn utc_offset(&self) -> anyhow::Result<FixedOffset> {
+        self.time_zone
+            .parse::<FixedOffset>()
+            .map_err(|e| anyhow::anyhow!("Invalid zone time_zone '{}': {}", self.time_zone, e))
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        self.utc_offset()?;
+        if self.reset_hour > 23 {
+            anyhow::bail!("Invalid zone reset_hour: {}", self.reset_hour);
+        }
+        if self.week_start_day > 6 {
+            anyhow::bail!("Invalid zone week_start_day: {}", self.week_start_day);
+        }
+        Ok(())
+    }
+}
+
 impl ServerConfig {
     pub fn ensure_exists(path: &PathBuf) -> anyhow::Result<()> {
         if path.exists() {
@@ -81,13 +145,16 @@ impl ServerConfig {
             )
         })?;
 
-        toml::from_str(&content).map_err(|e| {
+        let config: Self = toml::from_str(&content).map_err(|e| {
             anyhow::anyhow!(
                 "Failed to parse config file '{}': {}",
                 config_path.display(),
                 e
             )
-        })
+        })?;
+
+        config.zone.validate()?;
+        Ok(config)
     }
 
     pub fn load_or_create(path: &PathBuf) -> anyhow::Result<Self> {
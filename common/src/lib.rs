@@ -16,6 +16,10 @@ pub fn config() -> &'static config::ServerConfig {
         .expect("Config not initialized - call init_config first")
 }
 
+pub fn try_config() -> Option<&'static config::ServerConfig> {
+    CONFIG.get()
+}
+
 pub fn host() -> &'static str {
     &config().server.host
 }
@@ -62,3 +66,12 @@ pub fn time_ms_u64() -> u64 {
         .unwrap()
         .as_millis() as u64
 }
+
+/// Compares two secrets without stopping at the first byte that differs
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
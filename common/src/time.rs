@@ -1,10 +1,14 @@
-use chrono::{DateTime, Datelike, Duration, TimeZone, Utc};
+use chrono::{DateTime, Datelike, Duration, FixedOffset, NaiveDateTime, TimeZone, Utc};
+
+use crate::config::ZoneConfig;
 
 pub struct ServerTime;
 
 const DAY_MS: i64 = 86_400_000;
-const RESET_OFFSET_MS: i64 = 5 * 60 * 60 * 1000;
-const RESET_OFFSET_SEC: i64 = 5 * 60 * 60;
+const HOUR_MS: i64 = 3_600_000;
+
+/// 1970-01-01 was a Thursday
+const EPOCH_WEEKDAY: i64 = 4;
 
 impl ServerTime {
     #[inline]
@@ -12,6 +16,28 @@ impl ServerTime {
         Utc::now().timestamp_millis()
     }
 
+    fn zone() -> ZoneConfig {
+        crate::try_config()
+            .map(|c| c.zone.clone())
+            .unwrap_or_default()
+    }
+
+    /// UTC offset of the configured zone
+    pub fn utc_offset() -> FixedOffset {
+        Self::zone()
+            .utc_offset()
+            .unwrap_or_else(|_| FixedOffset::east_opt(0).unwrap())
+    }
+
+    /// Shift applied to UTC so that a server day starts at 00:00
+    /// (zone offset minus the reset hour, -5h for a 05:00 UTC reset)
+    #[inline]
+    pub fn reset_shift_ms() -> i64 {
+        let zone = Self::zone();
+        let utc_offset_ms = Self::utc_offset().local_minus_utc() as i64 * 1000;
+        utc_offset_ms - zone.reset_hour as i64 * HOUR_MS
+    }
+
     #[inline]
     pub fn adjusted_datetime(timestamp_ms: i64) -> DateTime<Utc> {
         let utc = Utc
@@ -19,12 +45,18 @@ impl ServerTime {
             .single()
             .expect("invalid UTC timestamp");
 
-        utc - Duration::seconds(RESET_OFFSET_SEC)
+        utc + Duration::milliseconds(Self::reset_shift_ms())
     }
 
     #[inline]
     pub fn server_day(now_ms: i64) -> i64 {
-        (now_ms - RESET_OFFSET_MS) / DAY_MS
+        (now_ms + Self::reset_shift_ms()).div_euclid(DAY_MS)
+    }
+
+    /// Timestamp of the next daily reset boundary after `now_ms`
+    #[inline]
+    pub fn next_reset_ms(now_ms: i64) -> i64 {
+        (Self::server_day(now_ms) + 1) * DAY_MS - Self::reset_shift_ms()
     }
 
     #[inline]
@@ -44,9 +76,9 @@ impl ServerTime {
 
     #[inline]
     pub fn server_week(timestamp_ms: i64) -> i32 {
-        let adjusted = Self::adjusted_datetime(timestamp_ms);
-        let days = adjusted.timestamp() / 86_400;
-        ((days + 3) / 7) as i32
+        let days = Self::server_day(timestamp_ms);
+        let week_start = Self::zone().week_start_day as i64;
+        (days + (EPOCH_WEEKDAY - week_start).rem_euclid(7)).div_euclid(7) as i32
     }
 
     #[inline]
@@ -76,6 +108,16 @@ impl ServerTime {
         Self::adjusted_datetime(Self::now_ms())
     }
 
+    /// Parse a "%Y-%m-%d %H:%M:%S" wall clock time of the zone into a unix timestamp (ms)
+    pub fn parse_zone_datetime_ms(s: &str) -> anyhow::Result<i64> {
+        let naive = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S")?;
+        let local = Self::utc_offset()
+            .from_local_datetime(&naive)
+            .single()
+            .ok_or_else(|| anyhow::anyhow!("Ambiguous zone datetime: {}", s))?;
+        Ok(local.timestamp_millis())
+    }
+
     #[inline]
     pub fn now_sec_i32() -> i32 {
         (Self::now_ms() / 1000) as i32
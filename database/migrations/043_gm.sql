@@ -0,0 +1,15 @@
+ALTER TABLE users ADD COLUMN gm_role INTEGER NOT NULL DEFAULT 0; -- 0=player, 1=tester, 2=admin
+
+CREATE TABLE IF NOT EXISTS gm_audit_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    source TEXT NOT NULL,             -- 'chat', 'console', 'api'
+    invoker_id INTEGER NULL,          -- NULL when run from the console / admin api
+    target_user_id INTEGER NULL,
+    command TEXT NOT NULL,            -- full command line as typed
+    success BOOLEAN NOT NULL,
+    result TEXT NOT NULL,
+    created_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_gm_audit_log_target ON gm_audit_log(target_user_id);
+CREATE INDEX IF NOT EXISTS idx_gm_audit_log_time ON gm_audit_log(created_at);
@@ -0,0 +1,12 @@
+-- Seed each fight's randomness derives from, replaying battle_replays needs it
+CREATE TABLE IF NOT EXISTS battle_seeds (
+    user_id INTEGER NOT NULL,
+    episode_id INTEGER NOT NULL,
+    battle_id INTEGER NOT NULL,
+    seed INTEGER NOT NULL,
+    created_at INTEGER NOT NULL,
+    PRIMARY KEY (user_id, battle_id),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_battle_seeds ON battle_seeds(user_id, episode_id);
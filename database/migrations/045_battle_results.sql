@@ -0,0 +1,15 @@
+-- Final state of finished fights, the replay verifier compares re-simulations against it
+CREATE TABLE IF NOT EXISTS battle_results (
+    user_id INTEGER NOT NULL,
+    battle_id INTEGER NOT NULL,
+    episode_id INTEGER NOT NULL,
+    chapter_id INTEGER NOT NULL,
+    fight_group TEXT NOT NULL,  -- JSON FightGroup the fight was started with
+    rounds INTEGER NOT NULL,
+    entities TEXT NOT NULL,     -- JSON array of EntityOutcome
+    created_at INTEGER NOT NULL,
+    PRIMARY KEY (user_id, battle_id),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_battle_results ON battle_results(user_id, episode_id);
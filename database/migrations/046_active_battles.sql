@@ -0,0 +1,27 @@
+-- Fight each player is in the middle of, rewritten at every round boundary so it survives a
+-- disconnect or a restart. The fight state is read back from fight_data (049), rebuilding it
+-- from battle_seeds and battle_replays is only the fallback for rows without it. The other
+-- JSON columns are what the client is handed back on reconnect
+CREATE TABLE IF NOT EXISTS active_battles (
+    user_id INTEGER PRIMARY KEY,
+    battle_id INTEGER NOT NULL,     -- fight id, keys battle_seeds and battle_replays
+    episode_id INTEGER NOT NULL,
+    chapter_id INTEGER NOT NULL,
+    tower_type INTEGER,
+    tower_id INTEGER,
+    layer_id INTEGER,
+    difficulty INTEGER,
+    talent_plan_id INTEGER,
+    current_round INTEGER NOT NULL,
+    act_point INTEGER NOT NULL,
+    power INTEGER NOT NULL,
+    multiplication INTEGER,
+    seed INTEGER NOT NULL,
+    fight TEXT NOT NULL,            -- JSON Fight as of the last round
+    last_round TEXT,                -- JSON FightRound last sent to the client
+    fight_group TEXT NOT NULL,      -- JSON FightGroup
+    current_deck TEXT NOT NULL,     -- JSON array of CardInfo
+    ai_deck TEXT NOT NULL,          -- JSON array of CardInfo
+    updated_at INTEGER NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
@@ -0,0 +1,3 @@
+-- Mind's Eye skills used in the round that hasn't been played yet, they go into
+-- battle_replays with the round's opers once it is
+ALTER TABLE active_battles ADD COLUMN cloth_opers TEXT NOT NULL DEFAULT '[]'; -- JSON array of UseClothSkillOperRecord
@@ -0,0 +1,3 @@
+-- JSON of the fight state and decks a fight started from, the replay verifier starts
+-- from it instead of the heroes as they are now. Fights recorded before it have none.
+ALTER TABLE battle_results ADD COLUMN start_state TEXT;
@@ -0,0 +1,3 @@
+-- JSON of the fight state managers at the round boundary, the fight resumes from it instead
+-- of replaying the rounds again. Fights saved before it have none.
+ALTER TABLE active_battles ADD COLUMN fight_data TEXT;
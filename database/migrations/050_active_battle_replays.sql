@@ -0,0 +1,4 @@
+-- Replays of recorded fights are saved like any fight, reconnecting to one goes on
+-- playing back the recorded fight
+ALTER TABLE active_battles ADD COLUMN is_replay INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE active_battles ADD COLUMN replay_battle_id INTEGER; -- recorded fight the replay plays back
@@ -1,3 +1,4 @@
+use crate::models::game::battle::{BattleOutcome, BattleResult, RecordedBattle, SavedBattle};
 use anyhow::Result;
 use sqlx::SqlitePool;
 
@@ -31,10 +32,71 @@ pub async fn save_round_operations(
     Ok(())
 }
 
-pub async fn load_battle_replay(
+pub async fn save_battle_seed(
     pool: &SqlitePool,
     user_id: i64,
     episode_id: i32,
+    battle_id: i64,
+    seed: u64,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT OR REPLACE INTO battle_seeds (user_id, episode_id, battle_id, seed, created_at)
+         VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(user_id)
+    .bind(episode_id)
+    .bind(battle_id)
+    .bind(seed as i64)
+    .bind(chrono::Utc::now().timestamp())
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Latest fight of an episode that has a seed and recorded rounds, the one a record replays
+pub async fn latest_recorded_battle(
+    pool: &SqlitePool,
+    user_id: i64,
+    episode_id: i32,
+) -> Result<Option<i64>> {
+    let battle_id: Option<i64> = sqlx::query_scalar(
+        "SELECT s.battle_id
+         FROM battle_seeds s
+         JOIN battle_replays r ON r.user_id = s.user_id AND r.battle_id = s.battle_id
+         WHERE s.user_id = ? AND s.episode_id = ?
+         ORDER BY r.created_at DESC, s.battle_id DESC
+         LIMIT 1",
+    )
+    .bind(user_id)
+    .bind(episode_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(battle_id)
+}
+
+/// Seed a fight was played with
+pub async fn load_battle_seed(
+    pool: &SqlitePool,
+    user_id: i64,
+    battle_id: i64,
+) -> Result<Option<u64>> {
+    let seed: Option<i64> =
+        sqlx::query_scalar("SELECT seed FROM battle_seeds WHERE user_id = ? AND battle_id = ?")
+            .bind(user_id)
+            .bind(battle_id)
+            .fetch_optional(pool)
+            .await?;
+
+    Ok(seed.map(|s| s as u64))
+}
+
+/// Opers of every round of one recorded fight, what a replay of it is fed
+pub async fn load_battle_replay(
+    pool: &SqlitePool,
+    user_id: i64,
+    battle_id: i64,
 ) -> Result<Vec<sonettobuf::FightRoundOperRecord>> {
     #[allow(dead_code)]
     #[derive(sqlx::FromRow)]
@@ -47,11 +109,11 @@ pub async fn load_battle_replay(
     let rows: Vec<ReplayRow> = sqlx::query_as(
         "SELECT round_number, cloth_skill_opers, opers
          FROM battle_replays
-         WHERE user_id = ? AND episode_id = ?
+         WHERE user_id = ? AND battle_id = ?
          ORDER BY round_number",
     )
     .bind(user_id)
-    .bind(episode_id)
+    .bind(battle_id)
     .fetch_all(pool)
     .await?;
 
@@ -69,3 +131,276 @@ pub async fn load_battle_replay(
 
     Ok(records)
 }
+
+/// Record a fight as it starts, `start_state` is what the verifier replays it from
+pub async fn save_battle_start(
+    pool: &SqlitePool,
+    result: &BattleResult,
+    start_state: &str,
+) -> Result<()> {
+    let fight_group_json = serde_json::to_string(&result.fight_group)?;
+    let entities_json = serde_json::to_string(&result.outcome.entities)?;
+
+    sqlx::query(
+        "INSERT OR REPLACE INTO battle_results
+         (user_id, battle_id, episode_id, chapter_id, fight_group, rounds, entities,
+          start_state, created_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(result.user_id)
+    .bind(result.battle_id)
+    .bind(result.episode_id)
+    .bind(result.chapter_id)
+    .bind(fight_group_json)
+    .bind(result.outcome.rounds)
+    .bind(entities_json)
+    .bind(start_state)
+    .bind(chrono::Utc::now().timestamp())
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Update the outcome of a fight, the start state saved with it is kept
+pub async fn save_battle_result(pool: &SqlitePool, result: &BattleResult) -> Result<()> {
+    let fight_group_json = serde_json::to_string(&result.fight_group)?;
+    let entities_json = serde_json::to_string(&result.outcome.entities)?;
+
+    sqlx::query(
+        "INSERT INTO battle_results
+         (user_id, battle_id, episode_id, chapter_id, fight_group, rounds, entities, created_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+         ON CONFLICT (user_id, battle_id) DO UPDATE SET
+             rounds = excluded.rounds,
+             entities = excluded.entities",
+    )
+    .bind(result.user_id)
+    .bind(result.battle_id)
+    .bind(result.episode_id)
+    .bind(result.chapter_id)
+    .bind(fight_group_json)
+    .bind(result.outcome.rounds)
+    .bind(entities_json)
+    .bind(chrono::Utc::now().timestamp())
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Fights with a recorded seed and start state, optionally narrowed to a user or episode
+pub async fn load_recorded_battles(
+    pool: &SqlitePool,
+    user_id: Option<i64>,
+    episode_id: Option<i32>,
+) -> Result<Vec<RecordedBattle>> {
+    #[derive(sqlx::FromRow)]
+    struct ResultRow {
+        user_id: i64,
+        battle_id: i64,
+        episode_id: i32,
+        chapter_id: i32,
+        fight_group: String,
+        rounds: i32,
+        entities: String,
+        start_state: String,
+        seed: i64,
+    }
+
+    let rows: Vec<ResultRow> = sqlx::query_as(
+        "SELECT r.user_id, r.battle_id, r.episode_id, r.chapter_id, r.fight_group,
+                r.rounds, r.entities, r.start_state, s.seed
+         FROM battle_results r
+         JOIN battle_seeds s ON s.user_id = r.user_id AND s.battle_id = r.battle_id
+         WHERE r.start_state IS NOT NULL
+           AND (?1 IS NULL OR r.user_id = ?1) AND (?2 IS NULL OR r.episode_id = ?2)
+         ORDER BY r.user_id, r.battle_id",
+    )
+    .bind(user_id)
+    .bind(episode_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut battles = Vec::with_capacity(rows.len());
+    for row in rows {
+        battles.push(RecordedBattle {
+            seed: row.seed as u64,
+            start_state: row.start_state,
+            result: BattleResult {
+                user_id: row.user_id,
+                battle_id: row.battle_id,
+                episode_id: row.episode_id,
+                chapter_id: row.chapter_id,
+                fight_group: serde_json::from_str(&row.fight_group)?,
+                outcome: BattleOutcome {
+                    rounds: row.rounds,
+                    entities: serde_json::from_str(&row.entities)?,
+                },
+            },
+        });
+    }
+
+    Ok(battles)
+}
+
+/// Cloth skill opers and opers of every round of one fight, in round order
+pub async fn load_round_operations(
+    pool: &SqlitePool,
+    user_id: i64,
+    battle_id: i64,
+) -> Result<Vec<(i32, sonettobuf::FightRoundOperRecord)>> {
+    let rows: Vec<(i32, String, String)> = sqlx::query_as(
+        "SELECT round_number, cloth_skill_opers, opers
+         FROM battle_replays
+         WHERE user_id = ? AND battle_id = ?
+         ORDER BY round_number",
+    )
+    .bind(user_id)
+    .bind(battle_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut rounds = Vec::with_capacity(rows.len());
+    for (round_number, cloth_skill_opers, opers) in rows {
+        rounds.push((
+            round_number,
+            sonettobuf::FightRoundOperRecord {
+                cloth_skill_opers: serde_json::from_str(&cloth_skill_opers)?,
+                opers: serde_json::from_str(&opers)?,
+            },
+        ));
+    }
+
+    Ok(rounds)
+}
+
+/// Save the player's unfinished fight, replacing the one saved before
+pub async fn save_active_battle(pool: &SqlitePool, battle: &SavedBattle) -> Result<()> {
+    let last_round_json = battle
+        .last_round
+        .as_ref()
+        .map(serde_json::to_string)
+        .transpose()?;
+
+    sqlx::query(
+        "INSERT OR REPLACE INTO active_battles
+         (user_id, battle_id, episode_id, chapter_id, tower_type, tower_id, layer_id, difficulty,
+          talent_plan_id, current_round, act_point, power, multiplication, seed, fight, last_round,
+          fight_group, current_deck, ai_deck, cloth_opers, fight_data, is_replay, replay_battle_id,
+          updated_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(battle.user_id)
+    .bind(battle.battle_id)
+    .bind(battle.episode_id)
+    .bind(battle.chapter_id)
+    .bind(battle.tower_type)
+    .bind(battle.tower_id)
+    .bind(battle.layer_id)
+    .bind(battle.difficulty)
+    .bind(battle.talent_plan_id)
+    .bind(battle.current_round)
+    .bind(battle.act_point)
+    .bind(battle.power)
+    .bind(battle.multiplication)
+    .bind(battle.seed as i64)
+    .bind(serde_json::to_string(&battle.fight)?)
+    .bind(last_round_json)
+    .bind(serde_json::to_string(&battle.fight_group)?)
+    .bind(serde_json::to_string(&battle.current_deck)?)
+    .bind(serde_json::to_string(&battle.ai_deck)?)
+    .bind(serde_json::to_string(&battle.cloth_opers)?)
+    .bind(&battle.fight_data)
+    .bind(battle.is_replay)
+    .bind(battle.replay_battle_id)
+    .bind(chrono::Utc::now().timestamp())
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// The player's unfinished fight, if there is one
+pub async fn load_active_battle(pool: &SqlitePool, user_id: i64) -> Result<Option<SavedBattle>> {
+    #[derive(sqlx::FromRow)]
+    struct ActiveRow {
+        battle_id: i64,
+        episode_id: i32,
+        chapter_id: i32,
+        tower_type: Option<i32>,
+        tower_id: Option<i32>,
+        layer_id: Option<i32>,
+        difficulty: Option<i32>,
+        talent_plan_id: Option<i32>,
+        current_round: i32,
+        act_point: i32,
+        power: i32,
+        multiplication: Option<i32>,
+        seed: i64,
+        fight: String,
+        last_round: Option<String>,
+        fight_group: String,
+        current_deck: String,
+        ai_deck: String,
+        cloth_opers: String,
+        fight_data: Option<String>,
+        is_replay: bool,
+        replay_battle_id: Option<i64>,
+    }
+
+    let row: Option<ActiveRow> = sqlx::query_as(
+        "SELECT battle_id, episode_id, chapter_id, tower_type, tower_id, layer_id, difficulty,
+                talent_plan_id, current_round, act_point, power, multiplication, seed, fight,
+                last_round, fight_group, current_deck, ai_deck, cloth_opers, fight_data, is_replay,
+                replay_battle_id
+         FROM active_battles
+         WHERE user_id = ?",
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    let Some(row) = row else {
+        return Ok(None);
+    };
+
+    Ok(Some(SavedBattle {
+        user_id,
+        battle_id: row.battle_id,
+        episode_id: row.episode_id,
+        chapter_id: row.chapter_id,
+        tower_type: row.tower_type,
+        tower_id: row.tower_id,
+        layer_id: row.layer_id,
+        difficulty: row.difficulty,
+        talent_plan_id: row.talent_plan_id,
+        current_round: row.current_round,
+        act_point: row.act_point,
+        power: row.power,
+        multiplication: row.multiplication,
+        seed: row.seed as u64,
+        fight: serde_json::from_str(&row.fight)?,
+        last_round: row
+            .last_round
+            .as_deref()
+            .map(serde_json::from_str)
+            .transpose()?,
+        fight_group: serde_json::from_str(&row.fight_group)?,
+        current_deck: serde_json::from_str(&row.current_deck)?,
+        ai_deck: serde_json::from_str(&row.ai_deck)?,
+        cloth_opers: serde_json::from_str(&row.cloth_opers)?,
+        fight_data: row.fight_data,
+        is_replay: row.is_replay,
+        replay_battle_id: row.replay_battle_id,
+    }))
+}
+
+pub async fn delete_active_battle(pool: &SqlitePool, user_id: i64) -> Result<()> {
+    sqlx::query("DELETE FROM active_battles WHERE user_id = ?")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
@@ -80,6 +80,34 @@ pub async fn update_equipment_lock(
     Ok(rows_affected > 0)
 }
 
+/// Set level, breakthrough and refinement directly, no materials spent
+pub async fn set_equipment_level(
+    pool: &SqlitePool,
+    user_id: i64,
+    uid: i64,
+    level: i32,
+    break_lv: i32,
+    refine_lv: i32,
+) -> Result<bool> {
+    let now = common::time::ServerTime::now_ms();
+
+    let rows_affected = sqlx::query(
+        "UPDATE equipment SET level = ?, break_lv = ?, refine_lv = ?, updated_at = ?
+         WHERE uid = ? AND user_id = ?",
+    )
+    .bind(level)
+    .bind(break_lv)
+    .bind(refine_lv)
+    .bind(now)
+    .bind(uid)
+    .bind(user_id)
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    Ok(rows_affected > 0)
+}
+
 pub async fn build_equip_records(
     pool: &SqlitePool,
     player_id: i64,
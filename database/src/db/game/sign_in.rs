@@ -157,6 +157,27 @@ pub async fn process_manual_sign_in(pool: &SqlitePool, user_id: i64) -> Result<b
 
 /// Reset daily counters (call this for any daily-reset systems)
 pub async fn reset_daily_counters(pool: &SqlitePool, user_id: i64) -> Result<()> {
+    let game_data = config::configs::get();
+
+    let daily_store_goods: Vec<i32> = game_data
+        .store_goods
+        .iter()
+        .filter(|g| g.refresh_time == 1)
+        .map(|g| g.id)
+        .collect();
+
+    for goods_id in &daily_store_goods {
+        sqlx::query(
+            "UPDATE user_store_goods
+             SET buy_count = 0
+             WHERE user_id = ? AND goods_id = ?",
+        )
+        .bind(user_id)
+        .bind(goods_id)
+        .execute(pool)
+        .await?;
+    }
+
     // Reset dungeon daily attempts
     sqlx::query(
         "UPDATE user_dungeons SET today_pass_num = 0, today_total_num = 0 WHERE user_id = ?",
@@ -173,6 +194,12 @@ pub async fn reset_daily_counters(pool: &SqlitePool, user_id: i64) -> Result<()>
     .execute(pool)
     .await?;
 
+    // Reset tower mop-ups
+    sqlx::query("UPDATE user_tower_info SET mop_up_times = 0 WHERE user_id = ?")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
     // Reset hero touch count
     sqlx::query(
         r#"
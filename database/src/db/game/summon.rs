@@ -1,7 +1,8 @@
 use crate::models::game::summon::*;
 use anyhow::Result;
-use chrono::{NaiveDateTime, TimeZone, Utc};
+use chrono::Utc;
 use common::config::Banner;
+use common::time::ServerTime;
 use sonettobuf::SummonResult;
 use sqlx::SqlitePool;
 
@@ -131,9 +132,9 @@ pub async fn sync_banner_schedule(db: &SqlitePool, banners: &[Banner]) -> anyhow
     Ok(())
 }
 
+/// Banner times are wall clock times of the configured zone
 fn parse_ts_seconds(s: &str) -> anyhow::Result<i32> {
-    let dt = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S")?;
-    Ok(Utc.from_utc_datetime(&dt).timestamp() as i32)
+    Ok((ServerTime::parse_zone_datetime_ms(s)? / 1000) as i32)
 }
 
 async fn get_lucky_bag_info(
@@ -25,13 +25,7 @@ pub async fn get_tower_info(
             });
 
     // Get tower opens
-    let tower_opens = sqlx::query_as::<_, TowerOpen>(
-        "SELECT tower_type, tower_id, status, round, next_time, tower_start_time, task_end_time
-         FROM user_tower_opens WHERE user_id = ? ORDER BY tower_type, tower_id",
-    )
-    .bind(user_id)
-    .fetch_all(pool)
-    .await?;
+    let tower_opens = get_tower_opens(pool, user_id).await?;
 
     // Get towers
     let towers = get_towers(pool, user_id).await?;
@@ -53,42 +47,100 @@ async fn get_towers(pool: &SqlitePool, user_id: i64) -> Result<Vec<sonettobuf::T
 
     let mut towers = Vec::new();
     for (tower_type, tower_id, pass_layer_id, history_high_score, params) in tower_data {
-        // Get layers
-        let layer_nos = get_tower_layers(pool, user_id, tower_type, tower_id).await?;
+        towers.push(
+            build_tower_no(
+                pool,
+                user_id,
+                tower_type,
+                tower_id,
+                pass_layer_id,
+                history_high_score,
+                params,
+            )
+            .await?,
+        );
+    }
 
-        // Get open special layer IDs
-        let open_sp_layer_ids = sqlx::query_scalar(
-            "SELECT sp_layer_id FROM user_tower_open_sp_layers WHERE user_id = ? AND tower_type = ? AND tower_id = ?"
-        )
-        .bind(user_id)
-        .bind(tower_type)
-        .bind(tower_id)
-        .fetch_all(pool)
-        .await?;
+    Ok(towers)
+}
 
-        // Get pass teach IDs
-        let pass_teach_ids = sqlx::query_scalar(
-            "SELECT teach_id FROM user_tower_pass_teaches WHERE user_id = ? AND tower_type = ? AND tower_id = ?"
-        )
-        .bind(user_id)
-        .bind(tower_type)
-        .bind(tower_id)
-        .fetch_all(pool)
-        .await?;
+/// Get one tower with its layers, `None` if the player has never entered it
+pub async fn get_tower(
+    pool: &SqlitePool,
+    user_id: i64,
+    tower_type: i32,
+    tower_id: i32,
+) -> Result<Option<sonettobuf::TowerNo>> {
+    let tower_data: Option<(i32, i32, String)> = sqlx::query_as(
+        "SELECT pass_layer_id, history_high_score, params
+         FROM user_towers WHERE user_id = ? AND tower_type = ? AND tower_id = ?",
+    )
+    .bind(user_id)
+    .bind(tower_type)
+    .bind(tower_id)
+    .fetch_optional(pool)
+    .await?;
 
-        towers.push(sonettobuf::TowerNo {
-            r#type: Some(tower_type),
-            tower_id: Some(tower_id),
-            pass_layer_id: Some(pass_layer_id),
-            layer_n_os: layer_nos,
-            open_sp_layer_ids,
-            history_high_score: Some(history_high_score),
-            params: Some(params),
-            pass_teach_ids,
-        });
-    }
+    let Some((pass_layer_id, history_high_score, params)) = tower_data else {
+        return Ok(None);
+    };
+
+    let tower = build_tower_no(
+        pool,
+        user_id,
+        tower_type,
+        tower_id,
+        pass_layer_id,
+        history_high_score,
+        params,
+    )
+    .await?;
 
-    Ok(towers)
+    Ok(Some(tower))
+}
+
+async fn build_tower_no(
+    pool: &SqlitePool,
+    user_id: i64,
+    tower_type: i32,
+    tower_id: i32,
+    pass_layer_id: i32,
+    history_high_score: i32,
+    params: String,
+) -> Result<sonettobuf::TowerNo> {
+    // Get layers
+    let layer_nos = get_tower_layers(pool, user_id, tower_type, tower_id).await?;
+
+    // Get open special layer IDs
+    let open_sp_layer_ids = sqlx::query_scalar(
+        "SELECT sp_layer_id FROM user_tower_open_sp_layers WHERE user_id = ? AND tower_type = ? AND tower_id = ?"
+    )
+    .bind(user_id)
+    .bind(tower_type)
+    .bind(tower_id)
+    .fetch_all(pool)
+    .await?;
+
+    // Get pass teach IDs
+    let pass_teach_ids = sqlx::query_scalar(
+        "SELECT teach_id FROM user_tower_pass_teaches WHERE user_id = ? AND tower_type = ? AND tower_id = ?"
+    )
+    .bind(user_id)
+    .bind(tower_type)
+    .bind(tower_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(sonettobuf::TowerNo {
+        r#type: Some(tower_type),
+        tower_id: Some(tower_id),
+        pass_layer_id: Some(pass_layer_id),
+        layer_n_os: layer_nos,
+        open_sp_layer_ids,
+        history_high_score: Some(history_high_score),
+        params: Some(params),
+        pass_teach_ids,
+    })
 }
 
 async fn get_tower_layers(
@@ -123,6 +175,40 @@ async fn get_tower_layers(
     Ok(layer_nos)
 }
 
+/// Get one layer with its episodes, `None` if nothing was ever recorded for it
+pub async fn get_tower_layer(
+    pool: &SqlitePool,
+    user_id: i64,
+    tower_type: i32,
+    tower_id: i32,
+    layer_id: i32,
+) -> Result<Option<sonettobuf::LayerNo>> {
+    let layer: Option<(i32, i32)> = sqlx::query_as(
+        "SELECT curr_high_score, history_high_score
+         FROM user_tower_layers
+         WHERE user_id = ? AND tower_type = ? AND tower_id = ? AND layer_id = ?",
+    )
+    .bind(user_id)
+    .bind(tower_type)
+    .bind(tower_id)
+    .bind(layer_id)
+    .fetch_optional(pool)
+    .await?;
+
+    let Some((curr_high_score, history_high_score)) = layer else {
+        return Ok(None);
+    };
+
+    let episode_nos = get_layer_episodes(pool, user_id, tower_type, tower_id, layer_id).await?;
+
+    Ok(Some(sonettobuf::LayerNo {
+        layer_id: Some(layer_id),
+        curr_high_score: Some(curr_high_score),
+        history_high_score: Some(history_high_score),
+        episode_n_os: episode_nos,
+    }))
+}
+
 async fn get_layer_episodes(
     pool: &SqlitePool,
     user_id: i64,
@@ -254,7 +340,7 @@ async fn get_assist_bosses(pool: &SqlitePool, user_id: i64) -> Result<Vec<Assist
     Ok(assist_bosses)
 }
 
-/// Update tower layer score after battle
+/// Update tower layer score after battle, both scores keep the best one
 pub async fn update_tower_layer_score(
     pool: &SqlitePool,
     user_id: i64,
@@ -263,26 +349,27 @@ pub async fn update_tower_layer_score(
     layer_id: i32,
     score: i32,
 ) -> sqlx::Result<()> {
-    // Update current and history high scores
     sqlx::query(
-        "UPDATE user_tower_layers
-         SET curr_high_score = ?,
-             history_high_score = MAX(history_high_score, ?)
-         WHERE user_id = ? AND tower_type = ? AND tower_id = ? AND layer_id = ?",
+        "INSERT INTO user_tower_layers
+            (user_id, tower_type, tower_id, layer_id, curr_high_score, history_high_score)
+         VALUES (?, ?, ?, ?, ?, ?)
+         ON CONFLICT(user_id, tower_type, tower_id, layer_id) DO UPDATE SET
+             curr_high_score = MAX(curr_high_score, excluded.curr_high_score),
+             history_high_score = MAX(history_high_score, excluded.history_high_score)",
     )
-    .bind(score)
-    .bind(score)
     .bind(user_id)
     .bind(tower_type)
     .bind(tower_id)
     .bind(layer_id)
+    .bind(score)
+    .bind(score)
     .execute(pool)
     .await?;
 
     Ok(())
 }
 
-/// Update tower episode status
+/// Update tower episode status, adding the layer and episode if they're new
 pub async fn update_tower_episode_status(
     pool: &SqlitePool,
     user_id: i64,
@@ -293,16 +380,28 @@ pub async fn update_tower_episode_status(
     status: i32,
 ) -> sqlx::Result<()> {
     sqlx::query(
-        "UPDATE user_tower_episodes
-         SET status = ?
-         WHERE user_id = ? AND tower_type = ? AND tower_id = ? AND layer_id = ? AND episode_id = ?",
+        "INSERT OR IGNORE INTO user_tower_layers (user_id, tower_type, tower_id, layer_id)
+         VALUES (?, ?, ?, ?)",
+    )
+    .bind(user_id)
+    .bind(tower_type)
+    .bind(tower_id)
+    .bind(layer_id)
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "INSERT INTO user_tower_episodes (user_id, tower_type, tower_id, layer_id, episode_id, status)
+         VALUES (?, ?, ?, ?, ?, ?)
+         ON CONFLICT(user_id, tower_type, tower_id, layer_id, episode_id) DO UPDATE SET
+             status = excluded.status",
     )
-    .bind(status)
     .bind(user_id)
     .bind(tower_type)
     .bind(tower_id)
     .bind(layer_id)
     .bind(episode_id)
+    .bind(status)
     .execute(pool)
     .await?;
 
@@ -334,3 +433,205 @@ pub async fn update_tower_pass_layer(
 
     Ok(())
 }
+
+/// Get tower open status
+pub async fn get_tower_opens(pool: &SqlitePool, user_id: i64) -> Result<Vec<TowerOpen>> {
+    let tower_opens = sqlx::query_as::<_, TowerOpen>(
+        "SELECT tower_type, tower_id, status, round, next_time, tower_start_time, task_end_time
+         FROM user_tower_opens WHERE user_id = ? ORDER BY tower_type, tower_id",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(tower_opens)
+}
+
+/// Save a tower's open status
+pub async fn update_tower_open(pool: &SqlitePool, user_id: i64, open: &TowerOpen) -> Result<()> {
+    sqlx::query(
+        "UPDATE user_tower_opens
+         SET status = ?, round = ?, next_time = ?, tower_start_time = ?, task_end_time = ?
+         WHERE user_id = ? AND tower_type = ? AND tower_id = ?",
+    )
+    .bind(open.status)
+    .bind(open.round)
+    .bind(open.next_time)
+    .bind(open.tower_start_time)
+    .bind(open.task_end_time)
+    .bind(user_id)
+    .bind(open.tower_type)
+    .bind(open.tower_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Add the tower row if the player has never entered it
+pub async fn ensure_tower(
+    pool: &SqlitePool,
+    user_id: i64,
+    tower_type: i32,
+    tower_id: i32,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT OR IGNORE INTO user_towers (user_id, tower_type, tower_id) VALUES (?, ?, ?)",
+    )
+    .bind(user_id)
+    .bind(tower_type)
+    .bind(tower_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Replace the heroes that cleared an episode
+pub async fn set_tower_episode_heroes(
+    pool: &SqlitePool,
+    user_id: i64,
+    tower_type: i32,
+    tower_id: i32,
+    layer_id: i32,
+    episode_id: i32,
+    heroes: &[HeroInfo],
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+
+    for table in [
+        "user_tower_episode_heroes",
+        "user_tower_episode_hero_equips",
+    ] {
+        sqlx::query(&format!(
+            "DELETE FROM {}
+             WHERE user_id = ? AND tower_type = ? AND tower_id = ? AND layer_id = ? AND episode_id = ?",
+            table
+        ))
+        .bind(user_id)
+        .bind(tower_type)
+        .bind(tower_id)
+        .bind(layer_id)
+        .bind(episode_id)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    for hero in heroes {
+        sqlx::query(
+            "INSERT INTO user_tower_episode_heroes
+                (user_id, tower_type, tower_id, layer_id, episode_id, hero_id, trial_id)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(user_id)
+        .bind(tower_type)
+        .bind(tower_id)
+        .bind(layer_id)
+        .bind(episode_id)
+        .bind(hero.hero_id)
+        .bind(hero.trial_id)
+        .execute(&mut *tx)
+        .await?;
+
+        for equip_uid in &hero.equip_uids {
+            sqlx::query(
+                "INSERT OR IGNORE INTO user_tower_episode_hero_equips
+                    (user_id, tower_type, tower_id, layer_id, episode_id, hero_id, equip_uid)
+                 VALUES (?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(user_id)
+            .bind(tower_type)
+            .bind(tower_id)
+            .bind(layer_id)
+            .bind(episode_id)
+            .bind(hero.hero_id)
+            .bind(equip_uid)
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Raise the tower's high score to the sum of its layers' current scores, returns the high score
+pub async fn update_tower_total_score(
+    pool: &SqlitePool,
+    user_id: i64,
+    tower_type: i32,
+    tower_id: i32,
+) -> Result<i32> {
+    let total: i64 = sqlx::query_scalar(
+        "SELECT COALESCE(SUM(curr_high_score), 0) FROM user_tower_layers
+         WHERE user_id = ? AND tower_type = ? AND tower_id = ?",
+    )
+    .bind(user_id)
+    .bind(tower_type)
+    .bind(tower_id)
+    .fetch_one(pool)
+    .await?;
+
+    let high_score: i32 = sqlx::query_scalar(
+        "UPDATE user_towers
+         SET history_high_score = MAX(history_high_score, ?)
+         WHERE user_id = ? AND tower_type = ? AND tower_id = ?
+         RETURNING history_high_score",
+    )
+    .bind(total as i32)
+    .bind(user_id)
+    .bind(tower_type)
+    .bind(tower_id)
+    .fetch_optional(pool)
+    .await?
+    .unwrap_or(0);
+
+    Ok(high_score)
+}
+
+/// Clear a tower's season progress: current layer scores, episode clears and the heroes
+/// locked into them. Pass layer and history scores stay.
+pub async fn reset_tower_season(
+    pool: &SqlitePool,
+    user_id: i64,
+    tower_type: i32,
+    tower_id: i32,
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+
+    for query in [
+        "UPDATE user_tower_layers SET curr_high_score = 0
+         WHERE user_id = ? AND tower_type = ? AND tower_id = ?",
+        "UPDATE user_tower_episodes SET status = 0
+         WHERE user_id = ? AND tower_type = ? AND tower_id = ?",
+        "DELETE FROM user_tower_episode_heroes
+         WHERE user_id = ? AND tower_type = ? AND tower_id = ?",
+        "DELETE FROM user_tower_episode_hero_equips
+         WHERE user_id = ? AND tower_type = ? AND tower_id = ?",
+    ] {
+        sqlx::query(query)
+            .bind(user_id)
+            .bind(tower_type)
+            .bind(tower_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Count mop-ups done today, returns the new total
+pub async fn add_tower_mop_up_times(pool: &SqlitePool, user_id: i64, times: i32) -> Result<i32> {
+    let mop_up_times: i32 = sqlx::query_scalar(
+        "INSERT INTO user_tower_info (user_id, mop_up_times) VALUES (?, ?)
+         ON CONFLICT(user_id) DO UPDATE SET mop_up_times = mop_up_times + excluded.mop_up_times
+         RETURNING mop_up_times",
+    )
+    .bind(user_id)
+    .bind(times)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(mop_up_times)
+}
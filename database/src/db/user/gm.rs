@@ -0,0 +1,64 @@
+use crate::models::user::gm::{GmAuditEntry, GmRole};
+use anyhow::Result;
+use common::time::ServerTime;
+use sqlx::SqlitePool;
+
+pub async fn get_gm_role(pool: &SqlitePool, user_id: i64) -> Result<GmRole> {
+    let level: Option<i32> = sqlx::query_scalar("SELECT gm_role FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(GmRole::from_level(level.unwrap_or(0)))
+}
+
+/// Returns false if the user does not exist
+pub async fn set_gm_role(pool: &SqlitePool, user_id: i64, role: GmRole) -> Result<bool> {
+    let rows = sqlx::query("UPDATE users SET gm_role = ? WHERE id = ?")
+        .bind(role as i32)
+        .bind(user_id)
+        .execute(pool)
+        .await?
+        .rows_affected();
+
+    Ok(rows > 0)
+}
+
+pub async fn insert_audit_entry(
+    pool: &SqlitePool,
+    source: &str,
+    invoker_id: Option<i64>,
+    target_user_id: Option<i64>,
+    command: &str,
+    success: bool,
+    result: &str,
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO gm_audit_log
+            (source, invoker_id, target_user_id, command, success, result, created_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(source)
+    .bind(invoker_id)
+    .bind(target_user_id)
+    .bind(command)
+    .bind(success)
+    .bind(result)
+    .bind(ServerTime::now_ms())
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn get_recent_audit_entries(pool: &SqlitePool, limit: i64) -> Result<Vec<GmAuditEntry>> {
+    let entries =
+        sqlx::query_as::<_, GmAuditEntry>("SELECT * FROM gm_audit_log ORDER BY id DESC LIMIT ?")
+            .bind(limit)
+            .fetch_all(pool)
+            .await?;
+
+    Ok(entries)
+}
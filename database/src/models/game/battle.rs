@@ -0,0 +1,68 @@
+use serde::{Deserialize, Serialize};
+use sonettobuf;
+
+/// HP of one entity when the fight ended
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EntityOutcome {
+    pub uid: i64,
+    pub hp: i32,
+    pub dead: bool,
+}
+
+/// What the replay verifier compares
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct BattleOutcome {
+    pub rounds: i32,
+    pub entities: Vec<EntityOutcome>,
+}
+
+/// A finished fight with everything needed to rebuild it
+#[derive(Debug, Clone)]
+pub struct BattleResult {
+    pub user_id: i64,
+    pub battle_id: i64,
+    pub episode_id: i32,
+    pub chapter_id: i32,
+    pub fight_group: sonettobuf::FightGroup,
+    pub outcome: BattleOutcome,
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordedBattle {
+    pub seed: u64,
+    /// JSON of the state the fight started from
+    pub start_state: String,
+    pub result: BattleResult,
+}
+
+/// An unfinished fight as it stood at its last round boundary
+#[derive(Debug, Clone, Default)]
+pub struct SavedBattle {
+    pub user_id: i64,
+    pub battle_id: i64,
+    pub episode_id: i32,
+    pub chapter_id: i32,
+    pub tower_type: Option<i32>,
+    pub tower_id: Option<i32>,
+    pub layer_id: Option<i32>,
+    pub difficulty: Option<i32>,
+    pub talent_plan_id: Option<i32>,
+    /// Next round to play
+    pub current_round: i32,
+    pub act_point: i32,
+    pub power: i32,
+    pub multiplication: Option<i32>,
+    pub seed: u64,
+    pub fight: sonettobuf::Fight,
+    pub last_round: Option<sonettobuf::FightRound>,
+    pub fight_group: sonettobuf::FightGroup,
+    pub current_deck: Vec<sonettobuf::CardInfo>,
+    pub ai_deck: Vec<sonettobuf::CardInfo>,
+    /// Cloth skills used in `current_round` so far
+    pub cloth_opers: Vec<sonettobuf::UseClothSkillOperRecord>,
+    /// JSON of the fight state managers, none for fights saved before it was kept
+    pub fight_data: Option<String>,
+    pub is_replay: bool,
+    /// Recorded fight a replay plays back
+    pub replay_battle_id: Option<i64>,
+}
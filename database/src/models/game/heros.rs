@@ -19,8 +19,10 @@ pub trait HeroModel<T> {
     async fn use_touch(&self) -> Result<Option<i32>>;
     async fn skin(&self, hero_id: i32, skin_id: i32) -> Result<()>;
     async fn skins(&self) -> Result<Vec<i32>>;
+    async fn add_skin(&self, hero_id: i32, skin_id: i32) -> Result<bool>;
     async fn birthdays(&self) -> Result<Vec<(i32, i32)>>;
     async fn destiny_stone(&self, hero_id: i32, stone_id: i32) -> Result<()>;
+    async fn set_destiny(&self, hero_id: i32, rank: i32, level: i32) -> Result<()>;
     async fn level_up(&self, hero_id: i32, new_level: i32, stats: &CharacterLevel) -> Result<()>;
     async fn rank_up(&self, hero_id: i32, new_rank: i32) -> Result<()>;
     async fn unlock_insight_skin(&self, hero_id: i32, target_rank: i32) -> Result<bool>;
@@ -411,6 +413,10 @@ impl UserHeroModel {
         HeroModel::<HeroData>::skins(self).await
     }
 
+    pub async fn add_skin(&self, hero_id: i32, skin_id: i32) -> Result<bool> {
+        HeroModel::<HeroData>::add_skin(self, hero_id, skin_id).await
+    }
+
     pub async fn get_birthdays(&self) -> Result<Vec<(i32, i32)>> {
         HeroModel::<HeroData>::birthdays(self).await
     }
@@ -418,6 +424,10 @@ impl UserHeroModel {
     pub async fn update_destiny_stone(&self, hero_id: i32, stone_id: i32) -> Result<()> {
         HeroModel::<HeroData>::destiny_stone(self, hero_id, stone_id).await
     }
+
+    pub async fn update_destiny(&self, hero_id: i32, rank: i32, level: i32) -> Result<()> {
+        HeroModel::<HeroData>::set_destiny(self, hero_id, rank, level).await
+    }
 }
 
 impl HeroModel<HeroData> for UserHeroModel {
@@ -1299,6 +1309,30 @@ impl HeroModel<HeroData> for UserHeroModel {
         Ok(skins)
     }
 
+    async fn add_skin(&self, hero_id: i32, skin_id: i32) -> Result<bool> {
+        let hero_data = self.get(hero_id).await?;
+
+        let inserted =
+            sqlx::query("INSERT OR IGNORE INTO hero_all_skins (user_id, skin_id) VALUES (?, ?)")
+                .bind(self.user_id)
+                .bind(skin_id)
+                .execute(&self.pool)
+                .await?
+                .rows_affected();
+
+        if inserted == 0 {
+            return Ok(false);
+        }
+
+        sqlx::query("INSERT INTO hero_skins (hero_uid, skin, expire_sec) VALUES (?, ?, 0)")
+            .bind(hero_data.record.uid)
+            .bind(skin_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(true)
+    }
+
     async fn birthdays(&self) -> Result<Vec<(i32, i32)>> {
         let info: Vec<(i32, i32)> = sqlx::query_as(
             "SELECT hero_id, birthday_count FROM hero_birthday_info WHERE user_id = ?1",
@@ -1321,6 +1355,56 @@ impl HeroModel<HeroData> for UserHeroModel {
         Ok(())
     }
 
+    async fn set_destiny(&self, hero_id: i32, rank: i32, level: i32) -> Result<()> {
+        let hero_data = self.get(hero_id).await?;
+
+        sqlx::query(
+            "UPDATE heroes SET destiny_rank = ?, destiny_level = ? WHERE uid = ? AND user_id = ?",
+        )
+        .bind(rank)
+        .bind(level)
+        .bind(hero_data.record.uid)
+        .bind(self.user_id)
+        .execute(&self.pool)
+        .await?;
+
+        if rank == 0 {
+            return Ok(());
+        }
+
+        // Reaching a rank unlocks every facet of the hero's destiny
+        let game_data = config::configs::get();
+        let stones: Vec<i32> = game_data
+            .character_destiny
+            .iter()
+            .filter(|d| d.hero_id == hero_id)
+            .flat_map(|d| d.facets_id.split('#'))
+            .filter_map(|s| s.parse::<i32>().ok())
+            .collect();
+
+        for stone_id in &stones {
+            sqlx::query(
+                "INSERT OR IGNORE INTO hero_destiny_stone_unlocks (hero_uid, stone_id) VALUES (?, ?)",
+            )
+            .bind(hero_data.record.uid)
+            .bind(stone_id)
+            .execute(&self.pool)
+            .await?;
+        }
+
+        if hero_data.record.destiny_stone == 0
+            && let Some(stone_id) = stones.first()
+        {
+            sqlx::query("UPDATE heroes SET destiny_stone = ? WHERE uid = ?")
+                .bind(stone_id)
+                .bind(hero_data.record.uid)
+                .execute(&self.pool)
+                .await?;
+        }
+
+        Ok(())
+    }
+
     async fn level_up(&self, hero_id: i32, new_level: i32, stats: &CharacterLevel) -> Result<()> {
         let hero_data = self.get(hero_id).await?;
 
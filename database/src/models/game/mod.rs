@@ -1,5 +1,6 @@
 pub mod achievements;
 pub mod antiques;
+pub mod battle;
 
 pub mod bgm;
 pub mod block_packages;
@@ -0,0 +1,49 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// Permission level of an account for GM commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum GmRole {
+    Player = 0,
+    Tester = 1,
+    Admin = 2,
+}
+
+impl GmRole {
+    pub fn from_level(level: i32) -> Self {
+        match level {
+            2.. => GmRole::Admin,
+            1 => GmRole::Tester,
+            _ => GmRole::Player,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "player" | "0" => Some(GmRole::Player),
+            "tester" | "1" => Some(GmRole::Tester),
+            "admin" | "2" => Some(GmRole::Admin),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GmRole::Player => "player",
+            GmRole::Tester => "tester",
+            GmRole::Admin => "admin",
+        }
+    }
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct GmAuditEntry {
+    pub id: i64,
+    pub source: String,
+    pub invoker_id: Option<i64>,
+    pub target_user_id: Option<i64>,
+    pub command: String,
+    pub success: bool,
+    pub result: String,
+    pub created_at: i64,
+}
@@ -0,0 +1,171 @@
+//! Offline tools run through the gameserver binary instead of starting the server
+//!
+//! `gameserver verify-battles [--user <id>] [--episode <id>]` re-simulates recorded
+//! fights and exits with an error when any of them ended differently.
+//!
+//! `gameserver simulate-battle --episode <id> (--user <id> [--heroes <uid,..>] | --team <spec.json>)
+//! [--script <opers.json>] [--seed <n>] [--out <file>]` fights the episode without a client
+//! and writes the timeline as JSON. `--user` fields the player's current group unless
+//! `--heroes` picks the uids, `--team` seeds a [`TeamSpec`] into a scratch database. The
+//! script is a list of `BeginRoundOper` lists, one per round, the auto policy plays otherwise.
+
+use crate::state::{Policy, TeamSpec, new_battle_seed, seed_team, simulate, verify_battles};
+use anyhow::{Context, Result, bail};
+use database::db::game::hero_groups::get_current_hero_group;
+use database::{DatabaseSettings, connect_to, run_migrations};
+use sonettobuf::FightGroup;
+use sqlx::SqlitePool;
+
+/// Account the `--team` heroes belong to in the scratch database
+const SIMULATOR_USER_ID: i64 = 1;
+
+/// Runs the tool named by the first argument, returns false when there is none
+pub async fn run(pool: &SqlitePool, args: &[String]) -> Result<bool> {
+    let Some(command) = args.first() else {
+        return Ok(false);
+    };
+
+    match command.as_str() {
+        "verify-battles" => verify(pool, &args[1..]).await?,
+        "simulate-battle" => simulate_battle(pool, &args[1..]).await?,
+        other => bail!("Unknown command: {}", other),
+    }
+
+    Ok(true)
+}
+
+fn flag_value<T: std::str::FromStr>(args: &[String], flag: &str) -> Result<Option<T>> {
+    let Some(pos) = args.iter().position(|a| a == flag) else {
+        return Ok(None);
+    };
+
+    let value = args
+        .get(pos + 1)
+        .with_context(|| format!("Missing value for {}", flag))?;
+
+    value
+        .parse()
+        .map(Some)
+        .map_err(|_| anyhow::anyhow!("Invalid value for {}: {}", flag, value))
+}
+
+async fn verify(pool: &SqlitePool, args: &[String]) -> Result<()> {
+    let user_id = flag_value::<i64>(args, "--user")?;
+    let episode_id = flag_value::<i32>(args, "--episode")?;
+
+    let report = verify_battles(pool, user_id, episode_id).await?;
+
+    for (user_id, battle_id, diffs) in &report.failures {
+        println!("user {} battle {}:", user_id, battle_id);
+        for diff in diffs {
+            println!("  {}", diff);
+        }
+    }
+
+    println!(
+        "{} battles checked, {} diverged",
+        report.checked,
+        report.failures.len()
+    );
+
+    if !report.failures.is_empty() {
+        bail!("{} battles diverged", report.failures.len());
+    }
+
+    Ok(())
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &str) -> Result<T> {
+    let data = std::fs::read_to_string(path).with_context(|| format!("Couldn't read {}", path))?;
+    serde_json::from_str(&data).with_context(|| format!("Invalid JSON in {}", path))
+}
+
+async fn user_group(pool: &SqlitePool, user_id: i64, heroes: Option<String>) -> Result<FightGroup> {
+    if let Some(heroes) = heroes {
+        let hero_list = heroes
+            .split(',')
+            .map(|uid| {
+                uid.trim()
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("Invalid hero uid: {}", uid))
+            })
+            .collect::<Result<_>>()?;
+
+        return Ok(FightGroup {
+            hero_list,
+            ..Default::default()
+        });
+    }
+
+    let group = get_current_hero_group(pool, user_id)
+        .await?
+        .with_context(|| format!("User {} has no hero group, pass --heroes", user_id))?;
+
+    Ok(FightGroup {
+        hero_list: group.hero_list,
+        cloth_id: Some(group.cloth_id),
+        ..Default::default()
+    })
+}
+
+async fn simulate_battle(pool: &SqlitePool, args: &[String]) -> Result<()> {
+    let episode_id = flag_value::<i32>(args, "--episode")?.context("Missing --episode")?;
+    let user_id = flag_value::<i64>(args, "--user")?;
+    let team = flag_value::<String>(args, "--team")?;
+    let seed = flag_value::<u64>(args, "--seed")?.unwrap_or_else(new_battle_seed);
+
+    let policy = match flag_value::<String>(args, "--script")? {
+        Some(path) => Policy::Script(read_json(&path)?),
+        None => Policy::Auto,
+    };
+
+    let report = match (user_id, team) {
+        (Some(user_id), None) => {
+            let group = user_group(pool, user_id, flag_value(args, "--heroes")?).await?;
+            simulate(pool, user_id, &group, episode_id, seed, policy).await?
+        }
+        (None, Some(path)) => {
+            let spec: TeamSpec = read_json(&path)?;
+
+            let db_path = std::env::temp_dir().join(format!("sonetto-sim-{}.db", seed));
+            let settings = DatabaseSettings {
+                db_name: db_path.to_string_lossy().to_string(),
+            };
+            let scratch = connect_to(&settings).await?;
+            run_migrations(&scratch).await?;
+
+            let report = async {
+                let group = seed_team(&scratch, SIMULATOR_USER_ID, &spec).await?;
+                simulate(
+                    &scratch,
+                    SIMULATOR_USER_ID,
+                    &group,
+                    episode_id,
+                    seed,
+                    policy,
+                )
+                .await
+            }
+            .await;
+
+            scratch.close().await;
+            let _ = std::fs::remove_file(&db_path);
+            report?
+        }
+        _ => bail!("Pass either --user or --team"),
+    };
+
+    let json = serde_json::to_string_pretty(&report)?;
+    match flag_value::<String>(args, "--out")? {
+        Some(path) => {
+            std::fs::write(&path, json).with_context(|| format!("Couldn't write {}", path))?;
+            println!(
+                "episode {}: result {} after {} rounds, timeline written to {}",
+                report.episode_id, report.result, report.rounds, path
+            );
+        }
+        None => println!("{}", json),
+    }
+
+    Ok(())
+}
@@ -49,6 +49,9 @@ pub enum AppError {
 
     #[error("Banner has expired")]
     BannerExpired,
+
+    #[error("{0}")]
+    Gm(#[from] GmError),
 }
 
 impl From<std::str::Utf8Error> for AppError {
@@ -100,3 +103,28 @@ pub enum CmdError {
     #[error("Received server packet as client request")]
     ServerPacketReceivedAsClient,
 }
+
+/// A GM command that was refused before or while running
+#[derive(Debug, Error)]
+pub enum GmError {
+    #[error("Invalid command")]
+    InvalidCommand,
+
+    #[error("Unknown command: {0}\nType /help for commands")]
+    UnknownCommand(String),
+
+    #[error("Permission denied: {command} requires the {role} role")]
+    PermissionDenied {
+        command: &'static str,
+        role: &'static str,
+    },
+
+    #[error("{0} needs a target player")]
+    MissingTarget(&'static str),
+
+    #[error("{0}")]
+    InvalidArgs(String),
+
+    #[error("{0}")]
+    Rejected(String),
+}
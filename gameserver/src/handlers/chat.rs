@@ -234,6 +234,7 @@ pub async fn handle_command(ctx: Arc<Mutex<ConnectionContext>>, input: &str) ->
 
     match crate::handlers::gm::execute_command(ctx, input).await {
         Ok(response) => Some(response),
+        Err(AppError::Gm(e)) => Some(e.to_string()),
         Err(e) => Some(format!("Error: {:?}", e)),
     }
 }
@@ -12,7 +12,7 @@ pub async fn on_get_server_time(
 ) -> Result<(), AppError> {
     let data = GetServerTimeReply {
         server_time: Some(ServerTime::now_ms() as u64),
-        offset_time: Some(-18000000),
+        offset_time: Some(ServerTime::reset_shift_ms()),
     };
 
     {
@@ -1,17 +1,24 @@
 use crate::error::AppError;
+use crate::handlers::tower::{TowerFight, settle_tower_battle};
 use crate::network::packet::ClientPacket;
-use crate::util::push::{send_dungeon_update_push, send_end_dungeon_push, send_red_dot_push};
+use crate::util::push::{
+    send_dungeon_update_push, send_end_dungeon_push, send_fight_wave_push, send_red_dot_push,
+};
 
 use crate::send_push;
 use crate::state::{
-    BattleSimulator, ConnectionContext, generate_dungeon_rewards, send_end_fight_push,
+    BattleSimulator, ConnectionContext, battle_outcome, generate_dungeon_rewards, persist_battle,
+    send_end_fight_push,
 };
 use database::db::game::dungeons::{
     get_user_dungeon, should_update_dungeon_record, update_dungeon_progress,
 };
 use database::db::game::{
-    battle::save_round_operations, dungeons::save_dungeon_record, equipment::build_equip_records,
+    battle::{save_battle_result, save_round_operations},
+    dungeons::save_dungeon_record,
+    equipment::build_equip_records,
 };
+use database::models::game::battle::BattleResult;
 use prost::Message;
 use sonettobuf::{BeginRoundReply, BeginRoundRequest, CmdId, InstructionDungeonInfoPush};
 use std::sync::Arc;
@@ -40,6 +47,9 @@ pub async fn on_begin_round(
         multiplication,
         ai_deck,
         fight_data_mgr,
+        seed,
+        tower,
+        cloth_opers,
     ) = {
         let conn = ctx.lock().await;
         let battle = conn
@@ -58,6 +68,9 @@ pub async fn on_begin_round(
             battle.multiplication.unwrap_or(1),
             battle.ai_deck.clone(),
             battle.fight_data_mgr.clone().unwrap_or_default(),
+            battle.seed,
+            TowerFight::of(battle),
+            battle.cloth_opers.clone(),
         )
     };
 
@@ -69,13 +82,16 @@ pub async fn on_begin_round(
         )
     };
 
-    let mut simulator = BattleSimulator::new(fight_data_mgr);
-    let mut round = simulator
-        .process_round(request.opers.clone(), current_deck, ai_deck)
+    let mut simulator = BattleSimulator::new(fight_data_mgr, seed);
+    let wave_before = simulator.current_wave();
+    let round = simulator
+        .process_round(round_num, request.opers.clone(), current_deck, ai_deck)
         .await?;
 
-    // Auto-complete battle for now
-    round.is_finish = Some(true);
+    if simulator.current_wave() != wave_before {
+        send_fight_wave_push(ctx.clone(), simulator.fight_snapshot().as_ref().clone()).await?;
+    }
+
     let record_round = round.cur_round.unwrap_or(1);
 
     tracing::info!(
@@ -104,11 +120,52 @@ pub async fn on_begin_round(
             episode_id,
             battle_id,
             round_num,
-            vec![], // TODO: Extract cloth_skill_opers from request
+            cloth_opers,
             request.opers,
         )
         .await?;
 
+        save_battle_result(
+            &pool,
+            &BattleResult {
+                user_id: player_id,
+                battle_id,
+                episode_id,
+                chapter_id,
+                fight_group: fight_group.clone().unwrap_or_default(),
+                outcome: battle_outcome(&simulator.fight_snapshot(), record_round),
+            },
+        )
+        .await?;
+    }
+
+    let is_finish = round.is_finish.unwrap_or(false);
+    let fight = simulator.fight_snapshot();
+    let fight_result = simulator.fight_result();
+
+    {
+        let mut conn = ctx.lock().await;
+        if let Some(battle) = conn.active_battle.as_mut() {
+            battle.current_round = round_num + 1;
+            battle.current_deck = round.team_a_cards1.clone();
+            battle.ai_deck = round.ai_use_cards.clone();
+            battle.cloth_opers.clear();
+            battle.fight = Some(fight.as_ref().clone());
+            battle.last_round = Some(round);
+            battle.fight_data_mgr = Some(simulator.into_data());
+
+            persist_battle(&pool, player_id, battle).await?;
+        }
+    }
+
+    if !is_finish {
+        return Ok(());
+    }
+
+    // a loss or the round limit ends the fight too, only a win counts as a clear
+    let won = fight_result == 1;
+
+    if !is_replay && won {
         // Update player's dungeon progress
         let stars_earned = 2; // TODO: Calculate based on performance
         update_dungeon_progress(&pool, player_id, chapter_id, episode_id, stars_earned).await?;
@@ -136,7 +193,7 @@ pub async fn on_begin_round(
             record_round,
             should_save_record
         );
-    } else {
+    } else if is_replay {
         tracing::info!(
             "Replay completed: episode={}, round={}",
             episode_id,
@@ -147,7 +204,7 @@ pub async fn on_begin_round(
     send_end_fight_push(
         ctx.clone(),
         battle_id,
-        1, // Win
+        fight_result,
         fight_group.clone().unwrap_or_default(),
         vec![],     // TODO: Actual battle stats
         vec![],     // No defender stats
@@ -155,12 +212,20 @@ pub async fn on_begin_round(
     )
     .await?;
 
-    send_push!(
-        ctx,
-        CmdId::DungeonInstructionDungeonInfoPushCmd,
-        InstructionDungeonInfoPush,
-        "dungeon/instruction_dungeon_info.json"
-    );
+    if let Some(tower) = tower
+        && !is_replay
+    {
+        settle_tower_battle(
+            ctx.clone(),
+            &pool,
+            player_id,
+            tower,
+            &fight,
+            fight_result,
+            &fight_group.clone().unwrap_or_default(),
+        )
+        .await?;
+    }
 
     let updated_dungeon = get_user_dungeon(&pool, player_id, chapter_id, episode_id).await?;
 
@@ -185,6 +250,17 @@ pub async fn on_begin_round(
     )
     .await?;
 
+    if !won {
+        return Ok(());
+    }
+
+    send_push!(
+        ctx,
+        CmdId::DungeonInstructionDungeonInfoPushCmd,
+        InstructionDungeonInfoPush,
+        "dungeon/instruction_dungeon_info.json"
+    );
+
     // Generate rewards based on episode data
 
     let is_first_clear = updated_dungeon.challenge_count == 1;
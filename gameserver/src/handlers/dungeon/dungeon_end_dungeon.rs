@@ -1,6 +1,9 @@
 use crate::network::packet::ClientPacket;
 use crate::state::ConnectionContext;
-use crate::{error::AppError, state::send_end_fight_push};
+use crate::{
+    error::AppError,
+    state::{forget_battle, send_end_fight_push},
+};
 use prost::Message;
 use sonettobuf::{CmdId, EndDungeonReply, EndDungeonRequest};
 use std::sync::Arc;
@@ -16,7 +19,7 @@ pub async fn on_dungeon_end_dungeon(
 
     tracing::info!("Dungeon ended with is_abort: {}", is_abort);
 
-    let (fight_group, is_replay, battle_id) = {
+    let (fight_group, is_replay, battle_id, player_id, pool) = {
         let conn = ctx.lock().await;
         let battle = conn
             .active_battle
@@ -27,6 +30,8 @@ pub async fn on_dungeon_end_dungeon(
             battle.fight_group.clone(),
             battle.is_replay.unwrap_or(false),
             battle.fight_id.unwrap_or_default(),
+            conn.player_id.ok_or(AppError::NotLoggedIn)?,
+            conn.state.db.clone(),
         )
     };
 
@@ -43,6 +48,8 @@ pub async fn on_dungeon_end_dungeon(
         .await?;
     }
 
+    forget_battle(&pool, player_id).await?;
+
     {
         let mut conn = ctx.lock().await;
         conn.active_battle = None;
@@ -13,7 +13,7 @@ pub async fn on_get_fight_oper(
 ) -> Result<(), AppError> {
     let _ = GetFightOperRequest::decode(&req.data[..])?;
 
-    let (player_id, pool, episode_id, is_replay) = {
+    let (player_id, pool, replay_battle_id) = {
         let conn = ctx.lock().await;
         let battle = conn
             .active_battle
@@ -23,15 +23,13 @@ pub async fn on_get_fight_oper(
         (
             conn.player_id.ok_or(AppError::NotLoggedIn)?,
             conn.state.db.clone(),
-            battle.replay_episode_id.unwrap_or_default(),
-            battle.is_replay.unwrap_or(false),
+            battle.replay_battle_id,
         )
     };
 
-    let oper_records = if is_replay {
-        load_battle_replay(&pool, player_id, episode_id).await?
-    } else {
-        vec![]
+    let oper_records = match replay_battle_id {
+        Some(recorded_id) => load_battle_replay(&pool, player_id, recorded_id).await?,
+        None => vec![],
     };
 
     let reply = GetFightOperReply { oper_records };
@@ -1,10 +1,11 @@
 use crate::error::AppError;
 use crate::network::packet::ClientPacket;
 use crate::state::{
-    ActiveBattle, BattleContext, ConnectionContext, create_battle, default_max_ap,
-    generate_initial_deck,
+    ActiveBattle, BattleContext, BattleStart, ConnectionContext, create_battle, default_max_ap,
+    generate_initial_deck, new_battle_seed, persist_battle, record_battle_start,
 };
 use config::configs;
+use database::db::game::battle::{latest_recorded_battle, load_battle_seed, save_battle_seed};
 use database::db::game::dungeons::{get_user_dungeon, update_dungeon_progress};
 use prost::Message;
 use sonettobuf::{CmdId, DungeonUpdatePush, StartDungeonReply, StartDungeonRequest, UserDungeon};
@@ -50,46 +51,84 @@ pub async fn on_start_dungeon(
     let battle_id = episode_cfg.battle_id;
     let max_ap = default_max_ap(episode_id, hero_count);
 
+    // A record replays the latest recorded fight with the seed that fight was played with
+    let replay_battle_id = if use_record {
+        latest_recorded_battle(&pool, player_id, episode_id).await?
+    } else {
+        None
+    };
+    let seed = match replay_battle_id {
+        Some(recorded_id) => load_battle_seed(&pool, player_id, recorded_id)
+            .await?
+            .unwrap_or_else(new_battle_seed),
+        None => new_battle_seed(),
+    };
+
     let battle_ctx = BattleContext {
         player_id,
         chapter_id,
         episode_id,
         battle_id,
         max_ap,
+        seed,
     };
 
-    let card_push = generate_initial_deck(&pool, player_id, &fight_group, max_ap).await?;
+    let card_push = generate_initial_deck(&pool, player_id, &fight_group, max_ap, seed).await?;
 
     let card_deck = card_push.card_group.clone();
 
     let (modified_fight, initial_round, fight_data_mgr, ai_deck) =
         create_battle(&pool, battle_ctx, &fight_group, card_deck.clone()).await?;
 
-    {
-        let mut conn = ctx.lock().await;
-        conn.active_battle = Some(ActiveBattle {
-            tower_type: None,
-            tower_id: None,
-            layer_id: None,
+    let fight_id = chrono::Utc::now().timestamp_millis();
+    if !use_record {
+        save_battle_seed(&pool, player_id, episode_id, fight_id, seed).await?;
+
+        let start = BattleStart {
+            fight_data: fight_data_mgr.clone(),
+            deck: card_deck.clone(),
+            ai_deck: ai_deck.clone(),
+        };
+        record_battle_start(
+            &pool,
+            player_id,
+            fight_id,
             episode_id,
             chapter_id,
-            difficulty: None,
-            talent_plan_id: None,
-            fight: Some(modified_fight.clone()),
-            current_round: 1,
-            act_point: max_ap,
-            power: 15,
-            current_deck: card_deck,
-            fight_group: Some(fight_group.clone()),
-            is_replay: Some(use_record),
-            replay_episode_id: Some(episode_id),
-            fight_id: Some(chrono::Utc::now().timestamp_millis()),
-            multiplication: Some(multiplication),
-            ai_deck,
-            fight_data_mgr: Some(fight_data_mgr),
-        });
+            &fight_group,
+            &start,
+        )
+        .await?;
     }
 
+    let battle = ActiveBattle {
+        tower_type: None,
+        tower_id: None,
+        layer_id: None,
+        episode_id,
+        chapter_id,
+        difficulty: None,
+        talent_plan_id: None,
+        fight: Some(modified_fight.clone()),
+        last_round: Some(initial_round.clone()),
+        current_round: 1,
+        act_point: max_ap,
+        power: 15,
+        current_deck: card_deck,
+        fight_group: Some(fight_group.clone()),
+        is_replay: Some(use_record),
+        replay_battle_id,
+        fight_id: Some(fight_id),
+        multiplication: Some(multiplication),
+        ai_deck,
+        cloth_opers: vec![],
+        fight_data_mgr: Some(fight_data_mgr),
+        seed,
+    };
+    persist_battle(&pool, player_id, &battle).await?;
+
+    ctx.lock().await.active_battle = Some(battle);
+
     let updated_dungeon = get_user_dungeon(&pool, player_id, chapter_id, episode_id).await?;
 
     let chapter_type = game_data
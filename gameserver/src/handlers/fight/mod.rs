@@ -1,3 +1,5 @@
 mod reconnect_fight;
+mod use_cloth_skill;
 
 pub use reconnect_fight::on_reconnect_fight;
+pub use use_cloth_skill::on_use_cloth_skill;
@@ -1,16 +1,82 @@
 use crate::error::AppError;
 use crate::network::packet::ClientPacket;
-use crate::state::ConnectionContext;
-use sonettobuf::CmdId;
+use crate::state::{ActiveBattle, ConnectionContext, forget_battle, restore_battle};
+use database::db::game::battle::load_active_battle;
+use sonettobuf::fight_reason::FightType;
+use sonettobuf::{CmdId, FightReason, ReconnectFightReply};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
+/// Tower fights are dungeon episodes too, the protocol has no fight type of their own
+fn fight_type(battle: &ActiveBattle) -> FightType {
+    if battle.is_replay.unwrap_or(false) {
+        FightType::DungeonRecord
+    } else {
+        FightType::Dungeon
+    }
+}
+
 pub async fn on_reconnect_fight(
     ctx: Arc<Mutex<ConnectionContext>>,
     req: ClientPacket,
 ) -> Result<(), AppError> {
+    let (player_id, pool, in_memory) = {
+        let conn = ctx.lock().await;
+        (
+            conn.player_id.ok_or(AppError::NotLoggedIn)?,
+            conn.state.db.clone(),
+            conn.active_battle.is_some(),
+        )
+    };
+
+    // after a relog or a restart the fight only survives in the database
+    if !in_memory && let Some(saved) = load_active_battle(&pool, player_id).await? {
+        let battle_id = saved.battle_id;
+        match restore_battle(&pool, saved).await {
+            Ok(battle) => {
+                tracing::info!(
+                    "Restored fight {} of user {} at round {}",
+                    battle_id,
+                    player_id,
+                    battle.current_round
+                );
+                ctx.lock().await.active_battle = Some(battle);
+            }
+            Err(e) => {
+                tracing::error!(
+                    "Couldn't restore fight {} of user {}, dropping it: {:#}",
+                    battle_id,
+                    player_id,
+                    e
+                );
+                forget_battle(&pool, player_id).await?;
+            }
+        }
+    }
+
     let mut conn = ctx.lock().await;
-    conn.send_empty_reply(CmdId::ReconnectFightCmd, Vec::new(), 0, req.up_tag)
+
+    let Some(battle) = conn.active_battle.as_ref() else {
+        conn.send_empty_reply(CmdId::ReconnectFightCmd, Vec::new(), 0, req.up_tag)
+            .await?;
+        return Ok(());
+    };
+
+    let fight = battle.fight.clone();
+    let reply = ReconnectFightReply {
+        fight_reason: Some(FightReason {
+            r#type: Some(fight_type(battle) as i32),
+            battle_id: fight.as_ref().and_then(|f| f.battle_id),
+            multiplication: battle.multiplication,
+            ..Default::default()
+        }),
+        fight,
+        last_round: battle.last_round.clone(),
+        fight_group: battle.fight_group.clone(),
+    };
+
+    conn.send_reply(CmdId::ReconnectFightCmd, reply, 0, req.up_tag)
         .await?;
+
     Ok(())
 }
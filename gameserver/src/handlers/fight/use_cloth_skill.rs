@@ -0,0 +1,71 @@
+use crate::error::AppError;
+use crate::network::packet::ClientPacket;
+use crate::state::{BattleSimulator, ConnectionContext, persist_battle};
+use prost::Message;
+use sonettobuf::{CmdId, UseClothSkillOperRecord, UseClothSkillReply, UseClothSkillRequest};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+pub async fn on_use_cloth_skill(
+    ctx: Arc<Mutex<ConnectionContext>>,
+    req: ClientPacket,
+) -> Result<(), AppError> {
+    let request = UseClothSkillRequest::decode(&req.data[..])?;
+
+    tracing::info!(
+        "UseClothSkill: skill={}, from={}, to={}",
+        request.skill_id.unwrap_or(0),
+        request.from_id.unwrap_or(0),
+        request.to_id.unwrap_or(0)
+    );
+
+    let oper = UseClothSkillOperRecord {
+        skill_id: request.skill_id,
+        from_id: request.from_id,
+        to_id: request.to_id,
+        r#type: request.r#type,
+    };
+
+    let mut conn = ctx.lock().await;
+    let player_id = conn.player_id.ok_or(AppError::NotLoggedIn)?;
+    let pool = conn.state.db.clone();
+
+    let round = {
+        let battle = conn
+            .active_battle
+            .as_mut()
+            .ok_or(AppError::InvalidRequest)?;
+
+        let mut simulator = BattleSimulator::new(
+            battle.fight_data_mgr.clone().unwrap_or_default(),
+            battle.seed,
+        );
+        let mut hand = battle.current_deck.clone();
+
+        // the recorded opers have to replay it the same way, see `RngStream::ClothSkill`
+        let round = simulator.use_cloth_skill(
+            battle.current_round,
+            battle.cloth_opers.len(),
+            &oper,
+            &mut hand,
+        )?;
+
+        battle.current_deck = hand;
+        battle.fight = Some(simulator.fight_snapshot().as_ref().clone());
+        battle.fight_data_mgr = Some(simulator.into_data());
+        battle.cloth_opers.push(oper);
+
+        persist_battle(&pool, player_id, battle).await?;
+        round
+    };
+
+    conn.send_reply(
+        CmdId::UseClothSkillCmd,
+        UseClothSkillReply { round: Some(round) },
+        0,
+        req.up_tag,
+    )
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,102 @@
+use super::{GmInvocation, GmSource, execute};
+use crate::error::AppError;
+use crate::state::AppState;
+use anyhow::Result;
+use common::{config, constant_time_eq, host};
+use database::models::user::gm::GmRole;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+/// A command of the admin api (`POST /admin/gm`), one JSON line per connection
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiCommand {
+    pub key: String,
+    pub user_id: Option<i64>,
+    pub command: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiReply {
+    pub code: u16,
+    pub msg: String,
+}
+
+/// Takes the admin api commands the sdkserver forwards and runs them with the admin
+/// role on this server, where the sessions of the online players live
+pub async fn run_api(state: Arc<AppState>) {
+    if config().gm.admin_api_key.is_empty() {
+        return;
+    }
+
+    let addr = format!("{}:{}", host(), config().gm.admin_port);
+    let listener = match TcpListener::bind(&addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            tracing::error!("Failed to bind the GM api on {}: {}", addr, e);
+            return;
+        }
+    };
+    tracing::info!("GM api listening on tcp://{}", addr);
+
+    loop {
+        let (socket, client) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                tracing::error!("GM api accept error: {}", e);
+                continue;
+            }
+        };
+
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_api_client(state, socket).await {
+                tracing::warn!("GM api request from {} failed: {}", client, e);
+            }
+        });
+    }
+}
+
+async fn handle_api_client(state: Arc<AppState>, socket: TcpStream) -> Result<()> {
+    let (reader, mut writer) = socket.into_split();
+    let mut line = String::new();
+    BufReader::new(reader).read_line(&mut line).await?;
+
+    let command: ApiCommand = serde_json::from_str(&line)?;
+    let api_key = &config().gm.admin_api_key;
+
+    let reply = if !constant_time_eq(command.key.as_bytes(), api_key.as_bytes()) {
+        tracing::warn!("Rejected forwarded gm command");
+        ApiReply {
+            code: 401,
+            msg: "unauthorized".to_string(),
+        }
+    } else {
+        let invocation = GmInvocation {
+            source: GmSource::Api,
+            invoker_id: None,
+            role: GmRole::Admin,
+            target_user_id: command.user_id,
+            session: None,
+        };
+
+        match execute(state, invocation, &command.command).await {
+            Ok(msg) => ApiReply { code: 200, msg },
+            Err(AppError::Gm(e)) => ApiReply {
+                code: 400,
+                msg: e.to_string(),
+            },
+            Err(e) => ApiReply {
+                code: 500,
+                msg: e.to_string(),
+            },
+        }
+    };
+
+    let mut out = serde_json::to_string(&reply)?;
+    out.push('\n');
+    writer.write_all(out.as_bytes()).await?;
+
+    Ok(())
+}
@@ -0,0 +1,460 @@
+use super::progression::{
+    cmd_chapter, cmd_episode, cmd_euphoria, cmd_guide, cmd_hero_level, cmd_insight, cmd_portray,
+    cmd_resonance, cmd_story, send_hero_update,
+};
+use super::registry::{ArgSpec, CommandContext, GmCommand};
+use super::search::{self, MatchKind, SearchMatch};
+use crate::error::{AppError, GmError};
+use crate::util::inventory::{add_currencies, add_items};
+use crate::util::push;
+use database::db::{game, user};
+use database::models::game::heros::UserHeroModel;
+use database::models::user::gm::GmRole;
+
+pub static COMMANDS: &[GmCommand] = &[
+    GmCommand {
+        name: "/help",
+        args: &[],
+        role: GmRole::Player,
+        needs_target: false,
+        description: "Show this help",
+        handler: |c| Box::pin(cmd_help(c)),
+    },
+    GmCommand {
+        name: "/item",
+        args: &[
+            ArgSpec::int("id").range(0, i32::MAX as i64),
+            ArgSpec::int("amount"),
+        ],
+        role: GmRole::Tester,
+        needs_target: true,
+        description: "Add items",
+        handler: |c| Box::pin(cmd_item(c)),
+    },
+    GmCommand {
+        name: "/currency",
+        args: &[
+            ArgSpec::int("id").range(0, i32::MAX as i64),
+            ArgSpec::int("amount"),
+        ],
+        role: GmRole::Tester,
+        needs_target: true,
+        description: "Add currency",
+        handler: |c| Box::pin(cmd_currency(c)),
+    },
+    GmCommand {
+        name: "/level",
+        args: &[ArgSpec::int("level")],
+        role: GmRole::Tester,
+        needs_target: true,
+        description: "Set player level",
+        handler: |c| Box::pin(cmd_level(c)),
+    },
+    GmCommand {
+        name: "/hero",
+        args: &[ArgSpec::int("id")],
+        role: GmRole::Tester,
+        needs_target: true,
+        description: "Add hero",
+        handler: |c| Box::pin(cmd_hero(c)),
+    },
+    GmCommand {
+        name: "/equip",
+        args: &[
+            ArgSpec::int("id").range(0, i32::MAX as i64),
+            ArgSpec::int("amount"),
+        ],
+        role: GmRole::Tester,
+        needs_target: true,
+        description: "Add equipment",
+        handler: |c| Box::pin(cmd_equip(c)),
+    },
+    GmCommand {
+        name: "/herolevel",
+        args: &[ArgSpec::int("hero_id"), ArgSpec::int("level")],
+        role: GmRole::Tester,
+        needs_target: true,
+        description: "Set a hero's level within its insight rank",
+        handler: |c| Box::pin(cmd_hero_level(c)),
+    },
+    GmCommand {
+        name: "/insight",
+        args: &[ArgSpec::int("hero_id"), ArgSpec::int("rank")],
+        role: GmRole::Tester,
+        needs_target: true,
+        description: "Set a hero's insight rank",
+        handler: |c| Box::pin(cmd_insight(c)),
+    },
+    GmCommand {
+        name: "/portray",
+        args: &[ArgSpec::int("hero_id"), ArgSpec::int("level")],
+        role: GmRole::Tester,
+        needs_target: true,
+        description: "Set a hero's portray level",
+        handler: |c| Box::pin(cmd_portray(c)),
+    },
+    GmCommand {
+        name: "/resonance",
+        args: &[ArgSpec::int("hero_id"), ArgSpec::int("level")],
+        role: GmRole::Tester,
+        needs_target: true,
+        description: "Set a hero's resonance level",
+        handler: |c| Box::pin(cmd_resonance(c)),
+    },
+    GmCommand {
+        name: "/euphoria",
+        args: &[
+            ArgSpec::int("hero_id"),
+            ArgSpec::int("rank"),
+            ArgSpec::int("level").optional(),
+        ],
+        role: GmRole::Tester,
+        needs_target: true,
+        description: "Set a hero's euphoria rank and level",
+        handler: |c| Box::pin(cmd_euphoria(c)),
+    },
+    GmCommand {
+        name: "/episode",
+        args: &[ArgSpec::int("episode_id")],
+        role: GmRole::Tester,
+        needs_target: true,
+        description: "Clear an episode and every episode before it",
+        handler: |c| Box::pin(cmd_episode(c)),
+    },
+    GmCommand {
+        name: "/chapter",
+        args: &[ArgSpec::int("chapter_id")],
+        role: GmRole::Tester,
+        needs_target: true,
+        description: "Clear a chapter and every chapter before it",
+        handler: |c| Box::pin(cmd_chapter(c)),
+    },
+    GmCommand {
+        name: "/story",
+        args: &[ArgSpec::int("story_id")],
+        role: GmRole::Tester,
+        needs_target: true,
+        description: "Finish a story",
+        handler: |c| Box::pin(cmd_story(c)),
+    },
+    GmCommand {
+        name: "/guide",
+        args: &[ArgSpec::int("guide_id").optional()],
+        role: GmRole::Tester,
+        needs_target: true,
+        description: "Complete a guide (all guides when no id is given)",
+        handler: |c| Box::pin(cmd_guide(c)),
+    },
+    GmCommand {
+        name: "/find",
+        args: &[ArgSpec::rest("name")],
+        role: GmRole::Tester,
+        needs_target: false,
+        description: "Search items, currencies, equipment, heroes and skins by name",
+        handler: |c| Box::pin(cmd_find(c)),
+    },
+    GmCommand {
+        name: "/give",
+        args: &[ArgSpec::rest("name"), ArgSpec::int("amount")],
+        role: GmRole::Tester,
+        needs_target: true,
+        description: "Give the best match for a name, heroes and skins take an amount of 1",
+        handler: |c| Box::pin(cmd_give(c)),
+    },
+    GmCommand {
+        name: "/role",
+        args: &[
+            ArgSpec::int("user_id").range(1, i64::MAX),
+            ArgSpec::text("player|tester|admin"),
+        ],
+        role: GmRole::Admin,
+        needs_target: false,
+        description: "Set an account's GM role",
+        handler: |c| Box::pin(cmd_role(c)),
+    },
+    GmCommand {
+        name: "/audit",
+        args: &[ArgSpec::int("count").optional()],
+        role: GmRole::Admin,
+        needs_target: false,
+        description: "Show the latest GM commands run",
+        handler: |c| Box::pin(cmd_audit(c)),
+    },
+];
+
+pub fn find_command(name: &str) -> Option<&'static GmCommand> {
+    COMMANDS.iter().find(|c| c.name.eq_ignore_ascii_case(name))
+}
+
+async fn cmd_help(ctx: CommandContext) -> Result<String, AppError> {
+    let mut lines = vec!["Available GM Commands:".to_string()];
+
+    for command in COMMANDS.iter().filter(|c| c.role <= ctx.role) {
+        lines.push(format!("{} - {}", command.usage(), command.description));
+    }
+
+    Ok(lines.join("\n"))
+}
+
+async fn cmd_item(ctx: CommandContext) -> Result<String, AppError> {
+    let item_id = ctx.args.u32("id");
+    let amount = ctx.args.i32("amount");
+
+    give_item(&ctx, item_id, amount).await
+}
+
+async fn give_item(ctx: &CommandContext, item_id: u32, amount: i32) -> Result<String, AppError> {
+    let user_id = ctx.user_id()?;
+
+    let game_data = config::configs::get();
+    if game_data.item.get(item_id as i32).is_none() {
+        return Err(GmError::Rejected(format!("Invalid item ID: {}", item_id)).into());
+    }
+
+    add_items(ctx.db(), user_id, &[(item_id, amount)]).await?;
+
+    if let Some(session) = &ctx.session {
+        push::send_item_change_push(session.clone(), user_id, vec![item_id], vec![], vec![])
+            .await?;
+
+        let material_changes = vec![(1, item_id, amount)];
+        push::send_material_change_push(session.clone(), material_changes, None).await?;
+    }
+
+    Ok(format!("Added {} of item {}", amount, item_id))
+}
+
+async fn cmd_currency(ctx: CommandContext) -> Result<String, AppError> {
+    let currency_id = ctx.args.i32("id");
+    let amount = ctx.args.i32("amount");
+
+    give_currency(&ctx, currency_id, amount).await
+}
+
+async fn give_currency(
+    ctx: &CommandContext,
+    currency_id: i32,
+    amount: i32,
+) -> Result<String, AppError> {
+    let user_id = ctx.user_id()?;
+
+    add_currencies(ctx.db(), user_id, &[(currency_id, amount)]).await?;
+
+    if let Some(session) = &ctx.session {
+        push::send_currency_change_push(session.clone(), user_id, vec![(currency_id, amount)])
+            .await?;
+
+        let material_changes = vec![(2, currency_id as u32, amount)];
+        push::send_material_change_push(session.clone(), material_changes, None).await?;
+    }
+
+    Ok(format!("Added {} of currency {}", amount, currency_id))
+}
+
+async fn cmd_level(ctx: CommandContext) -> Result<String, AppError> {
+    let user_id = ctx.user_id()?;
+    let level = ctx.args.i32("level");
+
+    if !(1..=80).contains(&level) {
+        return Err(GmError::Rejected("Level must be between 1 and 80".to_string()).into());
+    }
+
+    user::account::update_user_level(ctx.db(), user_id, level).await?;
+
+    Ok(format!("Set level to {}", level))
+}
+
+async fn cmd_hero(ctx: CommandContext) -> Result<String, AppError> {
+    let hero_id = ctx.args.i32("id");
+
+    give_hero(&ctx, hero_id).await
+}
+
+async fn give_hero(ctx: &CommandContext, hero_id: i32) -> Result<String, AppError> {
+    let user_id = ctx.user_id()?;
+
+    let game_data = config::configs::get();
+    if !game_data.character.iter().any(|c| c.id == hero_id) {
+        return Err(GmError::Rejected(format!("Invalid hero ID: {}", hero_id)).into());
+    }
+
+    let hero = UserHeroModel::new(user_id, ctx.db().clone());
+
+    if hero.has_hero(hero_id).await? {
+        return Err(GmError::Rejected(format!("You already have hero {}", hero_id)).into());
+    }
+
+    hero.create_hero(hero_id).await?;
+    send_hero_update(ctx, &hero, hero_id).await?;
+
+    Ok(format!("Added hero {}", hero_id))
+}
+
+async fn cmd_equip(ctx: CommandContext) -> Result<String, AppError> {
+    let equip_id = ctx.args.i32("id");
+    let amount = ctx.args.i32("amount");
+
+    give_equip(&ctx, equip_id, amount).await
+}
+
+async fn give_equip(ctx: &CommandContext, equip_id: i32, amount: i32) -> Result<String, AppError> {
+    let user_id = ctx.user_id()?;
+
+    let game_data = config::configs::get();
+    if game_data.equip.get(equip_id).is_none() {
+        return Err(GmError::Rejected(format!("Invalid equipment ID: {}", equip_id)).into());
+    }
+
+    let db = ctx.db();
+
+    let equip_uids: Vec<i64> =
+        if equip_id == 1002 || equip_id == 1003 || equip_id == 1004 || equip_id == 1005 {
+            game::equipment::update_equipment_count(db, user_id, equip_id, amount).await?
+        } else {
+            game::equipment::add_equipments(db, user_id, &[(equip_id, amount)]).await?
+        };
+
+    if let Some(session) = &ctx.session {
+        push::send_equip_update_push_by_uid(session.clone(), user_id, &equip_uids).await?;
+
+        let material_changes = vec![(9, equip_id as u32, amount)];
+        push::send_material_change_push(session.clone(), material_changes, None).await?;
+    }
+
+    Ok(format!("Added {} of equipment {}", amount, equip_id))
+}
+
+async fn give_skin(ctx: &CommandContext, skin_id: i32) -> Result<String, AppError> {
+    let user_id = ctx.user_id()?;
+
+    let game_data = config::configs::get();
+    let Some(skin) = game_data.skin.get(skin_id) else {
+        return Err(GmError::Rejected(format!("Invalid skin ID: {}", skin_id)).into());
+    };
+
+    let hero = UserHeroModel::new(user_id, ctx.db().clone());
+
+    if !hero.has_hero(skin.character_id).await? {
+        return Err(GmError::Rejected(format!(
+            "Skin {} needs hero {}, add the hero first",
+            skin_id, skin.character_id
+        ))
+        .into());
+    }
+
+    if !hero.add_skin(skin.character_id, skin_id).await? {
+        return Err(GmError::Rejected(format!("You already have skin {}", skin_id)).into());
+    }
+    send_hero_update(ctx, &hero, skin.character_id).await?;
+
+    Ok(format!("Added skin {}", skin_id))
+}
+
+async fn cmd_find(ctx: CommandContext) -> Result<String, AppError> {
+    let query = ctx.args.text("name");
+
+    let matches = search::search(config::configs::get(), query, 10);
+    if matches.is_empty() {
+        return Ok(format!("No match for \"{}\"", query));
+    }
+
+    let mut lines = vec![format!("Matches for \"{}\":", query)];
+    lines.extend(matches.iter().map(format_match));
+
+    Ok(lines.join("\n"))
+}
+
+async fn cmd_give(ctx: CommandContext) -> Result<String, AppError> {
+    let query = ctx.args.text("name");
+    let amount = ctx.args.i32("amount");
+
+    let matches = search::search(config::configs::get(), query, 5);
+    let Some(best) = pick_match(&matches) else {
+        if matches.is_empty() {
+            return Err(GmError::Rejected(format!("No match for \"{}\"", query)).into());
+        }
+
+        let mut lines = vec![format!(
+            "\"{}\" is ambiguous, use a longer name or an id command:",
+            query
+        )];
+        lines.extend(matches.iter().map(format_match));
+        return Err(GmError::Rejected(lines.join("\n")).into());
+    };
+
+    // a hero or skin can only be owned once
+    if matches!(best.kind, MatchKind::Hero | MatchKind::Skin) && amount != 1 {
+        return Err(GmError::Rejected(format!(
+            "{} is a {}, it can only be given once (amount 1)",
+            best.name,
+            best.kind.as_str()
+        ))
+        .into());
+    }
+
+    match best.kind {
+        MatchKind::Item => give_item(&ctx, best.id as u32, amount).await,
+        MatchKind::Currency => give_currency(&ctx, best.id, amount).await,
+        MatchKind::Equip => give_equip(&ctx, best.id, amount).await,
+        MatchKind::Hero => give_hero(&ctx, best.id).await,
+        MatchKind::Skin => give_skin(&ctx, best.id).await,
+    }
+}
+
+/// Best match when it clearly beats the runner-up (an exact name always wins)
+fn pick_match(matches: &[SearchMatch]) -> Option<&SearchMatch> {
+    let best = matches.first()?;
+    match matches.get(1) {
+        Some(next) if next.score == best.score && best.score < 1000 => None,
+        _ => Some(best),
+    }
+}
+
+fn format_match(m: &SearchMatch) -> String {
+    format!("[{}] {} - {}", m.kind.as_str(), m.id, m.name)
+}
+
+async fn cmd_role(ctx: CommandContext) -> Result<String, AppError> {
+    let user_id = ctx.args.int("user_id");
+    let role_name = ctx.args.text("player|tester|admin");
+
+    let Some(role) = GmRole::from_name(role_name) else {
+        return Err(GmError::Rejected(format!("Unknown role: {}", role_name)).into());
+    };
+
+    if !user::gm::set_gm_role(ctx.db(), user_id, role).await? {
+        return Err(GmError::Rejected(format!("User {} not found", user_id)).into());
+    }
+
+    Ok(format!("Set role of user {} to {}", user_id, role.name()))
+}
+
+async fn cmd_audit(ctx: CommandContext) -> Result<String, AppError> {
+    let count = ctx.args.opt_int("count").unwrap_or(10).clamp(1, 100);
+
+    let entries = user::gm::get_recent_audit_entries(ctx.db(), count).await?;
+    if entries.is_empty() {
+        return Ok("No GM commands recorded".to_string());
+    }
+
+    let lines: Vec<String> = entries
+        .iter()
+        .map(|e| {
+            format!(
+                "#{} [{}] {} -> {}: {} ({})",
+                e.id,
+                e.source,
+                e.invoker_id
+                    .map(|id| id.to_string())
+                    .unwrap_or_else(|| "server".to_string()),
+                e.target_user_id
+                    .map(|id| id.to_string())
+                    .unwrap_or_else(|| "-".to_string()),
+                e.command,
+                if e.success { "ok" } else { "failed" }
+            )
+        })
+        .collect();
+
+    Ok(lines.join("\n"))
+}
@@ -0,0 +1,49 @@
+use super::{GmInvocation, GmSource, execute};
+use crate::state::AppState;
+use database::models::user::gm::GmRole;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+/// Reads GM commands from stdin, runs them with the admin role
+///
+/// `<user_id> /item 140001 10` targets a player, commands that don't need one
+/// (`/help`, `/role <user_id> admin`) can be typed without it
+pub async fn run_console(state: Arc<AppState>) {
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => return,
+            Err(e) => {
+                tracing::error!("Console read error: {}", e);
+                return;
+            }
+        };
+
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let (target_user_id, input) = match line.split_once(char::is_whitespace) {
+            Some((first, rest)) if first.parse::<i64>().is_ok() => {
+                (first.parse::<i64>().ok(), rest.trim())
+            }
+            _ => (None, line),
+        };
+
+        let invocation = GmInvocation {
+            source: GmSource::Console,
+            invoker_id: None,
+            role: GmRole::Admin,
+            target_user_id,
+            session: None,
+        };
+
+        match execute(state.clone(), invocation, input).await {
+            Ok(msg) => tracing::info!("[console] {}", msg),
+            Err(e) => tracing::error!("[console] Error: {}", e),
+        }
+    }
+}
@@ -1,208 +1,153 @@
-use crate::error::AppError;
-use crate::state::ConnectionContext;
-use crate::util::inventory::{add_currencies, add_items};
-use crate::util::push;
-use database::db::{game, user};
-use database::models::game::heros::UserHeroModel;
+//! GM commands
+//!
+//! Every command is declared in `commands::COMMANDS` with its argument schema and
+//! the minimum role needed to run it. Chat (Sonetto Bot), the server console and
+//! the admin api all go through [`execute`], which checks permissions, parses the
+//! arguments and writes the audit log. The admin api is served by the sdkserver,
+//! which forwards its commands to [`run_api`] so they reach the online sessions.
+
+pub mod api;
+mod commands;
+mod console;
+mod progression;
+mod registry;
+mod search;
+
+pub use api::run_api;
+pub use console::run_console;
+pub use registry::{GmInvocation, GmSource};
+
+use commands::find_command;
+use registry::CommandContext;
+
+use crate::error::{AppError, GmError};
+use crate::state::{AppState, ConnectionContext};
+use database::db::user::gm;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
-pub struct CommandContext {
-    pub ctx: Arc<Mutex<ConnectionContext>>,
-    pub user_id: i64,
-    pub args: Vec<String>,
-}
-
+/// Run a command typed by the logged in player (chat with the Sonetto Bot)
 pub async fn execute_command(
     ctx: Arc<Mutex<ConnectionContext>>,
     input: &str,
 ) -> Result<String, AppError> {
-    let input = input.trim();
-    if !input.starts_with("/") {
-        return Err(AppError::InvalidRequest);
-    }
-
-    let parts: Vec<String> = input.split_whitespace().map(|s| s.to_string()).collect();
-    if parts.is_empty() {
-        return Ok("Invalid command".to_string());
-    }
-
-    let cmd = &parts[0];
-    let args = parts[1..].to_vec();
+    let (state, user_id) = {
+        let conn = ctx.lock().await;
+        (
+            conn.state.clone(),
+            conn.player_id.ok_or(AppError::NotLoggedIn)?,
+        )
+    };
 
-    let user_id = ctx.lock().await.player_id.ok_or(AppError::NotLoggedIn)?;
+    let role = gm::get_gm_role(&state.db, user_id).await?;
 
-    let cmd_ctx = CommandContext {
-        ctx: ctx.clone(),
-        user_id,
-        args,
+    let invocation = GmInvocation {
+        source: GmSource::Chat,
+        invoker_id: Some(user_id),
+        role,
+        target_user_id: Some(user_id),
+        session: Some(ctx),
     };
 
-    match cmd.as_str() {
-        "/help" => Ok(get_help()),
-        "/item" => cmd_item(cmd_ctx).await,
-        "/currency" => cmd_currency(cmd_ctx).await,
-        "/level" => cmd_level(cmd_ctx).await,
-        "/hero" => cmd_hero(cmd_ctx).await,
-        "/equip" => cmd_equip(cmd_ctx).await,
-        _ => Ok(format!("Unknown command: {}", cmd)),
-    }
+    execute(state, invocation, input).await
 }
 
-fn get_help() -> String {
-    r#"Available GM Commands:
-/help - Show this help
-/item <id> <amount> - Add items
-/currency <id> <amount> - Add currency
-/level <level> - Set player level
-/hero <id> - Add hero
-/equip <id> <amount> - Add equipment"#
-        .to_string()
-}
-
-async fn cmd_item(ctx: CommandContext) -> Result<String, AppError> {
-    if ctx.args.len() < 2 {
-        return Ok("Usage: /item <id> <amount>".to_string());
+pub async fn execute(
+    state: Arc<AppState>,
+    invocation: GmInvocation,
+    input: &str,
+) -> Result<String, AppError> {
+    let input = input.trim();
+    if !input.starts_with('/') {
+        return Err(GmError::InvalidCommand.into());
     }
 
-    let item_id: u32 = match ctx.args[0].parse() {
-        Ok(id) => id,
-        Err(_) => return Ok(format!("Invalid item ID: {}", ctx.args[0])),
-    };
+    let result = dispatch(state.clone(), &invocation, input).await;
 
-    let amount: i32 = match ctx.args[1].parse() {
-        Ok(amt) => amt,
-        Err(_) => return Ok(format!("Invalid amount: {}", ctx.args[1])),
+    let (success, message) = match &result {
+        Ok(msg) => (true, msg.clone()),
+        Err(e) => (false, e.to_string()),
     };
 
-    let game_data = config::configs::get();
-    if game_data.item.get(item_id as i32).is_none() {
-        return Ok(format!("Invalid item ID: {}", item_id));
+    if let Err(e) = gm::insert_audit_entry(
+        &state.db,
+        invocation.source.as_str(),
+        invocation.invoker_id,
+        invocation.target_user_id,
+        input,
+        success,
+        &message,
+    )
+    .await
+    {
+        tracing::error!("Failed to write GM audit log: {}", e);
     }
 
-    let db = ctx.ctx.lock().await.state.db.clone();
-
-    add_items(&db, ctx.user_id, &[(item_id, amount)]).await?;
+    tracing::info!(
+        "GM [{}] invoker={:?} target={:?}: {} -> {}",
+        invocation.source.as_str(),
+        invocation.invoker_id,
+        invocation.target_user_id,
+        input,
+        if success { "ok" } else { "failed" }
+    );
 
-    push::send_item_change_push(ctx.ctx.clone(), ctx.user_id, vec![item_id], vec![], vec![])
-        .await?;
-
-    let material_changes = vec![(1, item_id, amount)];
-    push::send_material_change_push(ctx.ctx.clone(), material_changes, None).await?;
-
-    Ok(format!("Added {} of item {}", amount, item_id))
+    result
 }
 
-async fn cmd_currency(ctx: CommandContext) -> Result<String, AppError> {
-    if ctx.args.len() < 2 {
-        return Ok("Usage: /currency <id> <amount>".to_string());
-    }
-
-    let currency_id: i32 = match ctx.args[0].parse() {
-        Ok(id) => id,
-        Err(_) => return Ok(format!("Invalid currency ID: {}", ctx.args[0])),
+async fn dispatch(
+    state: Arc<AppState>,
+    invocation: &GmInvocation,
+    input: &str,
+) -> Result<String, AppError> {
+    let parts: Vec<&str> = input.split_whitespace().collect();
+    let Some(name) = parts.first() else {
+        return Err(GmError::InvalidCommand.into());
     };
 
-    let amount: i32 = match ctx.args[1].parse() {
-        Ok(amt) => amt,
-        Err(_) => return Ok(format!("Invalid amount: {}", ctx.args[1])),
+    let Some(command) = find_command(name) else {
+        return Err(GmError::UnknownCommand(name.to_string()).into());
     };
 
-    let db = ctx.ctx.lock().await.state.db.clone();
-
-    add_currencies(&db, ctx.user_id, &[(currency_id, amount)]).await?;
-
-    push::send_currency_change_push(ctx.ctx.clone(), ctx.user_id, vec![(currency_id, amount)])
-        .await?;
-
-    let material_changes = vec![(2, currency_id as u32, amount)];
-    push::send_material_change_push(ctx.ctx.clone(), material_changes, None).await?;
-
-    Ok(format!("Added {} of currency {}", amount, currency_id))
-}
-
-async fn cmd_level(ctx: CommandContext) -> Result<String, AppError> {
-    if ctx.args.is_empty() {
-        return Ok("Usage: /level <level>".to_string());
+    if invocation.role < command.role {
+        return Err(GmError::PermissionDenied {
+            command: command.name,
+            role: command.role.name(),
+        }
+        .into());
     }
 
-    let level: i32 = match ctx.args[0].parse() {
-        Ok(lvl) => lvl,
-        Err(_) => return Ok(format!("Invalid level: {}", ctx.args[0])),
-    };
-
-    if !(1..=80).contains(&level) {
-        return Ok("Level must be between 1 and 80".to_string());
+    if command.needs_target && invocation.target_user_id.is_none() {
+        return Err(GmError::MissingTarget(command.name).into());
     }
 
-    let db = ctx.ctx.lock().await.state.db.clone();
-
-    user::account::update_user_level(&db, ctx.user_id, level).await?;
-
-    Ok(format!("Set level to {}", level))
-}
-
-async fn cmd_hero(ctx: CommandContext) -> Result<String, AppError> {
-    if ctx.args.is_empty() {
-        return Ok("Usage: /hero <id>".to_string());
-    }
+    let args = command
+        .parse_args(&parts[1..])
+        .map_err(GmError::InvalidArgs)?;
 
-    let hero_id: i32 = match ctx.args[0].parse() {
-        Ok(id) => id,
-        Err(_) => return Ok(format!("Invalid hero ID: {}", ctx.args[0])),
+    let session = match &invocation.session {
+        Some(session) => Some(session.clone()),
+        None => invocation
+            .target_user_id
+            .and_then(|id| state.get_connection_context(id)),
     };
 
-    let game_data = config::configs::get();
-    if !game_data.character.iter().any(|c| c.id == hero_id) {
-        return Ok(format!("Invalid hero ID: {}", hero_id));
-    }
-
-    let pool = ctx.ctx.lock().await.state.db.clone();
-
-    let hero = UserHeroModel::new(ctx.user_id, pool);
-
-    if hero.has_hero(hero_id).await? {
-        return Ok(format!("You already have hero {}", hero_id));
-    }
-
-    hero.create_hero(hero_id).await?;
-
-    Ok(format!("Added hero {}", hero_id))
-}
-
-async fn cmd_equip(ctx: CommandContext) -> Result<String, AppError> {
-    if ctx.args.len() < 2 {
-        return Ok("Usage: /equip <id> <amount>".to_string());
-    }
-
-    let equip_id: i32 = match ctx.args[0].parse() {
-        Ok(id) => id,
-        Err(_) => return Ok(format!("Invalid equipment ID: {}", ctx.args[0])),
+    let cmd_ctx = CommandContext {
+        state,
+        session: session.clone(),
+        target_user_id: invocation.target_user_id,
+        role: invocation.role,
+        args,
     };
 
-    let amount: i32 = match ctx.args[1].parse() {
-        Ok(amt) => amt,
-        Err(_) => return Ok(format!("Invalid amount: {}", ctx.args[1])),
-    };
+    let result = (command.handler)(cmd_ctx).await;
 
-    let game_data = config::configs::get();
-    if game_data.equip.get(equip_id).is_none() {
-        return Ok(format!("Invalid equipment ID: {}", equip_id));
+    // chat replies are flushed by the client loop, other sources have to wake it
+    if invocation.source != GmSource::Chat
+        && let Some(session) = session
+    {
+        session.lock().await.request_flush();
     }
 
-    let db = ctx.ctx.lock().await.state.db.clone();
-
-    let equip_uids: Vec<i64> =
-        if equip_id == 1002 || equip_id == 1003 || equip_id == 1004 || equip_id == 1005 {
-            game::equipment::update_equipment_count(&db, ctx.user_id, equip_id, amount).await?
-        } else {
-            game::equipment::add_equipments(&db, ctx.user_id, &[(equip_id, amount)]).await?
-        };
-
-    push::send_equip_update_push_by_uid(ctx.ctx.clone(), ctx.user_id, &equip_uids).await?;
-
-    let material_changes = vec![(9, equip_id as u32, amount)];
-    push::send_material_change_push(ctx.ctx.clone(), material_changes, None).await?;
-
-    Ok(format!("Added {} of equipment {}", amount, equip_id))
+    result
 }
@@ -0,0 +1,476 @@
+use super::registry::CommandContext;
+use crate::error::{AppError, GmError};
+use crate::util::push;
+use config::character_level::CharacterLevel;
+use database::db::game::{dungeons, guides, stories};
+use database::models::game::heros::{HeroModel, UserHeroModel};
+use sonettobuf::{CmdId, GuideInfo, HeroUpdatePush, StoryFinishPush, UpdateGuidePush};
+use std::collections::HashSet;
+
+/// Stars given for episodes cleared through GM commands
+const GM_EPISODE_STARS: i32 = 2;
+
+async fn owned_hero(ctx: &CommandContext, hero_id: i32) -> Result<Option<UserHeroModel>, AppError> {
+    let hero = UserHeroModel::new(ctx.user_id()?, ctx.db().clone());
+
+    if !hero.has_hero(hero_id).await? {
+        return Ok(None);
+    }
+
+    Ok(Some(hero))
+}
+
+pub(super) async fn send_hero_update(
+    ctx: &CommandContext,
+    hero: &UserHeroModel,
+    hero_id: i32,
+) -> Result<(), AppError> {
+    let Some(session) = &ctx.session else {
+        return Ok(());
+    };
+
+    let hero_info: sonettobuf::HeroInfo = hero.get_hero(hero_id).await?.into();
+
+    let mut conn = session.lock().await;
+    conn.notify(
+        CmdId::HeroHeroUpdatePushCmd,
+        HeroUpdatePush {
+            hero_updates: vec![hero_info],
+        },
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Level required to reach `rank`, `None` when the hero has no such rank
+fn rank_required_level(hero_id: i32, rank: i32) -> Option<i32> {
+    let rank_data = config::configs::get()
+        .character_rank
+        .iter()
+        .find(|r| r.hero_id == hero_id && r.rank == rank)?;
+
+    // same requirement format as the rank up handler: "1#<level>"
+    let mut parts = rank_data.requirement.split('#');
+    match (parts.next(), parts.next()) {
+        (Some("1"), Some(level)) => level.parse().ok(),
+        _ => Some(1),
+    }
+}
+
+/// Highest level a hero can be at its insight rank: the level the next rank requires, or
+/// the hero's last level at the highest rank. Insight starts every rank over at level 1.
+fn rank_max_level(hero_id: i32, rank: i32) -> i32 {
+    rank_required_level(hero_id, rank + 1)
+        .unwrap_or_else(|| {
+            config::configs::get()
+                .character_level
+                .iter()
+                .filter(|l| l.hero_id == hero_id)
+                .map(|l| l.level)
+                .max()
+                .unwrap_or(1)
+        })
+        .max(1)
+}
+
+/// Stats of the hero at `level`, from the closest level row at or below it
+fn level_stats(hero_id: i32, level: i32) -> Option<&'static CharacterLevel> {
+    config::configs::get()
+        .character_level
+        .iter()
+        .filter(|l| l.hero_id == hero_id && l.level <= level)
+        .max_by_key(|l| l.level)
+}
+
+pub async fn cmd_hero_level(ctx: CommandContext) -> Result<String, AppError> {
+    let hero_id = ctx.args.i32("hero_id");
+    let level = ctx.args.i32("level");
+
+    let Some(hero) = owned_hero(&ctx, hero_id).await? else {
+        return Err(GmError::Rejected(format!("You don't have hero {}", hero_id)).into());
+    };
+
+    let rank = hero.get_hero(hero_id).await?.record.rank;
+    let max_level = rank_max_level(hero_id, rank);
+
+    if !(1..=max_level).contains(&level) {
+        return Err(GmError::Rejected(format!(
+            "Level must be between 1 and {} at insight rank {}",
+            max_level, rank
+        ))
+        .into());
+    }
+
+    let Some(stats) = level_stats(hero_id, level) else {
+        return Err(GmError::Rejected(format!(
+            "No level stats for hero {} at level {}",
+            hero_id, level
+        ))
+        .into());
+    };
+
+    hero.level_up(hero_id, level, stats).await?;
+    send_hero_update(&ctx, &hero, hero_id).await?;
+
+    Ok(format!("Set hero {} level to {}", hero_id, level))
+}
+
+pub async fn cmd_insight(ctx: CommandContext) -> Result<String, AppError> {
+    let hero_id = ctx.args.i32("hero_id");
+    let rank = ctx.args.i32("rank");
+
+    let Some(hero) = owned_hero(&ctx, hero_id).await? else {
+        return Err(GmError::Rejected(format!("You don't have hero {}", hero_id)).into());
+    };
+
+    let game_data = config::configs::get();
+    let max_rank = game_data
+        .character_rank
+        .iter()
+        .filter(|r| r.hero_id == hero_id)
+        .map(|r| r.rank)
+        .max()
+        .unwrap_or(1);
+
+    if !(1..=max_rank).contains(&rank) {
+        return Err(
+            GmError::Rejected(format!("Insight rank must be between 1 and {}", max_rank)).into(),
+        );
+    }
+
+    hero.rank_up(hero_id, rank).await?;
+
+    // keep the level and its stats within what the new rank allows
+    let level = hero
+        .get_hero(hero_id)
+        .await?
+        .record
+        .level
+        .min(rank_max_level(hero_id, rank));
+    if let Some(stats) = level_stats(hero_id, level) {
+        hero.level_up(hero_id, level, stats).await?;
+    }
+
+    hero.unlock_insight_skin(hero_id, rank).await?;
+    send_hero_update(&ctx, &hero, hero_id).await?;
+
+    Ok(format!("Set hero {} insight rank to {}", hero_id, rank))
+}
+
+pub async fn cmd_portray(ctx: CommandContext) -> Result<String, AppError> {
+    let hero_id = ctx.args.i32("hero_id");
+    let level = ctx.args.i32("level");
+
+    let Some(hero) = owned_hero(&ctx, hero_id).await? else {
+        return Err(GmError::Rejected(format!("You don't have hero {}", hero_id)).into());
+    };
+
+    if !(0..=5).contains(&level) {
+        return Err(GmError::Rejected("Portray must be between 0 and 5".to_string()).into());
+    }
+
+    let current = hero.get_hero(hero_id).await?.record.ex_skill_level;
+    hero.upgrade_ex_skill(hero_id, level - current).await?;
+    send_hero_update(&ctx, &hero, hero_id).await?;
+
+    Ok(format!("Set hero {} portray to {}", hero_id, level))
+}
+
+pub async fn cmd_resonance(ctx: CommandContext) -> Result<String, AppError> {
+    let hero_id = ctx.args.i32("hero_id");
+    let level = ctx.args.i32("level");
+
+    let Some(hero) = owned_hero(&ctx, hero_id).await? else {
+        return Err(GmError::Rejected(format!("You don't have hero {}", hero_id)).into());
+    };
+
+    let game_data = config::configs::get();
+    let max_level = game_data
+        .character_talent
+        .iter()
+        .filter(|t| t.hero_id == hero_id)
+        .map(|t| t.talent_id)
+        .max()
+        .unwrap_or(1);
+
+    if !(1..=max_level).contains(&level) {
+        return Err(
+            GmError::Rejected(format!("Resonance must be between 1 and {}", max_level)).into(),
+        );
+    }
+
+    hero.update_talent(hero_id, level).await?;
+    send_hero_update(&ctx, &hero, hero_id).await?;
+
+    Ok(format!("Set hero {} resonance to {}", hero_id, level))
+}
+
+pub async fn cmd_euphoria(ctx: CommandContext) -> Result<String, AppError> {
+    let hero_id = ctx.args.i32("hero_id");
+    let rank = ctx.args.i32("rank");
+
+    let Some(hero) = owned_hero(&ctx, hero_id).await? else {
+        return Err(GmError::Rejected(format!("You don't have hero {}", hero_id)).into());
+    };
+
+    let game_data = config::configs::get();
+    let facets: HashSet<i32> = game_data
+        .character_destiny
+        .iter()
+        .filter(|d| d.hero_id == hero_id)
+        .flat_map(|d| d.facets_id.split('#'))
+        .filter_map(|s| s.parse::<i32>().ok())
+        .collect();
+
+    if facets.is_empty() {
+        return Err(GmError::Rejected(format!("Hero {} has no euphoria", hero_id)).into());
+    }
+
+    let max_rank = game_data
+        .character_destiny_facets
+        .iter()
+        .filter(|f| facets.contains(&f.facets_id))
+        .map(|f| f.level)
+        .max()
+        .unwrap_or(1);
+
+    if !(0..=max_rank).contains(&rank) {
+        return Err(
+            GmError::Rejected(format!("Euphoria rank must be between 0 and {}", max_rank)).into(),
+        );
+    }
+
+    let level = if rank == 0 {
+        0
+    } else {
+        ctx.args.opt_i32("level").unwrap_or(1).max(1)
+    };
+
+    hero.update_destiny(hero_id, rank, level).await?;
+    send_hero_update(&ctx, &hero, hero_id).await?;
+
+    Ok(format!(
+        "Set hero {} euphoria to rank {} level {}",
+        hero_id, rank, level
+    ))
+}
+
+/// Clear episodes and send a `DungeonUpdatePush` for each of them
+async fn clear_episodes(ctx: &CommandContext, episode_ids: &[i32]) -> Result<(), AppError> {
+    let user_id = ctx.user_id()?;
+    let game_data = config::configs::get();
+
+    for episode_id in episode_ids {
+        let Some(episode) = game_data.episode.get(*episode_id) else {
+            continue;
+        };
+
+        dungeons::update_dungeon_progress(
+            ctx.db(),
+            user_id,
+            episode.chapter_id,
+            episode.id,
+            GM_EPISODE_STARS,
+        )
+        .await?;
+    }
+
+    let Some(session) = &ctx.session else {
+        return Ok(());
+    };
+
+    let type_nums = dungeons::get_chapter_type_nums(ctx.db(), user_id).await?;
+
+    for episode_id in episode_ids {
+        let Some(episode) = game_data.episode.get(*episode_id) else {
+            continue;
+        };
+
+        let chapter_type = game_data
+            .chapter
+            .get(episode.chapter_id)
+            .map(|c| c.r#type)
+            .unwrap_or(6);
+        let type_num = type_nums.iter().find(|n| n.chapter_type == chapter_type);
+
+        let dungeon =
+            dungeons::get_user_dungeon(ctx.db(), user_id, episode.chapter_id, episode.id).await?;
+
+        push::send_dungeon_update_push(
+            session.clone(),
+            episode.chapter_id,
+            episode.id,
+            dungeon.star,
+            dungeon.challenge_count,
+            dungeon.has_record,
+            chapter_type,
+            type_num.map(|n| n.today_pass_num).unwrap_or(0),
+            type_num.map(|n| n.today_total_num).unwrap_or(0),
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// The episode and every episode before it, oldest first
+fn episode_chain(episode_id: i32) -> Vec<i32> {
+    let game_data = config::configs::get();
+    let mut chain = Vec::new();
+    let mut seen = HashSet::new();
+    let mut current = episode_id;
+
+    while current != 0 && seen.insert(current) {
+        let Some(episode) = game_data.episode.get(current) else {
+            break;
+        };
+        chain.push(episode.id);
+        current = episode.pre_episode;
+    }
+
+    chain.reverse();
+    chain
+}
+
+/// The chapter and every chapter before it, oldest first
+fn chapter_chain(chapter_id: i32) -> Vec<i32> {
+    let game_data = config::configs::get();
+    let mut chain = Vec::new();
+    let mut seen = HashSet::new();
+    let mut current = chapter_id;
+
+    while current != 0 && seen.insert(current) {
+        let Some(chapter) = game_data.chapter.get(current) else {
+            break;
+        };
+        chain.push(chapter.id);
+        current = chapter.pre_chapter;
+    }
+
+    chain.reverse();
+    chain
+}
+
+pub async fn cmd_episode(ctx: CommandContext) -> Result<String, AppError> {
+    let episode_id = ctx.args.i32("episode_id");
+
+    let game_data = config::configs::get();
+    if game_data.episode.get(episode_id).is_none() {
+        return Err(GmError::Rejected(format!("Invalid episode ID: {}", episode_id)).into());
+    }
+
+    let episodes = episode_chain(episode_id);
+    clear_episodes(&ctx, &episodes).await?;
+
+    Ok(format!(
+        "Cleared episode {} ({} episodes)",
+        episode_id,
+        episodes.len()
+    ))
+}
+
+pub async fn cmd_chapter(ctx: CommandContext) -> Result<String, AppError> {
+    let chapter_id = ctx.args.i32("chapter_id");
+
+    let game_data = config::configs::get();
+    if game_data.chapter.get(chapter_id).is_none() {
+        return Err(GmError::Rejected(format!("Invalid chapter ID: {}", chapter_id)).into());
+    }
+
+    let chapters = chapter_chain(chapter_id);
+    let episodes: Vec<i32> = chapters
+        .iter()
+        .flat_map(|chapter_id| {
+            game_data
+                .episode
+                .iter()
+                .filter(move |e| e.chapter_id == *chapter_id)
+                .map(|e| e.id)
+        })
+        .collect();
+
+    clear_episodes(&ctx, &episodes).await?;
+
+    Ok(format!(
+        "Cleared chapter {} ({} chapters, {} episodes)",
+        chapter_id,
+        chapters.len(),
+        episodes.len()
+    ))
+}
+
+/// The story tables are client side, a story is known when an episode plays it before
+/// or after its fight or an antique unlocks it
+fn is_known_story(story_id: i32) -> bool {
+    if story_id <= 0 {
+        return false;
+    }
+
+    let game_data = config::configs::get();
+    game_data
+        .episode
+        .iter()
+        .any(|e| e.before_story == story_id || e.after_story == story_id)
+        || game_data.antique.iter().any(|a| a.story_id == story_id)
+}
+
+pub async fn cmd_story(ctx: CommandContext) -> Result<String, AppError> {
+    let user_id = ctx.user_id()?;
+    let story_id = ctx.args.i32("story_id");
+
+    if !is_known_story(story_id) {
+        return Err(GmError::Rejected(format!("Invalid story ID: {}", story_id)).into());
+    }
+
+    stories::finish_story(ctx.db(), user_id, story_id).await?;
+
+    if let Some(session) = &ctx.session {
+        let mut conn = session.lock().await;
+        conn.notify(
+            CmdId::StoryFinishPushCmd,
+            StoryFinishPush {
+                story_id: Some(story_id),
+            },
+        )
+        .await?;
+    }
+
+    Ok(format!("Finished story {}", story_id))
+}
+
+pub async fn cmd_guide(ctx: CommandContext) -> Result<String, AppError> {
+    let user_id = ctx.user_id()?;
+
+    let game_data = config::configs::get();
+    let guide_ids: Vec<i32> = match ctx.args.opt_i32("guide_id") {
+        Some(id) => {
+            if !game_data.guide.iter().any(|g| g.id == id) {
+                return Err(GmError::Rejected(format!("Invalid guide ID: {}", id)).into());
+            }
+            vec![id]
+        }
+        None => game_data.guide.iter().map(|g| g.id).collect(),
+    };
+
+    for guide_id in &guide_ids {
+        guides::complete_guide(ctx.db(), user_id, *guide_id).await?;
+    }
+
+    if let Some(session) = &ctx.session {
+        let push = UpdateGuidePush {
+            guide_infos: guide_ids
+                .iter()
+                .map(|id| GuideInfo {
+                    guide_id: *id,
+                    step_id: -1,
+                })
+                .collect(),
+        };
+
+        let mut conn = session.lock().await;
+        conn.notify(CmdId::UpdateGuidePushCmd, push).await?;
+    }
+
+    Ok(format!("Completed {} guide(s)", guide_ids.len()))
+}
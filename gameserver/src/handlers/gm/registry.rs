@@ -0,0 +1,301 @@
+use crate::error::AppError;
+use crate::state::{AppState, ConnectionContext};
+use database::models::user::gm::GmRole;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+pub type CommandFuture = Pin<Box<dyn Future<Output = Result<String, AppError>> + Send>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgKind {
+    Int,
+    Text,
+    /// Several words joined back together (names with spaces)
+    Rest,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ArgSpec {
+    pub name: &'static str,
+    pub kind: ArgKind,
+    pub optional: bool,
+    /// Accepted values of an int argument, the i32 range unless set with [`ArgSpec::range`]
+    pub min: i64,
+    pub max: i64,
+}
+
+impl ArgSpec {
+    pub const fn int(name: &'static str) -> Self {
+        Self {
+            name,
+            kind: ArgKind::Int,
+            optional: false,
+            min: i32::MIN as i64,
+            max: i32::MAX as i64,
+        }
+    }
+
+    pub const fn text(name: &'static str) -> Self {
+        Self {
+            name,
+            kind: ArgKind::Text,
+            optional: false,
+            min: i32::MIN as i64,
+            max: i32::MAX as i64,
+        }
+    }
+
+    pub const fn rest(name: &'static str) -> Self {
+        Self {
+            name,
+            kind: ArgKind::Rest,
+            optional: false,
+            min: i32::MIN as i64,
+            max: i32::MAX as i64,
+        }
+    }
+
+    pub const fn optional(self) -> Self {
+        Self {
+            optional: true,
+            ..self
+        }
+    }
+
+    pub const fn range(self, min: i64, max: i64) -> Self {
+        Self { min, max, ..self }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ArgValue {
+    Int(i64),
+    Text(String),
+}
+
+/// Arguments parsed against a command's schema
+#[derive(Debug, Clone, Default)]
+pub struct CommandArgs {
+    values: Vec<(&'static str, ArgValue)>,
+}
+
+impl CommandArgs {
+    fn get(&self, name: &str) -> Option<&ArgValue> {
+        self.values.iter().find(|(n, _)| *n == name).map(|(_, v)| v)
+    }
+
+    pub fn opt_int(&self, name: &str) -> Option<i64> {
+        match self.get(name) {
+            Some(ArgValue::Int(v)) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn opt_text(&self, name: &str) -> Option<&str> {
+        match self.get(name) {
+            Some(ArgValue::Text(v)) => Some(v.as_str()),
+            _ => None,
+        }
+    }
+
+    /// Required int argument (presence is checked while parsing)
+    pub fn int(&self, name: &str) -> i64 {
+        self.opt_int(name).unwrap_or_default()
+    }
+
+    /// Int argument whose spec keeps it in the i32 range
+    pub fn opt_i32(&self, name: &str) -> Option<i32> {
+        self.opt_int(name).and_then(|v| i32::try_from(v).ok())
+    }
+
+    /// Required i32 argument (presence and range are checked while parsing)
+    pub fn i32(&self, name: &str) -> i32 {
+        self.opt_i32(name).unwrap_or_default()
+    }
+
+    /// Required u32 argument, its spec's range must start at 0
+    pub fn u32(&self, name: &str) -> u32 {
+        u32::try_from(self.int(name)).unwrap_or_default()
+    }
+
+    /// Required text argument (presence is checked while parsing)
+    pub fn text(&self, name: &str) -> &str {
+        self.opt_text(name).unwrap_or_default()
+    }
+}
+
+pub struct GmCommand {
+    pub name: &'static str,
+    pub args: &'static [ArgSpec],
+    pub role: GmRole,
+    /// Whether the command acts on a player account
+    pub needs_target: bool,
+    pub description: &'static str,
+    pub handler: fn(CommandContext) -> CommandFuture,
+}
+
+impl GmCommand {
+    pub fn usage(&self) -> String {
+        let mut usage = self.name.to_string();
+        for arg in self.args {
+            if arg.kind == ArgKind::Rest {
+                usage.push_str(&format!(" <{}...>", arg.name));
+            } else if arg.optional {
+                usage.push_str(&format!(" [{}]", arg.name));
+            } else {
+                usage.push_str(&format!(" <{}>", arg.name));
+            }
+        }
+        usage
+    }
+
+    pub fn parse_args(&self, raw: &[&str]) -> Result<CommandArgs, String> {
+        let mut values = Vec::new();
+        let mut idx = 0;
+
+        for (pos, spec) in self.args.iter().enumerate() {
+            if idx >= raw.len() {
+                if spec.optional {
+                    continue;
+                }
+                return Err(format!("Missing <{}>\nUsage: {}", spec.name, self.usage()));
+            }
+
+            let value = match spec.kind {
+                ArgKind::Int => {
+                    let value = raw[idx].parse::<i64>().map_err(|_| {
+                        format!(
+                            "Invalid {}: {}\nUsage: {}",
+                            spec.name,
+                            raw[idx],
+                            self.usage()
+                        )
+                    })?;
+                    if !(spec.min..=spec.max).contains(&value) {
+                        return Err(format!(
+                            "{} must be between {} and {}\nUsage: {}",
+                            spec.name,
+                            spec.min,
+                            spec.max,
+                            self.usage()
+                        ));
+                    }
+                    idx += 1;
+                    ArgValue::Int(value)
+                }
+                ArgKind::Text => {
+                    idx += 1;
+                    ArgValue::Text(raw[idx - 1].to_string())
+                }
+                ArgKind::Rest => {
+                    // leave one word for every required argument after this one
+                    let trailing = self.args[pos + 1..].iter().filter(|a| !a.optional).count();
+                    let end = raw.len().saturating_sub(trailing).max(idx + 1);
+                    let value = raw[idx..end].join(" ");
+                    idx = end;
+                    ArgValue::Text(value)
+                }
+            };
+
+            values.push((spec.name, value));
+        }
+
+        if idx < raw.len() {
+            return Err(format!("Too many arguments\nUsage: {}", self.usage()));
+        }
+
+        Ok(CommandArgs { values })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GmSource {
+    Chat,
+    Console,
+    Api,
+}
+
+impl GmSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GmSource::Chat => "chat",
+            GmSource::Console => "console",
+            GmSource::Api => "api",
+        }
+    }
+}
+
+/// Who runs a command and on whose account
+pub struct GmInvocation {
+    pub source: GmSource,
+    pub invoker_id: Option<i64>,
+    pub role: GmRole,
+    pub target_user_id: Option<i64>,
+    /// Session of the target, looked up from the app state when not given
+    pub session: Option<Arc<Mutex<ConnectionContext>>>,
+}
+
+pub struct CommandContext {
+    pub state: Arc<AppState>,
+    /// Live session of the target player, None when they're offline
+    pub session: Option<Arc<Mutex<ConnectionContext>>>,
+    pub target_user_id: Option<i64>,
+    pub role: GmRole,
+    pub args: CommandArgs,
+}
+
+impl CommandContext {
+    pub fn user_id(&self) -> Result<i64, AppError> {
+        self.target_user_id.ok_or(AppError::MissingPlayerId)
+    }
+
+    pub fn db(&self) -> &sqlx::SqlitePool {
+        &self.state.db
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(args: &'static [ArgSpec]) -> GmCommand {
+        GmCommand {
+            name: "/test",
+            args,
+            role: GmRole::Player,
+            needs_target: false,
+            description: "",
+            handler: |_| Box::pin(async { Ok(String::new()) }),
+        }
+    }
+
+    #[test]
+    fn int_arguments_outside_the_i32_range_are_rejected() {
+        const ARGS: &[ArgSpec] = &[ArgSpec::int("amount")];
+        let cmd = command(ARGS);
+
+        assert_eq!(cmd.parse_args(&["-5"]).unwrap().i32("amount"), -5);
+        assert!(cmd.parse_args(&["2147483647"]).is_ok());
+        assert!(cmd.parse_args(&["2147483648"]).is_err());
+        assert!(cmd.parse_args(&["-2147483649"]).is_err());
+    }
+
+    #[test]
+    fn int_arguments_follow_their_range() {
+        const ARGS: &[ArgSpec] = &[
+            ArgSpec::int("id").range(0, i32::MAX as i64),
+            ArgSpec::int("user_id").range(1, i64::MAX).optional(),
+        ];
+        let cmd = command(ARGS);
+
+        assert!(cmd.parse_args(&["4294967295"]).is_err());
+        assert!(cmd.parse_args(&["-1"]).is_err());
+        assert!(cmd.parse_args(&["3", "0"]).is_err());
+
+        let args = cmd.parse_args(&["3", "8589934592"]).unwrap();
+        assert_eq!(args.u32("id"), 3);
+        assert_eq!(args.int("user_id"), 8589934592);
+    }
+}
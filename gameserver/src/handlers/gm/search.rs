@@ -0,0 +1,123 @@
+use config::configs::GameDB;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchKind {
+    Item,
+    Currency,
+    Equip,
+    Hero,
+    Skin,
+}
+
+impl MatchKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MatchKind::Item => "item",
+            MatchKind::Currency => "currency",
+            MatchKind::Equip => "equip",
+            MatchKind::Hero => "hero",
+            MatchKind::Skin => "skin",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchMatch {
+    pub kind: MatchKind,
+    pub id: i32,
+    pub name: String,
+    pub score: u32,
+}
+
+/// Fuzzy search item, currency, equip, character and skin names (best matches first)
+pub fn search(game_data: &GameDB, query: &str, limit: usize) -> Vec<SearchMatch> {
+    let query = normalize(query);
+    if query.is_empty() {
+        return Vec::new();
+    }
+
+    let mut matches = Vec::new();
+    let mut push = |kind: MatchKind, id: i32, names: &[&str]| {
+        let best = names
+            .iter()
+            .filter(|n| !n.is_empty())
+            .filter_map(|n| score(&query, &normalize(n)).map(|s| (s, *n)))
+            .max_by_key(|(s, _)| *s);
+
+        if let Some((score, name)) = best {
+            matches.push(SearchMatch {
+                kind,
+                id,
+                name: name.to_string(),
+                score,
+            });
+        }
+    };
+
+    for item in game_data.item.iter() {
+        push(MatchKind::Item, item.id, &[&item.name]);
+    }
+    for currency in game_data.currency.iter() {
+        push(MatchKind::Currency, currency.id, &[&currency.name]);
+    }
+    for equip in game_data.equip.iter() {
+        push(MatchKind::Equip, equip.id, &[&equip.name, &equip.name_en]);
+    }
+    for character in game_data.character.iter() {
+        push(
+            MatchKind::Hero,
+            character.id,
+            &[&character.name, &character.name_eng],
+        );
+    }
+    for skin in game_data.skin.iter() {
+        push(MatchKind::Skin, skin.id, &[&skin.name, &skin.name_eng]);
+    }
+
+    matches.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then(a.name.len().cmp(&b.name.len()))
+            .then(a.id.cmp(&b.id))
+    });
+    matches.truncate(limit);
+    matches
+}
+
+fn normalize(s: &str) -> String {
+    s.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// exact > prefix > word prefix > substring > subsequence, None when the query doesn't match
+fn score(query: &str, name: &str) -> Option<u32> {
+    if name == query {
+        return Some(1000);
+    }
+    if name.starts_with(query) {
+        return Some(800);
+    }
+    if let Some(pos) = name.find(query) {
+        let word_start = name[..pos].ends_with([' ', '-', '\'', '·', '(']);
+        return Some(if word_start { 700 } else { 600 });
+    }
+
+    // every query char appears in order, fewer gaps score higher
+    let mut gaps = 0u32;
+    let mut chars = name.chars();
+    for qc in query.chars().filter(|c| !c.is_whitespace()) {
+        let mut skipped = 0u32;
+        loop {
+            match chars.next() {
+                Some(c) if c == qc => break,
+                Some(_) => skipped += 1,
+                None => return None,
+            }
+        }
+        gaps += skipped;
+    }
+
+    Some(400u32.saturating_sub(gaps * 10).max(1))
+}
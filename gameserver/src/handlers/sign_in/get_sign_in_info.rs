@@ -3,6 +3,7 @@ use crate::network::packet::ClientPacket;
 use crate::state::ConnectionContext;
 use database::db::game::sign_in;
 use sonettobuf::{CmdId, GetSignInInfoReply};
+use sqlx::SqlitePool;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
@@ -10,14 +11,28 @@ pub async fn on_get_sign_in_info(
     ctx: Arc<Mutex<ConnectionContext>>,
     req: ClientPacket,
 ) -> Result<(), AppError> {
-    let (info, sign_in_days, addup_bonus, month_card_days, month_card_history, birthday_heroes) = {
+    let reply = {
         let conn = ctx.lock().await;
         let player_id = conn.player_id.ok_or(AppError::NotLoggedIn)?;
 
-        sign_in::get_sign_in_info(&conn.state.db, player_id).await?
+        build_sign_in_info_reply(&conn.state.db, player_id).await?
     };
 
-    let reply = GetSignInInfoReply {
+    let mut conn = ctx.lock().await;
+    conn.send_reply(CmdId::GetSignInInfoCmd, reply, 0, req.up_tag)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn build_sign_in_info_reply(
+    pool: &SqlitePool,
+    player_id: i64,
+) -> Result<GetSignInInfoReply, AppError> {
+    let (info, sign_in_days, addup_bonus, month_card_days, month_card_history, birthday_heroes) =
+        sign_in::get_sign_in_info(pool, player_id).await?;
+
+    Ok(GetSignInInfoReply {
         has_sign_in_days: sign_in_days,
         addup_sign_in_day: Some(info.addup_sign_in_day),
         has_get_addup_bonus: addup_bonus,
@@ -26,11 +41,5 @@ pub async fn on_get_sign_in_info(
         month_card_history: month_card_history.into_iter().map(Into::into).collect(),
         birthday_hero_ids: birthday_heroes,
         reward_mark: Some(info.reward_mark),
-    };
-
-    let mut conn = ctx.lock().await;
-    conn.send_reply(CmdId::GetSignInInfoCmd, reply, 0, req.up_tag)
-        .await?;
-
-    Ok(())
+    })
 }
@@ -4,7 +4,7 @@ mod sign_in_addup;
 mod sign_in_history;
 mod sign_in_total_reward_all;
 
-pub use get_sign_in_info::on_get_sign_in_info;
+pub use get_sign_in_info::{build_sign_in_info_reply, on_get_sign_in_info};
 pub use sign_in::on_sign_in;
 pub use sign_in_addup::on_sign_in_addup;
 pub use sign_in_history::on_sign_in_history;
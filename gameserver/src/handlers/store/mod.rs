@@ -3,5 +3,5 @@ mod get_store_infos;
 mod new_order;
 
 pub use buy_goods::on_buy_goods;
-pub use get_store_infos::on_get_store_infos;
+pub use get_store_infos::{build_store_infos, on_get_store_infos};
 pub use new_order::on_new_order;
@@ -1,6 +1,8 @@
 use crate::error::AppError;
 use crate::network::packet::ClientPacket;
 use crate::state::ConnectionContext;
+
+use super::progress::refresh_tower_seasons;
 use database::db::game::tower;
 use sonettobuf::{CmdId, GetTowerInfoReply};
 use std::sync::Arc;
@@ -14,6 +16,7 @@ pub async fn on_get_tower_info(
         let conn = ctx.lock().await;
         let player_id = conn.player_id.ok_or(AppError::NotLoggedIn)?;
 
+        refresh_tower_seasons(&conn.state.db, player_id).await?;
         tower::get_tower_info(&conn.state.db, player_id).await?
     };
 
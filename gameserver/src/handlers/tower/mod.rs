@@ -1,5 +1,9 @@
 mod get_tower_info;
+mod progress;
 mod start_tower_battle;
+mod tower_mop_up;
 
 pub use get_tower_info::on_get_tower_info;
+pub use progress::{TowerFight, settle_tower_battle};
 pub use start_tower_battle::on_start_tower_battle;
+pub use tower_mop_up::on_tower_mop_up;
@@ -0,0 +1,445 @@
+//! Tower progress
+//!
+//! | type | tower     | progress                                                             |
+//! |------|-----------|----------------------------------------------------------------------|
+//! | 1    | permanent | layers in order, a layer with episodes passes once all are cleared   |
+//! | 2    | boss      | layers in order while the boss is open                               |
+//! | 3    | limited   | every layer open while the season is, fights score by difficulty     |
+//!
+//! Boss and limited towers follow their `user_tower_opens` row. A waiting tower opens at
+//! `tower_start_time`, an open one moves on at `next_time`: a boss starts its next round,
+//! a limited season closes. Both clear the season progress, the heroes locked into
+//! episodes and the current layer scores, but keep pass layers and history scores.
+//!
+//! Limited towers score the boss fight out of [`DIFFICULTY_SCORES`], scaled by how much of
+//! the enemies' HP went down, so a loss still scores. The tower's high score is the sum of
+//! its layers' best. A fight is only settled once it has a result.
+
+use std::sync::Arc;
+
+use common::time::ServerTime;
+use database::db::game::tower::{
+    ensure_tower, get_tower, get_tower_layer, get_tower_opens, reset_tower_season,
+    set_tower_episode_heroes, update_tower_episode_status, update_tower_layer_score,
+    update_tower_open, update_tower_pass_layer, update_tower_total_score,
+};
+use database::models::game::tower::{HeroInfo, TowerOpen};
+use sonettobuf::{CmdId, Fight, FightGroup, LayerNo, TowerBattleFinishPush, TowerLayerUpdatePush};
+use sqlx::SqlitePool;
+use tokio::sync::Mutex;
+
+use crate::error::AppError;
+use crate::state::{ActiveBattle, ConnectionContext};
+
+pub const PERMANENT_TOWER: i32 = 1;
+pub const BOSS_TOWER: i32 = 2;
+pub const LIMITED_TOWER: i32 = 3;
+
+const STATUS_CLOSED: i32 = 0;
+const STATUS_WAITING: i32 = 1;
+const STATUS_OPEN: i32 = 2;
+
+const EPISODE_CLEARED: i32 = 1;
+
+/// Full score of a limited tower fight by difficulty, the last digit of the episode id.
+/// There is no tower table in the config, these are the full layer scores of the captured
+/// `tower/tower_info.json`
+const DIFFICULTY_SCORES: [i32; 3] = [10000, 20000, 40000];
+
+/// The tower fight an active battle belongs to
+#[derive(Debug, Clone, Copy)]
+pub struct TowerFight {
+    pub tower_type: i32,
+    pub tower_id: i32,
+    pub layer_id: i32,
+    pub episode_id: i32,
+    pub difficulty: i32,
+}
+
+impl TowerFight {
+    pub fn of(battle: &ActiveBattle) -> Option<Self> {
+        Some(Self {
+            tower_type: battle.tower_type?,
+            tower_id: battle.tower_id?,
+            layer_id: battle.layer_id?,
+            episode_id: battle.episode_id,
+            difficulty: battle.difficulty.unwrap_or(1),
+        })
+    }
+}
+
+/// Move the open status along, returns whether a new season started
+fn advance_season(open: &mut TowerOpen, now: i64) -> bool {
+    match open.status {
+        STATUS_WAITING if now >= open.tower_start_time => {
+            open.status = STATUS_OPEN;
+            if open.tower_type == BOSS_TOWER {
+                open.round += 1;
+                open.next_time = 0;
+            } else {
+                open.next_time = open.task_end_time;
+            }
+            true
+        }
+        STATUS_OPEN if open.next_time > 0 && now >= open.next_time => {
+            if open.tower_type == BOSS_TOWER {
+                open.round += 1;
+                open.tower_start_time = open.next_time;
+            } else {
+                open.status = STATUS_CLOSED;
+            }
+            open.next_time = 0;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Open, close and reset the player's towers that are due
+pub async fn refresh_tower_seasons(pool: &SqlitePool, user_id: i64) -> Result<(), AppError> {
+    let now = ServerTime::now_ms();
+
+    for mut open in get_tower_opens(pool, user_id).await? {
+        if !advance_season(&mut open, now) {
+            continue;
+        }
+
+        update_tower_open(pool, user_id, &open).await?;
+        ensure_tower(pool, user_id, open.tower_type, open.tower_id).await?;
+        reset_tower_season(pool, user_id, open.tower_type, open.tower_id).await?;
+
+        tracing::info!(
+            "Tower {}/{} moved to status {} round {} for user {}",
+            open.tower_type,
+            open.tower_id,
+            open.status,
+            open.round,
+            user_id
+        );
+    }
+
+    Ok(())
+}
+
+/// Whether the player may fight the layer right now
+pub async fn can_enter_layer(
+    pool: &SqlitePool,
+    user_id: i64,
+    tower_type: i32,
+    tower_id: i32,
+    layer_id: i32,
+) -> Result<bool, AppError> {
+    refresh_tower_seasons(pool, user_id).await?;
+
+    if tower_type != PERMANENT_TOWER {
+        let is_open = get_tower_opens(pool, user_id).await?.iter().any(|o| {
+            o.tower_type == tower_type && o.tower_id == tower_id && o.status == STATUS_OPEN
+        });
+        if !is_open {
+            return Ok(false);
+        }
+    }
+
+    if tower_type == LIMITED_TOWER {
+        return Ok(true);
+    }
+
+    let pass_layer_id = get_tower(pool, user_id, tower_type, tower_id)
+        .await?
+        .and_then(|t| t.pass_layer_id)
+        .unwrap_or(0);
+
+    Ok(layer_id <= pass_layer_id + 1)
+}
+
+/// Limited tower score, the difficulty's full score times the share of enemy HP taken down
+pub fn boss_score(fight: &Fight, difficulty: i32) -> i32 {
+    let full_score = DIFFICULTY_SCORES[(difficulty.clamp(1, 3) - 1) as usize] as i64;
+
+    let (hp, max_hp) = fight.defender.iter().flat_map(|t| t.entitys.iter()).fold(
+        (0i64, 0i64),
+        |(hp, max_hp), e| {
+            let entity_max = e.attr.as_ref().and_then(|a| a.hp).unwrap_or(0) as i64;
+            (
+                hp + (e.current_hp.unwrap_or(0) as i64).clamp(0, entity_max),
+                max_hp + entity_max,
+            )
+        },
+    );
+
+    if max_hp == 0 {
+        return 0;
+    }
+
+    (full_score * (max_hp - hp) / max_hp) as i32
+}
+
+/// Average level of the heroes that fought
+fn team_level(fight: &Fight) -> i32 {
+    let levels: Vec<i32> = fight
+        .attacker
+        .iter()
+        .flat_map(|t| t.entitys.iter())
+        .filter_map(|e| e.level)
+        .collect();
+
+    if levels.is_empty() {
+        return 0;
+    }
+
+    levels.iter().sum::<i32>() / levels.len() as i32
+}
+
+/// Heroes of the group as they fought, with the psychubes they wore
+fn fought_heroes(fight: &Fight, fight_group: &FightGroup) -> Vec<HeroInfo> {
+    fight
+        .attacker
+        .iter()
+        .flat_map(|t| t.entitys.iter())
+        .filter(|e| {
+            e.uid.is_some_and(|uid| {
+                fight_group.hero_list.contains(&uid) || fight_group.sub_hero_list.contains(&uid)
+            })
+        })
+        .map(|e| HeroInfo {
+            hero_id: e.model_id.unwrap_or(0),
+            equip_uids: e.equip_uid.filter(|&u| u != 0).into_iter().collect(),
+            trial_id: 0,
+        })
+        .collect()
+}
+
+/// Record a finished tower fight and push the result
+pub async fn settle_tower_battle(
+    ctx: Arc<Mutex<ConnectionContext>>,
+    pool: &SqlitePool,
+    user_id: i64,
+    tower: TowerFight,
+    fight: &Fight,
+    fight_result: i32,
+    fight_group: &FightGroup,
+) -> Result<(), AppError> {
+    let TowerFight {
+        tower_type,
+        tower_id,
+        layer_id,
+        episode_id,
+        difficulty,
+    } = tower;
+
+    if fight_result == 0 {
+        tracing::warn!(
+            "Tower fight {}/{} layer {} has no result yet, not settling",
+            tower_type,
+            tower_id,
+            layer_id
+        );
+        return Ok(());
+    }
+    let won = fight_result == 1;
+
+    ensure_tower(pool, user_id, tower_type, tower_id).await?;
+
+    let mut score = 0;
+    let mut layer_changed = false;
+
+    match tower_type {
+        LIMITED_TOWER => {
+            score = boss_score(fight, difficulty);
+            if score > 0 {
+                update_tower_episode_status(
+                    pool,
+                    user_id,
+                    tower_type,
+                    tower_id,
+                    layer_id,
+                    episode_id,
+                    EPISODE_CLEARED,
+                )
+                .await?;
+                set_tower_episode_heroes(
+                    pool,
+                    user_id,
+                    tower_type,
+                    tower_id,
+                    layer_id,
+                    episode_id,
+                    &fought_heroes(fight, fight_group),
+                )
+                .await?;
+                update_tower_layer_score(pool, user_id, tower_type, tower_id, layer_id, score)
+                    .await?;
+                update_tower_total_score(pool, user_id, tower_type, tower_id).await?;
+                layer_changed = true;
+            }
+        }
+        _ if won => {
+            // only the layers that come with episodes keep them, the rest just pass
+            let multi_episode = get_tower_layer(pool, user_id, tower_type, tower_id, layer_id)
+                .await?
+                .is_some_and(|l| !l.episode_n_os.is_empty());
+
+            let layer_passed = if multi_episode {
+                update_tower_episode_status(
+                    pool,
+                    user_id,
+                    tower_type,
+                    tower_id,
+                    layer_id,
+                    episode_id,
+                    EPISODE_CLEARED,
+                )
+                .await?;
+                set_tower_episode_heroes(
+                    pool,
+                    user_id,
+                    tower_type,
+                    tower_id,
+                    layer_id,
+                    episode_id,
+                    &fought_heroes(fight, fight_group),
+                )
+                .await?;
+                layer_changed = true;
+
+                get_tower_layer(pool, user_id, tower_type, tower_id, layer_id)
+                    .await?
+                    .is_some_and(|l| {
+                        l.episode_n_os
+                            .iter()
+                            .all(|e| e.status == Some(EPISODE_CLEARED))
+                    })
+            } else {
+                true
+            };
+
+            if layer_passed {
+                update_tower_pass_layer(pool, user_id, tower_type, tower_id, layer_id, 0).await?;
+            }
+        }
+        _ => {}
+    }
+
+    let tower_no = get_tower(pool, user_id, tower_type, tower_id)
+        .await?
+        .unwrap_or_default();
+    let layer = get_tower_layer(pool, user_id, tower_type, tower_id, layer_id)
+        .await?
+        .unwrap_or(LayerNo {
+            layer_id: Some(layer_id),
+            ..Default::default()
+        });
+
+    let boss_level = match tower_type {
+        LIMITED_TOWER => difficulty,
+        BOSS_TOWER => tower_no.pass_layer_id.unwrap_or(0),
+        _ => 0,
+    };
+
+    tracing::info!(
+        "Tower fight settled: type={}, tower={}, layer={}, episode={}, won={}, score={}, pass_layer={}",
+        tower_type,
+        tower_id,
+        layer_id,
+        episode_id,
+        won,
+        score,
+        tower_no.pass_layer_id.unwrap_or(0)
+    );
+
+    let finish_push = TowerBattleFinishPush {
+        r#type: Some(tower_type),
+        tower_id: Some(tower_id),
+        layer_id: Some(layer_id),
+        difficulty: Some(difficulty),
+        score: Some(score),
+        boss_level: Some(boss_level),
+        team_level: Some(team_level(fight)),
+        layer: Some(layer.clone()),
+        history_high_score: tower_no.history_high_score,
+        params: tower_no.params.clone(),
+    };
+
+    let mut conn = ctx.lock().await;
+    conn.notify(CmdId::TowerBattleFinishPushCmd, finish_push)
+        .await?;
+
+    if layer_changed {
+        conn.notify(
+            CmdId::TowerLayerUpdatePushCmd,
+            TowerLayerUpdatePush {
+                r#type: Some(tower_type),
+                tower_id: Some(tower_id),
+                layers: vec![layer],
+            },
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sonettobuf::{FightEntityInfo, FightTeam, HeroAttribute};
+
+    #[test]
+    fn difficulty_scores_match_captured_layers() {
+        let info: serde_json::Value = serde_json::from_str(include_str!(
+            "../../../../assets/static/tower/tower_info.json"
+        ))
+        .unwrap();
+        let mut reached = [false; 3];
+
+        let limited = info["towers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|t| t["type"] == LIMITED_TOWER);
+        for layer in limited.flat_map(|t| t["layerNOs"].as_array().unwrap()) {
+            let score = layer["currHighScore"].as_i64().unwrap() as i32;
+            // the episode id ends in its difficulty
+            let hardest = layer["episodeNOs"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .filter(|e| e["status"] == EPISODE_CLEARED)
+                .map(|e| (e["episodeId"].as_i64().unwrap() % 10) as usize - 1)
+                .max();
+
+            let Some(hardest) = hardest else {
+                assert_eq!(score, 0);
+                continue;
+            };
+            assert!(score <= DIFFICULTY_SCORES[hardest]);
+            reached[hardest] |= score == DIFFICULTY_SCORES[hardest];
+        }
+
+        assert_eq!(reached, [true; 3]);
+    }
+
+    #[test]
+    fn boss_score_scales_with_hp_taken() {
+        let enemy = |hp, max_hp| FightEntityInfo {
+            current_hp: Some(hp),
+            attr: Some(HeroAttribute {
+                hp: Some(max_hp),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let fight = |entitys| Fight {
+            defender: Some(FightTeam {
+                entitys,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        assert_eq!(boss_score(&fight(vec![enemy(0, 100)]), 3), 40000);
+        assert_eq!(boss_score(&fight(vec![enemy(25, 100)]), 2), 15000);
+        assert_eq!(boss_score(&fight(vec![enemy(100, 100)]), 1), 0);
+        assert_eq!(boss_score(&fight(vec![]), 1), 0);
+    }
+}
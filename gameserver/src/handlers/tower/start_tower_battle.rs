@@ -1,10 +1,11 @@
 use crate::error::AppError;
 use crate::network::packet::ClientPacket;
 use crate::state::{
-    ActiveBattle, BattleContext, ConnectionContext, create_battle, default_max_ap,
-    generate_initial_deck,
+    ActiveBattle, BattleContext, BattleStart, ConnectionContext, create_battle, default_max_ap,
+    generate_initial_deck, new_battle_seed, persist_battle, record_battle_start,
 };
 use config::configs;
+use database::db::game::battle::save_battle_seed;
 use prost::Message;
 use sonettobuf::{
     CmdId, DungeonUpdatePush, StartDungeonReply, StartTowerBattleReply, StartTowerBattleRequest,
@@ -13,6 +14,8 @@ use sonettobuf::{
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
+use super::progress::can_enter_layer;
+
 pub async fn on_start_tower_battle(
     ctx: Arc<Mutex<ConnectionContext>>,
     req: ClientPacket,
@@ -58,6 +61,16 @@ pub async fn on_start_tower_battle(
         )
     };
 
+    if !can_enter_layer(&pool, player_id, dungeon_type, tower_id, layer_id).await? {
+        tracing::warn!(
+            "Tower layer not open: type={}, tower={}, layer={}",
+            dungeon_type,
+            tower_id,
+            layer_id
+        );
+        return Err(AppError::InvalidRequest);
+    }
+
     let hero_count = fight_group.hero_list.iter().filter(|&&u| u != 0).count();
 
     let game_data = configs::get();
@@ -69,6 +82,7 @@ pub async fn on_start_tower_battle(
         .battle_id;
 
     let max_ap = default_max_ap(episode_id, hero_count);
+    let seed = new_battle_seed();
 
     let battle_ctx = BattleContext {
         player_id,
@@ -76,39 +90,62 @@ pub async fn on_start_tower_battle(
         episode_id,
         battle_id,
         max_ap,
+        seed,
     };
 
-    let card_push = generate_initial_deck(&pool, player_id, &fight_group, max_ap).await?;
+    let card_push = generate_initial_deck(&pool, player_id, &fight_group, max_ap, seed).await?;
 
     let card_deck = card_push.card_group.clone();
 
     let (modified_fight, initial_round, fight_data_mgr, ai_deck) =
         create_battle(&pool, battle_ctx, &fight_group, card_deck.clone()).await?;
 
-    {
-        let mut conn = ctx.lock().await;
-        conn.active_battle = Some(ActiveBattle {
-            tower_type: Some(dungeon_type),
-            tower_id: Some(tower_id),
-            layer_id: Some(layer_id),
-            episode_id,
-            chapter_id,
-            difficulty: Some(difficulty),
-            talent_plan_id: Some(talent_plan_id),
-            fight: Some(modified_fight.clone()),
-            current_round: 1,
-            act_point: max_ap,
-            power: 15,
-            current_deck: card_deck,
-            fight_group: Some(fight_group.clone()),
-            is_replay: None,
-            replay_episode_id: None,
-            fight_id: Some(chrono::Utc::now().timestamp_millis()),
-            multiplication: None,
-            ai_deck,
-            fight_data_mgr: Some(fight_data_mgr),
-        });
-    }
+    let fight_id = chrono::Utc::now().timestamp_millis();
+    save_battle_seed(&pool, player_id, episode_id, fight_id, seed).await?;
+
+    let start = BattleStart {
+        fight_data: fight_data_mgr.clone(),
+        deck: card_deck.clone(),
+        ai_deck: ai_deck.clone(),
+    };
+    record_battle_start(
+        &pool,
+        player_id,
+        fight_id,
+        episode_id,
+        chapter_id,
+        &fight_group,
+        &start,
+    )
+    .await?;
+
+    let battle = ActiveBattle {
+        tower_type: Some(dungeon_type),
+        tower_id: Some(tower_id),
+        layer_id: Some(layer_id),
+        episode_id,
+        chapter_id,
+        difficulty: Some(difficulty),
+        talent_plan_id: Some(talent_plan_id),
+        fight: Some(modified_fight.clone()),
+        last_round: Some(initial_round.clone()),
+        current_round: 1,
+        act_point: max_ap,
+        power: 15,
+        current_deck: card_deck,
+        fight_group: Some(fight_group.clone()),
+        is_replay: None,
+        replay_battle_id: None,
+        fight_id: Some(fight_id),
+        multiplication: None,
+        ai_deck,
+        cloth_opers: vec![],
+        fight_data_mgr: Some(fight_data_mgr),
+        seed,
+    };
+    persist_battle(&pool, player_id, &battle).await?;
+
+    ctx.lock().await.active_battle = Some(battle);
 
     {
         let mut conn = ctx.lock().await;
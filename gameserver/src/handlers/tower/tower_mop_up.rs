@@ -0,0 +1,49 @@
+use crate::error::AppError;
+use crate::network::packet::ClientPacket;
+use crate::state::ConnectionContext;
+use database::db::game::tower::add_tower_mop_up_times;
+use prost::Message;
+use sonettobuf::{CmdId, TowerMopUpReply, TowerMopUpRequest};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+pub async fn on_tower_mop_up(
+    ctx: Arc<Mutex<ConnectionContext>>,
+    req: ClientPacket,
+) -> Result<(), AppError> {
+    let request = TowerMopUpRequest::decode(&req.data[..])?;
+    let times = request.times.unwrap_or(1);
+
+    if times <= 0 {
+        return Err(AppError::InvalidRequest);
+    }
+
+    let (player_id, pool) = {
+        let conn = ctx.lock().await;
+        (
+            conn.player_id.ok_or(AppError::NotLoggedIn)?,
+            conn.state.db.clone(),
+        )
+    };
+
+    // counts reset with the other daily counters
+    let mop_up_times = add_tower_mop_up_times(&pool, player_id, times).await?;
+
+    tracing::info!(
+        "Tower mop-up: user={}, times={}, today={}",
+        player_id,
+        times,
+        mop_up_times
+    );
+
+    let reply = TowerMopUpReply {
+        times: Some(times),
+        mop_up_times: Some(mop_up_times),
+    };
+
+    let mut conn = ctx.lock().await;
+    conn.send_reply(CmdId::TowerMopUpCmd, reply, 0, req.up_tag)
+        .await?;
+
+    Ok(())
+}
@@ -1,6 +1,7 @@
 use crate::{
     network::client::handle_client,
-    state::{AppState, ConnectionContext},
+    state::{AppState, ConnectionContext, report_unknown_conditions},
+    util::reset::run_reset_scheduler,
 };
 use ::config::configs;
 use common::{config, excel_data_directory, game_port, host, init_config, init_tracing};
@@ -14,6 +15,7 @@ use tokio::net::TcpListener;
 use tokio::sync::Mutex;
 use tracing::info;
 
+mod cli;
 mod error;
 mod handlers;
 mod network;
@@ -56,8 +58,18 @@ async fn main() -> anyhow::Result<()> {
     info!("Loading game data...");
     configs::init(excel_data_directory().to_str().unwrap())?;
     info!("Game data loaded");
+    report_unknown_conditions();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if cli::run(&db, &args).await? {
+        return Ok(());
+    }
 
     let state = Arc::new(AppState::new(db));
+    tokio::spawn(run_reset_scheduler(state.clone()));
+    tokio::spawn(handlers::gm::run_console(state.clone()));
+    tokio::spawn(handlers::gm::run_api(state.clone()));
+
     let addr = format!("{}:{}", host(), game_port());
     let listener = TcpListener::bind(&addr).await?;
     info!("Listening on tcp://{}", &addr);
@@ -5,7 +5,31 @@ use std::sync::Arc;
 use tokio::{io::AsyncReadExt, sync::Mutex};
 
 pub async fn handle_client(ctx: Arc<Mutex<ConnectionContext>>) -> anyhow::Result<()> {
+    let (socket, flush_signal) = {
+        let conn = ctx.lock().await;
+        (conn.socket.clone(), conn.flush_signal.clone())
+    };
+
     loop {
+        // wait for the next request without holding the context lock,
+        // so server-initiated pushes (daily reset etc.) can be queued and flushed
+        let readable = tokio::select! {
+            res = async { socket.lock().await.readable().await } => res,
+            _ = flush_signal.notified() => {
+                let mut conn = ctx.lock().await;
+                if let Err(e) = conn.flush_send_queue().await {
+                    tracing::error!("Failed to flush send queue: {e}");
+                    break;
+                }
+                continue;
+            }
+        };
+
+        if let Err(e) = readable {
+            tracing::debug!("Client disconnected: {e}");
+            return Ok(());
+        }
+
         let packet = {
             let conn = ctx.lock().await;
             let mut socket = conn.socket.lock().await;
@@ -109,10 +109,12 @@ pub async fn dispatch_command(
         CmdId::ChangeHeroGroupSelectCmd => dungeon::on_change_hero_group_select,
         CmdId::DungeonEndDungeonCmd => dungeon::on_dungeon_end_dungeon,
         CmdId::ReconnectFightCmd => fight::on_reconnect_fight,
+        CmdId::UseClothSkillCmd => fight::on_use_cloth_skill,
 
         // === Tower ===
         CmdId::GetTowerInfoCmd => tower::on_get_tower_info,
         CmdId::StartTowerBattleCmd => tower::on_start_tower_battle,
+        CmdId::TowerMopUpCmd => tower::on_tower_mop_up,
 
         // === Exploration ===
         CmdId::GetExploreSimpleInfoCmd => explore::on_get_explore_simple_info,
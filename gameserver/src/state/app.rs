@@ -32,6 +32,13 @@ impl AppState {
         self.sessions.get(&player_id).map(|v| Arc::clone(v.value()))
     }
 
+    pub fn sessions(&self) -> Vec<(i64, Arc<Mutex<ConnectionContext>>)> {
+        self.sessions
+            .iter()
+            .map(|entry| (*entry.key(), Arc::clone(entry.value())))
+            .collect()
+    }
+
     pub fn register_session(&self, player_id: i64, ctx: Arc<Mutex<ConnectionContext>>) {
         self.sessions.insert(player_id, ctx);
     }
@@ -0,0 +1,184 @@
+//! Enemy AI
+//!
+//! Every living enemy plans one card per round from its `monster_skill_template`: the
+//! ultimate (`uniqueSkill`) once its moxie reaches `uniqueSkillPoint`, otherwise a card
+//! of its active skill groups, which take turns round by round. The planned cards go
+//! out with the round as the enemy intent and are played after the player's cards.
+//!
+//! Cards target living heroes that aren't `CantSelect`. A `Taunt` hero draws every card,
+//! otherwise the `logicTarget` of the skill picks the lowest HP or the highest attack
+//! hero, and a random one for anything else.
+
+use rand::Rng;
+use rand::rngs::StdRng;
+use sonettobuf::{CardInfo, FightEntityInfo};
+
+use crate::state::battle::manager::{buff_mgr::BuffMgr, calculate_mgr::FightCalculateDataMgr};
+
+const TAUNT: &str = "Taunt";
+const CANT_SELECT: &str = "CantSelect";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TargetRule {
+    Random,
+    LowestHp,
+    HighestAttack,
+}
+
+/// `logicTarget` values of `skill_effect` with a targeting rule
+const TARGET_RULES: &[(&str, TargetRule)] = &[
+    ("3", TargetRule::LowestHp),
+    ("4", TargetRule::HighestAttack),
+];
+
+impl TargetRule {
+    fn of_skill(skill_id: i32) -> Self {
+        let game_data = config::configs::get();
+
+        game_data
+            .skill_effect
+            .get(skill_id)
+            .and_then(|s| {
+                TARGET_RULES
+                    .iter()
+                    .find(|(logic, _)| *logic == s.logic_target)
+            })
+            .map(|(_, rule)| *rule)
+            .unwrap_or(Self::Random)
+    }
+}
+
+/// Cards the living enemies among `entities` will play in `round`
+pub fn plan_cards(
+    entities: &[&FightEntityInfo],
+    buff_mgr: &BuffMgr,
+    round: i32,
+    rng: &mut StdRng,
+) -> Vec<CardInfo> {
+    let mut enemies: Vec<&FightEntityInfo> = entities
+        .iter()
+        .copied()
+        .filter(|e| e.uid.unwrap_or(0) < 0 && e.current_hp.unwrap_or(0) > 0)
+        .collect();
+    enemies.sort_by_key(|e| (e.position.unwrap_or(0), -e.uid.unwrap_or(0)));
+
+    let mut cards = Vec::new();
+
+    for enemy in enemies {
+        let Some(skill_id) = choose_skill(enemy, round, rng) else {
+            continue;
+        };
+        let Some(target_uid) = pick_target(entities, buff_mgr, skill_id, rng) else {
+            continue;
+        };
+
+        cards.push(CardInfo {
+            uid: enemy.uid,
+            skill_id: Some(skill_id),
+            card_effect: Some(0),
+            temp_card: Some(false),
+            enchants: vec![],
+            card_type: Some(0),
+            hero_id: enemy.model_id,
+            status: Some(0),
+            target_uid: Some(target_uid),
+            extra_info: None,
+            energy: Some(0),
+            extra_infos: vec![],
+            area_red_or_blue: Some(0),
+            heat_id: Some(0),
+        });
+    }
+
+    cards
+}
+
+fn choose_skill(enemy: &FightEntityInfo, round: i32, rng: &mut StdRng) -> Option<i32> {
+    let game_data = config::configs::get();
+
+    let unique_skill_point = game_data
+        .monster
+        .get(enemy.model_id.unwrap_or(0))
+        .and_then(|m| game_data.monster_skill_template.get(m.skill_template))
+        .map(|t| t.unique_skill_point)
+        .unwrap_or(0);
+
+    let ex_skill = enemy.ex_skill.unwrap_or(0);
+    if ex_skill != 0 && unique_skill_point > 0 && enemy.ex_point.unwrap_or(0) >= unique_skill_point
+    {
+        return Some(ex_skill);
+    }
+
+    let groups: Vec<&Vec<i32>> = [&enemy.skill_group1, &enemy.skill_group2]
+        .into_iter()
+        .filter(|g| !g.is_empty())
+        .collect();
+    if groups.is_empty() {
+        return None;
+    }
+
+    let group = groups[(round - 1).rem_euclid(groups.len() as i32) as usize];
+    let skill_id = group[rng.gen_range(0..group.len())];
+
+    if game_data.skill_effect.get(skill_id).is_none() {
+        tracing::warn!("AI: skill {} has no skillEffect, skipping", skill_id);
+        return None;
+    }
+
+    Some(skill_id)
+}
+
+/// Heroes an enemy card may hit: alive, selectable and taunting if anyone taunts
+fn candidates(entities: &[&FightEntityInfo], buff_mgr: &BuffMgr) -> Vec<i64> {
+    let mut selectable: Vec<i64> = entities
+        .iter()
+        .filter(|e| e.uid.unwrap_or(0) > 0 && e.current_hp.unwrap_or(0) > 0)
+        .filter_map(|e| e.uid)
+        .filter(|&uid| !buff_mgr.has_feature(uid, CANT_SELECT))
+        .collect();
+    selectable.sort_unstable();
+
+    let taunting: Vec<i64> = selectable
+        .iter()
+        .copied()
+        .filter(|&uid| buff_mgr.has_feature(uid, TAUNT))
+        .collect();
+
+    if taunting.is_empty() {
+        selectable
+    } else {
+        taunting
+    }
+}
+
+/// Whether a planned target can still be hit
+pub fn is_valid_target(entities: &[&FightEntityInfo], buff_mgr: &BuffMgr, uid: i64) -> bool {
+    candidates(entities, buff_mgr).contains(&uid)
+}
+
+pub fn pick_target(
+    entities: &[&FightEntityInfo],
+    buff_mgr: &BuffMgr,
+    skill_id: i32,
+    rng: &mut StdRng,
+) -> Option<i64> {
+    let candidates = candidates(entities, buff_mgr);
+    if candidates.is_empty() {
+        return None;
+    }
+
+    let find = |uid: i64| entities.iter().find(|e| e.uid == Some(uid));
+
+    match TargetRule::of_skill(skill_id) {
+        TargetRule::Random => Some(candidates[rng.gen_range(0..candidates.len())]),
+        TargetRule::LowestHp => candidates
+            .iter()
+            .copied()
+            .min_by_key(|&uid| find(uid).and_then(|e| e.current_hp).unwrap_or(0)),
+        TargetRule::HighestAttack => candidates.iter().copied().max_by_key(|&uid| {
+            find(uid)
+                .map(|e| FightCalculateDataMgr::combat_attr(e, buff_mgr).attack)
+                .unwrap_or(0)
+        }),
+    }
+}
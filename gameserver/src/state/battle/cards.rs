@@ -1,15 +1,15 @@
 use crate::error::AppError;
+use crate::state::battle::ai;
+use crate::state::battle::manager::buff_mgr::BuffMgr;
+use crate::state::battle::rng::{RngStream, stream_rng};
+use crate::state::battle::uids::UidCounter;
 use config::configs;
 use database::models::game::heros::{HeroModel, UserHeroModel};
 use once_cell::sync::Lazy;
-use rand::thread_rng;
-use rand::{Rng, SeedableRng, rngs::StdRng, seq::SliceRandom};
-use sonettobuf::{CardInfo, CardInfoPush, Fight, FightGroup};
+use rand::{rngs::StdRng, seq::SliceRandom};
+use sonettobuf::{CardInfo, CardInfoPush, Fight, FightEntityInfo, FightGroup};
 use sqlx::SqlitePool;
 use std::collections::HashMap;
-use std::sync::atomic::{AtomicI64, Ordering};
-
-static CARD_UID: AtomicI64 = AtomicI64::new(1);
 
 // Core deck generation
 pub async fn generate_card_deck(
@@ -17,6 +17,7 @@ pub async fn generate_card_deck(
     user_id: i64,
     fight_group: &FightGroup,
     max_cards: usize,
+    seed: u64,
 ) -> Result<Vec<CardInfo>, AppError> {
     let active_heroes: Vec<i64> = fight_group
         .hero_list
@@ -26,7 +27,8 @@ pub async fn generate_card_deck(
         .collect();
 
     let candidates = build_candidate_pool(pool, user_id, &active_heroes).await?;
-    let deck = draw_cards_with_merge(candidates, max_cards);
+    let mut rng = stream_rng(seed, RngStream::PlayerDeck);
+    let deck = draw_cards_with_merge(&mut rng, candidates, max_cards);
 
     Ok(deck)
 }
@@ -37,11 +39,12 @@ pub async fn generate_initial_deck(
     user_id: i64,
     fight_group: &FightGroup,
     act_point: i32,
+    seed: u64,
 ) -> Result<CardInfoPush, AppError> {
     let hero_count = fight_group.hero_list.iter().filter(|&&u| u != 0).count();
     let max_cards = compute_max_cards(hero_count);
 
-    let deck = generate_card_deck(pool, user_id, fight_group, max_cards).await?;
+    let deck = generate_card_deck(pool, user_id, fight_group, max_cards, seed).await?;
 
     Ok(CardInfoPush {
         card_group: deck.clone(),
@@ -55,89 +58,33 @@ pub async fn generate_initial_deck(
 }
 
 pub async fn generate_ai_initial_deck(fight: &Fight, seed: u64) -> Vec<CardInfo> {
-    let mut rng = StdRng::seed_from_u64(seed);
-
-    let mut cards = Vec::new();
+    let mut rng = stream_rng(seed, RngStream::AiDeck);
 
-    let Some(attacker) = &fight.attacker else {
-        return cards;
-    };
-    let Some(defender) = &fight.defender else {
-        return cards;
-    };
-
-    let players: Vec<i64> = attacker
-        .entitys
-        .iter()
-        .filter_map(|e| {
-            let uid = e.uid.unwrap_or(0);
-            let hp = e.current_hp.unwrap_or(0);
-            if uid > 0 && hp > 0 { Some(uid) } else { None }
-        })
+    let entities: Vec<&FightEntityInfo> = [&fight.attacker, &fight.defender]
+        .into_iter()
+        .flatten()
+        .flat_map(|team| team.entitys.iter())
         .collect();
 
-    if players.is_empty() {
-        return cards;
-    }
-
-    for enemy in &defender.entitys {
-        let enemy_uid = enemy.uid.unwrap_or(0);
-        if enemy_uid >= 0 {
-            continue;
-        }
-        if enemy.current_hp.unwrap_or(0) <= 0 {
-            continue;
-        }
-
-        let skill_id = enemy.skill_group1.first().copied().unwrap_or(0);
-        if skill_id == 0 {
-            continue;
-        }
-
-        let target_uid = players[rng.gen_range(0..players.len())];
-
-        let game_data = config::configs::get();
-        let skill_effect_id = game_data
-            .skill
-            .iter()
-            .find(|s| s.id == skill_id)
-            .map(|s| s.skill_effect)
-            .unwrap_or(0);
-
-        if skill_effect_id == 0 {
-            tracing::warn!("AI: skill {} has no skillEffect, skipping", skill_id);
-            continue;
-        }
-
-        cards.push(CardInfo {
-            uid: Some(enemy_uid),
-            skill_id: Some(skill_id),
-            card_effect: Some(0),
-            temp_card: Some(false),
-            enchants: vec![],
-            card_type: Some(0),
-            hero_id: enemy.model_id,
-            status: Some(0),
-            target_uid: Some(target_uid),
-            extra_info: None,
-            energy: Some(0),
-            extra_infos: vec![],
-            area_red_or_blue: Some(0),
-            heat_id: Some(0),
-        });
-    }
-
-    cards
+    ai::plan_cards(
+        &entities,
+        &BuffMgr::new(),
+        fight.cur_round.unwrap_or(1),
+        &mut rng,
+    )
 }
 
 #[allow(dead_code)]
-fn draw_cards_no_merge(candidates: Vec<CardInfo>, max_cards: usize) -> Vec<CardInfo> {
-    let mut rng = thread_rng();
+fn draw_cards_no_merge(
+    rng: &mut StdRng,
+    candidates: Vec<CardInfo>,
+    max_cards: usize,
+) -> Vec<CardInfo> {
     let mut deck: Vec<CardInfo> = Vec::with_capacity(max_cards);
 
     for _ in 0..max_cards {
         let card = candidates
-            .choose(&mut rng)
+            .choose(rng)
             .expect("candidate pool empty")
             .clone();
         deck.push(card);
@@ -146,7 +93,7 @@ fn draw_cards_no_merge(candidates: Vec<CardInfo>, max_cards: usize) -> Vec<CardI
     deck
 }
 
-fn compute_max_cards(hero_count: usize) -> usize {
+pub fn compute_max_cards(hero_count: usize) -> usize {
     (hero_count * 3).min(9)
 }
 
@@ -172,6 +119,8 @@ async fn build_candidate_pool(
 ) -> Result<Vec<CardInfo>, AppError> {
     let mut pool_cards = Vec::new();
     let game_data = configs::get();
+    // the fight deals its own cards after the uids of the opening deck
+    let uids = UidCounter::default();
 
     let hero = UserHeroModel::new(user_id, pool.clone());
 
@@ -215,7 +164,7 @@ async fn build_candidate_pool(
 
         for skill_id in skills {
             pool_cards.push(CardInfo {
-                uid: Some(CARD_UID.fetch_add(1, Ordering::SeqCst)),
+                uid: Some(uids.next()),
                 hero_id: Some(hero_id),
                 skill_id: Some(skill_id),
                 card_type: Some(0),
@@ -236,13 +185,16 @@ async fn build_candidate_pool(
     Ok(pool_cards)
 }
 
-fn draw_cards_with_merge(candidates: Vec<CardInfo>, max_cards: usize) -> Vec<CardInfo> {
-    let mut rng = thread_rng();
+fn draw_cards_with_merge(
+    rng: &mut StdRng,
+    candidates: Vec<CardInfo>,
+    max_cards: usize,
+) -> Vec<CardInfo> {
     let mut deck: Vec<CardInfo> = Vec::with_capacity(max_cards);
 
     while deck.len() < max_cards {
         let card = candidates
-            .choose(&mut rng)
+            .choose(rng)
             .expect("candidate pool empty")
             .clone();
 
@@ -0,0 +1,321 @@
+//! Mind's Eye (cloth) skills
+//!
+//! The cloth of the fight group gives the player the `skill1`..`skill3` of its
+//! `cloth_level` row. Their state lives on the attacker team, so it is saved and replayed
+//! with the fight: `power` is what the player has to spend and every `skill_infos` entry
+//! carries the rounds left on its cooldown in `cd`.
+//! - Power starts at `initial` and is capped at `maxPower`. At the end of a round every
+//!   card played gives `use` power and every card moved `move`
+//! - Skill N costs `usePowerN` and then waits `cdN` rounds, every skill is ready at the
+//!   start of the fight
+//!
+//! What a skill does comes from the behaviors of its `skill_effect` row:
+//!
+//! | behavior                                       | effect                                   |
+//! |------------------------------------------------|------------------------------------------|
+//! | `Heal`, `HealCantCrit`                         | `param1` per mille of max HP             |
+//! | `AddCard`, `AddHandCard`, `DealCard1/2`        | draws `param1` cards past the hand size  |
+//! | `ReDealCard`                                   | redeals the incantations in the hand     |
+//!
+//! A heal goes to the hero the skill is aimed at, or to every living hero when it isn't
+//! aimed at one. Skills are used between rounds, each one rolls from its own stream.
+
+use anyhow::{Result, bail};
+use config::cloth_level::ClothLevel;
+use config::configs;
+use rand::rngs::StdRng;
+use sonettobuf::{
+    ActEffect, Fight, FightStep, PlayerSkillInfo, UseClothSkillOperRecord,
+    effect_type_enum::EffectType, fight_step,
+};
+
+use crate::state::battle::{manager::deck_mgr, round::RoundState, utils::VfxConfig};
+
+/// Power of a team without a cloth
+const DEFAULT_POWER: i32 = 15;
+
+#[derive(Debug, Clone, Copy)]
+enum ClothEffect {
+    /// Per mille of max HP
+    Heal(i32),
+    Draw(i32),
+    Redeal,
+}
+
+struct ClothSkill {
+    skill_id: i32,
+    cd: i32,
+    need_power: i32,
+}
+
+fn cloth_level(cloth_id: Option<i32>) -> Option<&'static ClothLevel> {
+    let cloth_id = cloth_id.unwrap_or(1);
+
+    configs::get()
+        .cloth_level
+        .iter()
+        .find(|c| c.id == cloth_id && c.level == 1)
+}
+
+fn cloth_skills(cloth: &ClothLevel) -> Vec<ClothSkill> {
+    [
+        (
+            cloth.skill1,
+            cloth.cd1,
+            cloth.use_power1.first().copied().unwrap_or(0),
+        ),
+        (
+            cloth.skill2,
+            cloth.cd2,
+            cloth.use_power2.first().copied().unwrap_or(0),
+        ),
+        (
+            cloth.skill3,
+            cloth.cd3,
+            cloth
+                .use_power3
+                .first()
+                .and_then(|v| v.as_i64())
+                .unwrap_or(0) as i32,
+        ),
+    ]
+    .into_iter()
+    .filter(|&(skill_id, _, _)| skill_id != 0)
+    .map(|(skill_id, cd, need_power)| ClothSkill {
+        skill_id,
+        cd,
+        need_power,
+    })
+    .collect()
+}
+
+/// Skills of the cloth as the fight starts with them
+pub fn player_skills(cloth_id: Option<i32>) -> Vec<PlayerSkillInfo> {
+    let Some(cloth) = cloth_level(cloth_id) else {
+        return vec![];
+    };
+
+    cloth_skills(cloth)
+        .into_iter()
+        .map(|skill| PlayerSkillInfo {
+            skill_id: Some(skill.skill_id),
+            cd: Some(0),
+            need_power: Some(skill.need_power),
+            r#type: Some(0),
+        })
+        .collect()
+}
+
+pub fn initial_power(cloth_id: Option<i32>) -> i32 {
+    cloth_level(cloth_id)
+        .map(|c| c.initial)
+        .unwrap_or(DEFAULT_POWER)
+}
+
+pub fn power(fight: &Fight) -> i32 {
+    fight.attacker.as_ref().and_then(|t| t.power).unwrap_or(0)
+}
+
+pub fn skill_infos(fight: &Fight) -> Vec<PlayerSkillInfo> {
+    fight
+        .attacker
+        .as_ref()
+        .map(|t| t.skill_infos.clone())
+        .unwrap_or_default()
+}
+
+/// Give the power the round's cards earned and tick the cooldowns down
+pub fn on_round_end(fight: &mut Fight, cards_used: usize, cards_moved: i32) {
+    let Some(team) = fight.attacker.as_mut() else {
+        return;
+    };
+
+    if let Some(cloth) = cloth_level(team.cloth_id) {
+        let gained = cards_used as i32 * cloth.r#use + cards_moved * cloth.r#move;
+        let mut power = team.power.unwrap_or(0) + gained;
+        if cloth.max_power > 0 {
+            power = power.min(cloth.max_power);
+        }
+        team.power = Some(power.max(0));
+    }
+
+    for skill in &mut team.skill_infos {
+        skill.cd = Some((skill.cd.unwrap_or(0) - 1).max(0));
+    }
+}
+
+fn skill_effects(skill_id: i32) -> Vec<ClothEffect> {
+    let game_data = configs::get();
+    let Some(skill) = game_data.skill_effect.get(skill_id) else {
+        return vec![];
+    };
+
+    [
+        &skill.behavior1,
+        &skill.behavior2,
+        &skill.behavior3,
+        &skill.behavior4,
+        &skill.behavior5,
+        &skill.behavior6,
+        &skill.behavior7,
+        &skill.behavior8,
+        &skill.behavior9,
+    ]
+    .into_iter()
+    .filter(|b| !b.is_empty())
+    .filter_map(|behavior| {
+        let mut parts = behavior.split('#').map(|p| p.parse::<i32>().unwrap_or(0));
+        let behavior_id = parts.next()?;
+        let param1 = parts.next().unwrap_or(0);
+
+        let behavior_type = game_data.skill_behavior.get(behavior_id)?.r#type.as_str();
+        let effect = match behavior_type {
+            "Heal" | "HealCantCrit" => ClothEffect::Heal(param1),
+            "AddCard" | "AddHandCard" | "DealCard1" | "DealCard2" => {
+                ClothEffect::Draw(param1.max(1))
+            }
+            "ReDealCard" => ClothEffect::Redeal,
+            other => {
+                tracing::warn!(
+                    "Cloth skill {} has unsupported behavior {}",
+                    skill_id,
+                    other
+                );
+                return None;
+            }
+        };
+        Some(effect)
+    })
+    .collect()
+}
+
+fn heal_effects(state: &RoundState, to_id: i64, rate: i32) -> Vec<ActEffect> {
+    let alive: Vec<i64> = state
+        .hero_uids
+        .iter()
+        .copied()
+        .filter(|uid| {
+            state
+                .get_entity(*uid)
+                .is_some_and(|e| e.current_hp.unwrap_or(0) > 0)
+        })
+        .collect();
+
+    let targets = if alive.contains(&to_id) {
+        vec![to_id]
+    } else {
+        alive
+    };
+
+    targets
+        .into_iter()
+        .filter_map(|uid| {
+            let entity = state.get_entity(uid)?;
+            let max_hp = entity.attr.as_ref().and_then(|a| a.hp).unwrap_or(0);
+            let heal = (max_hp as i64 * rate as i64 / 1000).max(1) as i32;
+
+            Some(ActEffect {
+                effect_type: Some(EffectType::Heal as i32),
+                target_id: Some(uid),
+                effect_num: Some(heal),
+                config_effect: Some(VfxConfig::Heal as i32),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// Use a cloth skill: pay for it, start its cooldown and play its effects on the hand.
+/// The heals in the returned step still have to be played on the fight.
+pub fn use_skill(
+    rng: &mut StdRng,
+    fight: &mut Fight,
+    state: &mut RoundState,
+    oper: &UseClothSkillOperRecord,
+) -> Result<FightStep> {
+    let skill_id = oper.skill_id.unwrap_or(0);
+    let to_id = oper.to_id.unwrap_or(0);
+
+    let Some(team) = fight.attacker.as_mut() else {
+        bail!("No attacker team");
+    };
+    let Some(cloth) = cloth_level(team.cloth_id) else {
+        bail!("Cloth {:?} not found", team.cloth_id);
+    };
+    let Some(skill) = cloth_skills(cloth)
+        .into_iter()
+        .find(|s| s.skill_id == skill_id)
+    else {
+        bail!("Skill {} isn't one of cloth {}", skill_id, cloth.id);
+    };
+
+    let power = team.power.unwrap_or(0);
+    if power < skill.need_power {
+        bail!(
+            "Skill {} needs {} power, {} left",
+            skill_id,
+            skill.need_power,
+            power
+        );
+    }
+
+    let Some(info) = team
+        .skill_infos
+        .iter_mut()
+        .find(|s| s.skill_id == Some(skill_id))
+    else {
+        bail!("Skill {} isn't in the fight", skill_id);
+    };
+    if info.cd.unwrap_or(0) > 0 {
+        bail!(
+            "Skill {} is on cooldown for {} rounds",
+            skill_id,
+            info.cd.unwrap_or(0)
+        );
+    }
+
+    let cloth_effects = skill_effects(skill_id);
+    if cloth_effects.is_empty() {
+        bail!("Skill {} does nothing the server knows", skill_id);
+    }
+
+    info.cd = Some(skill.cd);
+    team.power = Some(power - skill.need_power);
+    state.power = power - skill.need_power;
+
+    let mut effects = vec![ActEffect {
+        effect_type: Some(EffectType::Powerchange as i32),
+        target_id: Some(0),
+        effect_num: Some(-skill.need_power),
+        team_type: Some(1),
+        ..Default::default()
+    }];
+
+    for effect in cloth_effects {
+        match effect {
+            ClothEffect::Heal(rate) => effects.extend(heal_effects(state, to_id, rate)),
+            ClothEffect::Draw(count) => {
+                effects.extend(deck_mgr::draw_cards(rng, state, count as usize))
+            }
+            ClothEffect::Redeal => effects.extend(deck_mgr::redeal_hand(rng, state)),
+        }
+    }
+
+    tracing::info!(
+        "Cloth skill {} used on {}, {} power left",
+        skill_id,
+        to_id,
+        state.power
+    );
+
+    Ok(FightStep {
+        act_type: Some(fight_step::ActType::Skill.into()),
+        from_id: Some(0),
+        to_id: Some(to_id),
+        act_id: Some(skill_id),
+        act_effect: effects,
+        card_index: Some(0),
+        support_hero_id: Some(0),
+        fake_timeline: Some(false),
+    })
+}
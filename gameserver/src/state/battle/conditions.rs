@@ -0,0 +1,300 @@
+//! Skill conditions
+//!
+//! `conditionN` of a `skill_effect` row gates behavior slot N. A condition is one or more
+//! `id#param#param..` clauses, joined with `&` when all of them must hold or `|` when one
+//! is enough. Clauses check the units picked by `conditionTargetN`, and hold when every
+//! living unit there passes:
+//!
+//! | id          | params               | holds when                                  |
+//! |-------------|----------------------|---------------------------------------------|
+//! | 203, 208    |                      | trigger markers, see `PassiveTrigger`       |
+//! | 57210       | buff id              | carries the buff                            |
+//! | 19210       | buff id              | doesn't carry the buff                      |
+//!
+//! Only ids seen in the tables are mapped. [`report_unknown_conditions`] runs when the
+//! game data is loaded and lists the rest (HP, moxie, afflatus and target count checks
+//! among them), a condition that doesn't parse never holds.
+
+use std::collections::BTreeMap;
+
+use anyhow::{Result, bail};
+use sonettobuf::FightEntityInfo;
+
+use crate::state::battle::{manager::buff_mgr::BuffMgr, passives::engine::PassiveTrigger};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Clause {
+    Trigger(PassiveTrigger),
+    HasBuff(i32),
+    LacksBuff(i32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    clauses: Vec<Clause>,
+    /// `|` joined, one clause holding is enough
+    any: bool,
+}
+
+fn param(params: &[i32], index: usize, id: i32) -> Result<i32> {
+    params
+        .get(index)
+        .copied()
+        .ok_or_else(|| anyhow::anyhow!("condition {} is missing param {}", id, index + 1))
+}
+
+impl Clause {
+    pub fn parse(clause: &str) -> Result<Self> {
+        let mut parts = clause.split('#').map(str::trim);
+        let id: i32 = parts
+            .next()
+            .and_then(|id| id.parse().ok())
+            .ok_or_else(|| anyhow::anyhow!("bad condition clause '{}'", clause))?;
+        let params = parts
+            .map(|p| {
+                p.parse::<i32>()
+                    .map_err(|_| anyhow::anyhow!("bad param '{}' of condition {}", p, id))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if let Some(trigger) = PassiveTrigger::from_condition_id(id) {
+            return Ok(Self::Trigger(trigger));
+        }
+
+        Ok(match id {
+            57210 => Self::HasBuff(param(&params, 0, id)?),
+            19210 => Self::LacksBuff(param(&params, 0, id)?),
+            _ => bail!("unknown condition {}", id),
+        })
+    }
+
+    fn holds_for(&self, unit: &FightEntityInfo, buff_mgr: &BuffMgr) -> bool {
+        let uid = unit.uid.unwrap_or(0);
+
+        match self {
+            Self::Trigger(_) => true,
+            Self::HasBuff(buff_id) => buff_mgr.has_buff(uid, *buff_id),
+            Self::LacksBuff(buff_id) => !buff_mgr.has_buff(uid, *buff_id),
+        }
+    }
+
+    fn holds(&self, units: &[&FightEntityInfo], buff_mgr: &BuffMgr) -> bool {
+        // nothing to check on a side with nobody left standing
+        if units.is_empty() {
+            return matches!(self, Self::Trigger(_) | Self::LacksBuff(_));
+        }
+
+        units.iter().all(|u| self.holds_for(u, buff_mgr))
+    }
+}
+
+impl Condition {
+    pub fn parse(condition: &str) -> Result<Self> {
+        let condition = condition.trim();
+        if condition.is_empty() {
+            return Ok(Self {
+                clauses: vec![],
+                any: false,
+            });
+        }
+
+        let any = condition.contains('|');
+        if any && condition.contains('&') {
+            bail!("condition '{}' mixes '&' and '|'", condition);
+        }
+
+        let clauses = condition
+            .split(['&', '|'])
+            .map(Clause::parse)
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { clauses, any })
+    }
+
+    /// Trigger point the condition names, if any
+    pub fn trigger(&self) -> Option<PassiveTrigger> {
+        self.clauses.iter().find_map(|c| match c {
+            Clause::Trigger(trigger) => Some(*trigger),
+            _ => None,
+        })
+    }
+
+    /// Whether there's anything to check beyond when it fires
+    pub fn is_trigger_only(&self) -> bool {
+        self.clauses.iter().all(|c| matches!(c, Clause::Trigger(_)))
+    }
+
+    /// `units` are the living units the condition target picked
+    pub fn holds(&self, units: &[&FightEntityInfo], buff_mgr: &BuffMgr) -> bool {
+        if self.clauses.is_empty() {
+            return true;
+        }
+
+        if self.any {
+            self.clauses.iter().any(|c| c.holds(units, buff_mgr))
+        } else {
+            self.clauses.iter().all(|c| c.holds(units, buff_mgr))
+        }
+    }
+}
+
+/// Log every `skill_effect` condition that doesn't parse, returns how many there were
+pub fn report_unknown_conditions() -> usize {
+    let game_data = config::configs::get();
+    let mut unknown: BTreeMap<String, Vec<i32>> = BTreeMap::new();
+
+    for skill in game_data.skill_effect.iter() {
+        for condition in [
+            &skill.condition1,
+            &skill.condition2,
+            &skill.condition3,
+            &skill.condition4,
+            &skill.condition5,
+            &skill.condition6,
+            &skill.condition7,
+            &skill.condition8,
+            &skill.condition9,
+            &skill.condition10,
+            &skill.condition11,
+            &skill.condition12,
+            &skill.condition13,
+            &skill.condition14,
+            &skill.condition15,
+            &skill.condition16,
+            &skill.condition17,
+            &skill.condition18,
+            &skill.condition19,
+            &skill.condition20,
+        ] {
+            if let Err(e) = Condition::parse(condition) {
+                let skills = unknown.entry(e.to_string()).or_default();
+                if !skills.contains(&skill.id) {
+                    skills.push(skill.id);
+                }
+            }
+        }
+    }
+
+    for (error, skills) in &unknown {
+        tracing::warn!(
+            "Skill condition: {} ({} skills, e.g. {:?})",
+            error,
+            skills.len(),
+            &skills[..skills.len().min(5)]
+        );
+    }
+
+    unknown.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::battle::manager::buff_mgr::BuffInstance;
+
+    const BUFF: i32 = 4001;
+
+    fn unit(uid: i64) -> FightEntityInfo {
+        FightEntityInfo {
+            uid: Some(uid),
+            current_hp: Some(100),
+            ..Default::default()
+        }
+    }
+
+    /// Unit 1 carries the buff, unit 2 doesn't
+    fn buffs() -> BuffMgr {
+        let buff = BuffInstance {
+            uid: 1,
+            buff_id: BUFF,
+            layer: 1,
+            ..Default::default()
+        };
+        serde_json::from_value(serde_json::json!({
+            "active": { "1": [buff] },
+            "base_modifiers": {},
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn parses_known_clauses() {
+        assert_eq!(
+            Clause::parse("203").unwrap(),
+            Clause::Trigger(PassiveTrigger::BattleStart)
+        );
+        assert_eq!(
+            Clause::parse("208").unwrap(),
+            Clause::Trigger(PassiveTrigger::RoundStart)
+        );
+        assert_eq!(Clause::parse("57210#4001").unwrap(), Clause::HasBuff(BUFF));
+        assert_eq!(
+            Clause::parse(" 19210 # 4001 ").unwrap(),
+            Clause::LacksBuff(BUFF)
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_and_malformed_clauses() {
+        assert!(Clause::parse("10101#500").is_err());
+        assert!(Clause::parse("57210").is_err());
+        assert!(Clause::parse("57210#x").is_err());
+        assert!(Clause::parse("").is_err());
+        assert!(Condition::parse("57210#4001&99999").is_err());
+        assert!(Condition::parse("57210#4001&19210#4001|208").is_err());
+    }
+
+    #[test]
+    fn empty_and_trigger_only() {
+        let empty = Condition::parse("  ").unwrap();
+        assert!(empty.holds(&[], &BuffMgr::default()));
+        assert_eq!(empty.trigger(), None);
+
+        let trigger = Condition::parse("208").unwrap();
+        assert!(trigger.is_trigger_only());
+        assert_eq!(trigger.trigger(), Some(PassiveTrigger::RoundStart));
+
+        let gated = Condition::parse("208&57210#4001").unwrap();
+        assert!(!gated.is_trigger_only());
+        assert_eq!(gated.trigger(), Some(PassiveTrigger::RoundStart));
+    }
+
+    #[test]
+    fn and_needs_every_clause() {
+        let buff_mgr = buffs();
+        let (carrier, other) = (unit(1), unit(2));
+        let condition = Condition::parse("203&57210#4001").unwrap();
+
+        assert!(condition.holds(&[&carrier], &buff_mgr));
+        assert!(!condition.holds(&[&other], &buff_mgr));
+        // every picked unit has to pass
+        assert!(!condition.holds(&[&carrier, &other], &buff_mgr));
+    }
+
+    #[test]
+    fn or_needs_one_clause() {
+        let buff_mgr = buffs();
+        let (carrier, other) = (unit(1), unit(2));
+        let condition = Condition::parse("57210#4001|19210#4001").unwrap();
+
+        assert!(condition.holds(&[&carrier], &buff_mgr));
+        assert!(condition.holds(&[&other], &buff_mgr));
+        assert!(!condition.holds(&[&carrier, &other], &buff_mgr));
+    }
+
+    #[test]
+    fn nobody_picked() {
+        let buff_mgr = buffs();
+
+        assert!(
+            !Condition::parse("57210#4001")
+                .unwrap()
+                .holds(&[], &buff_mgr)
+        );
+        assert!(
+            Condition::parse("19210#4001")
+                .unwrap()
+                .holds(&[], &buff_mgr)
+        );
+    }
+}
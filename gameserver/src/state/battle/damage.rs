@@ -0,0 +1,444 @@
+//! Damage formula
+//!
+//! All rates are per mille and the math is done in integers so a replayed fight
+//! deals the exact same numbers. A hit goes through these steps in order:
+//!
+//! 1. Defense: reality damage (`dmgType` 1) is reduced by `defense`, mental damage
+//!    (`dmgType` 2) by `mdefense`. Penetration (`defense_ignore`) ignores part of it.
+//! 2. Base damage is `attack - defense`, scaled by the skill rate from `skill_effect`
+//!    plus the [`RateUp`] the caster's passives give: every card skill, incantations of
+//!    one rank, and card skills per moxie point the caster holds when casting.
+//! 3. Might: incantations add the caster's `normal_skill_rate`, ultimates its
+//!    `big_skill_rate`.
+//! 4. Damage bonus of the attacker against damage reduction of the target.
+//! 5. Afflatus: an advantaged hit deals 130%, a disadvantaged one 80%. The advantage
+//!    rate is pinned by the round captured in `assets/static/dungeon`, see the tests.
+//! 6. Crit: the chance is `cri - recri`, a crit deals `cri_dmg - cri_def` but never
+//!    less than a normal hit. Only hits with a crit roll can crit, passive damage has
+//!    none.
+
+use sonettobuf::{FightEntityInfo, HeroExAttribute};
+
+use crate::state::battle::manager::calculate_mgr::CombatAttr;
+
+pub const DMG_TYPE_REALITY: i32 = 1;
+pub const DMG_TYPE_MENTAL: i32 = 2;
+
+const CAREER_STAR: i32 = 1;
+const CAREER_PLANT: i32 = 2;
+const CAREER_BEAST: i32 = 3;
+const CAREER_MINERAL: i32 = 4;
+const CAREER_SPIRIT: i32 = 5;
+const CAREER_INTELLECT: i32 = 6;
+
+/// (attacker, target) pairs where the attacker has the afflatus advantage
+const CAREER_ADVANTAGE: &[(i32, i32)] = &[
+    (CAREER_STAR, CAREER_MINERAL),
+    (CAREER_MINERAL, CAREER_BEAST),
+    (CAREER_BEAST, CAREER_PLANT),
+    (CAREER_PLANT, CAREER_STAR),
+    (CAREER_SPIRIT, CAREER_INTELLECT),
+    (CAREER_INTELLECT, CAREER_SPIRIT),
+];
+
+const ADVANTAGE_RATE: i64 = 1300;
+const DISADVANTAGE_RATE: i64 = 800;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkillKind {
+    /// Card skill of rank 1, 2 or 3
+    Incantation(i32),
+    Ultimate,
+    Passive,
+}
+
+impl SkillKind {
+    pub fn of(caster: &FightEntityInfo, skill_id: i32) -> Self {
+        if caster.ex_skill == Some(skill_id) {
+            return Self::Ultimate;
+        }
+
+        [&caster.skill_group1, &caster.skill_group2]
+            .into_iter()
+            .find_map(|group| group.iter().position(|&id| id == skill_id))
+            .map(|idx| Self::Incantation(idx as i32 + 1))
+            .unwrap_or(Self::Passive)
+    }
+}
+
+/// Per mille skill rate the caster's passives add to its card skills, from the
+/// `SkillRateUp` (every card skill), `SkillRateUp1`/`SkillRateUp2` (incantations of
+/// rank 1/2) and `SkillRateUpExPoint` (per moxie point) behaviors
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RateUp {
+    pub skill: i32,
+    pub rank1: i32,
+    pub rank2: i32,
+    pub per_moxie: i32,
+}
+
+impl RateUp {
+    pub fn add(&mut self, behavior_type: &str, value: i32) {
+        match behavior_type {
+            "SkillRateUp" => self.skill += value,
+            "SkillRateUp1" => self.rank1 += value,
+            "SkillRateUp2" => self.rank2 += value,
+            "SkillRateUpExPoint" => self.per_moxie += value,
+            _ => {}
+        }
+    }
+
+    /// Rate added to a `kind` skill cast with `moxie` points
+    pub fn total(&self, kind: SkillKind, moxie: i32) -> i32 {
+        let rank = match kind {
+            SkillKind::Incantation(1) => self.rank1,
+            SkillKind::Incantation(2) => self.rank2,
+            SkillKind::Incantation(_) | SkillKind::Ultimate => 0,
+            SkillKind::Passive => return 0,
+        };
+
+        self.skill + rank + self.per_moxie * moxie.max(0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Restraint {
+    Advantage,
+    Neutral,
+    Disadvantage,
+}
+
+impl Restraint {
+    pub fn of(attacker_career: i32, target_career: i32) -> Self {
+        if CAREER_ADVANTAGE.contains(&(attacker_career, target_career)) {
+            Self::Advantage
+        } else if CAREER_ADVANTAGE.contains(&(target_career, attacker_career)) {
+            Self::Disadvantage
+        } else {
+            Self::Neutral
+        }
+    }
+
+    fn rate(self) -> i64 {
+        match self {
+            Self::Advantage => ADVANTAGE_RATE,
+            Self::Neutral => 1000,
+            Self::Disadvantage => DISADVANTAGE_RATE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DamageInput {
+    pub attacker: CombatAttr,
+    pub target: CombatAttr,
+    pub attacker_career: i32,
+    pub target_career: i32,
+    pub dmg_type: i32,
+    /// Skill rate per mille
+    pub rate: i32,
+    pub rate_up: RateUp,
+    /// Moxie of the attacker when casting
+    pub moxie: i32,
+    pub kind: SkillKind,
+    /// Roll in `0..1000` deciding the crit, `None` for hits that can't crit
+    pub crit_roll: Option<i32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DamageResult {
+    pub damage: i32,
+    pub critical: bool,
+    pub restraint: Restraint,
+}
+
+pub fn compute(input: &DamageInput) -> DamageResult {
+    let atk = &input.attacker;
+    let def = &input.target;
+
+    let defense = if input.dmg_type == DMG_TYPE_MENTAL {
+        def.mdefense
+    } else {
+        def.defense
+    } as i64;
+    let defense = defense * (1000 - atk.defense_ignore as i64).clamp(0, 1000) / 1000;
+
+    let attack = atk.attack as i64;
+    let mut damage = (attack - defense).max(0);
+
+    let rate = input.rate + input.rate_up.total(input.kind, input.moxie);
+    damage = damage * rate.max(0) as i64 / 1000;
+
+    let might = match input.kind {
+        SkillKind::Incantation(_) => atk.normal_skill_rate,
+        SkillKind::Ultimate => atk.big_skill_rate,
+        SkillKind::Passive => 0,
+    } as i64;
+    damage = damage * (1000 + might).max(0) / 1000;
+
+    let bonus = (1000 + atk.add_dmg as i64 - def.drop_dmg as i64).max(0);
+    damage = damage * bonus / 1000;
+
+    let restraint = Restraint::of(input.attacker_career, input.target_career);
+    damage = damage * restraint.rate() / 1000;
+
+    let crit_chance = (atk.cri - def.recri).clamp(0, 1000);
+    let critical = input.crit_roll.is_some_and(|roll| roll < crit_chance);
+    if critical {
+        let crit_rate = (atk.cri_dmg as i64 - def.cri_def as i64).max(1000);
+        damage = damage * crit_rate / 1000;
+    }
+
+    DamageResult {
+        damage: damage.clamp(1, i32::MAX as i64) as i32,
+        critical,
+        restraint,
+    }
+}
+
+/// Reality or mental, from `character` for heroes and `monster_skill_template` for enemies
+pub fn dmg_type(entity: &FightEntityInfo) -> i32 {
+    let game_data = config::configs::get();
+    let model_id = entity.model_id.unwrap_or(0);
+
+    let dmg_type = if entity.entity_type == Some(2) {
+        game_data
+            .monster
+            .get(model_id)
+            .and_then(|m| game_data.monster_skill_template.get(m.skill_template))
+            .map(|t| t.dmg_type)
+    } else {
+        game_data.character.get(model_id).map(|c| c.dmg_type)
+    };
+
+    dmg_type.unwrap_or(DMG_TYPE_REALITY)
+}
+
+/// Crit and damage attributes of `entity` at its level, before buffs
+pub fn base_ex_attr(entity: &FightEntityInfo) -> HeroExAttribute {
+    let game_data = config::configs::get();
+    let model_id = entity.model_id.unwrap_or(0);
+    let level = entity.level.unwrap_or(1);
+
+    if entity.entity_type == Some(2) {
+        let Some(monster) = game_data.monster.get(model_id) else {
+            return HeroExAttribute::default();
+        };
+        let template_id = if monster.template != 0 {
+            monster.template
+        } else {
+            monster.skill_template
+        };
+
+        return game_data
+            .monster_template
+            .iter()
+            .find(|t| t.template == template_id)
+            .map(|t| HeroExAttribute {
+                cri: Some(t.cri + t.cri_grow * level),
+                recri: Some(t.recri + t.recri_grow * level),
+                cri_dmg: Some(t.cri_dmg + t.cri_dmg_grow * level),
+                cri_def: Some(t.cri_def + t.cri_def_grow * level),
+                add_dmg: Some(t.add_dmg + t.add_dmg_grow * level),
+                drop_dmg: Some(t.drop_dmg + t.drop_dmg_grow * level),
+            })
+            .unwrap_or_default();
+    }
+
+    game_data
+        .character_level
+        .iter()
+        .find(|l| l.hero_id == model_id && l.level == level)
+        .map(|l| HeroExAttribute {
+            cri: Some(l.cri),
+            recri: Some(l.recri),
+            cri_dmg: Some(l.cri_dmg),
+            cri_def: Some(l.cri_def),
+            add_dmg: Some(l.add_dmg),
+            drop_dmg: Some(l.drop_dmg),
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hero 3086 (Intellect, 1668 attack, reality damage) in the captured round
+    fn captured_attacker() -> CombatAttr {
+        CombatAttr {
+            attack: 1668,
+            cri: 1000,
+            cri_dmg: 1535,
+            ..Default::default()
+        }
+    }
+
+    fn hit(attacker: CombatAttr, target: CombatAttr, target_career: i32) -> DamageInput {
+        DamageInput {
+            attacker,
+            target,
+            attacker_career: CAREER_INTELLECT,
+            target_career,
+            dmg_type: DMG_TYPE_REALITY,
+            rate: 2935,
+            rate_up: RateUp::default(),
+            moxie: 0,
+            kind: SkillKind::Incantation(2),
+            crit_roll: Some(0),
+        }
+    }
+
+    #[test]
+    fn advantage_matches_captured_round() {
+        // card 30860121 crit -1 (Spirit, 471 defense) for 7008 and -2 (Intellect, 436
+        // defense) for 5549 in assets/static/dungeon/begin_round.json. The card's rate
+        // and crit damage aren't in the capture, they are picked to give the neutral hit.
+        let spirit = CombatAttr {
+            defense: 471,
+            mdefense: 436,
+            ..Default::default()
+        };
+        let intellect = CombatAttr {
+            defense: 436,
+            mdefense: 471,
+            ..Default::default()
+        };
+
+        let neutral = compute(&hit(captured_attacker(), intellect, CAREER_INTELLECT));
+        assert_eq!(neutral.restraint, Restraint::Neutral);
+        assert_eq!(neutral.damage, 5549);
+
+        let advantaged = compute(&hit(captured_attacker(), spirit, CAREER_SPIRIT));
+        assert_eq!(advantaged.restraint, Restraint::Advantage);
+        assert!(advantaged.critical);
+        assert_eq!(advantaged.damage, 7008);
+    }
+
+    #[test]
+    fn afflatus_cycle() {
+        for (attacker, target) in [
+            (CAREER_STAR, CAREER_MINERAL),
+            (CAREER_MINERAL, CAREER_BEAST),
+            (CAREER_BEAST, CAREER_PLANT),
+            (CAREER_PLANT, CAREER_STAR),
+            (CAREER_SPIRIT, CAREER_INTELLECT),
+            (CAREER_INTELLECT, CAREER_SPIRIT),
+        ] {
+            assert_eq!(Restraint::of(attacker, target), Restraint::Advantage);
+        }
+
+        assert_eq!(
+            Restraint::of(CAREER_MINERAL, CAREER_STAR),
+            Restraint::Disadvantage
+        );
+        assert_eq!(Restraint::of(CAREER_STAR, CAREER_BEAST), Restraint::Neutral);
+        assert_eq!(
+            Restraint::of(CAREER_STAR, CAREER_SPIRIT),
+            Restraint::Neutral
+        );
+    }
+
+    #[test]
+    fn mental_damage_uses_mdefense() {
+        let target = CombatAttr {
+            defense: 600,
+            mdefense: 200,
+            ..Default::default()
+        };
+        let mut input = hit(captured_attacker(), target, CAREER_INTELLECT);
+        input.crit_roll = None;
+        input.rate = 1000;
+
+        input.dmg_type = DMG_TYPE_REALITY;
+        assert_eq!(compute(&input).damage, 1668 - 600);
+
+        input.dmg_type = DMG_TYPE_MENTAL;
+        assert_eq!(compute(&input).damage, 1668 - 200);
+    }
+
+    #[test]
+    fn penetration_ignores_defense() {
+        let attacker = CombatAttr {
+            defense_ignore: 500,
+            ..captured_attacker()
+        };
+        let target = CombatAttr {
+            defense: 600,
+            ..Default::default()
+        };
+        let mut input = hit(attacker, target, CAREER_INTELLECT);
+        input.crit_roll = None;
+        input.rate = 1000;
+
+        assert_eq!(compute(&input).damage, 1668 - 300);
+    }
+
+    #[test]
+    fn crit_needs_a_roll_and_never_lowers_damage() {
+        let target = CombatAttr::default();
+        let mut input = hit(captured_attacker(), target, CAREER_INTELLECT);
+        input.rate = 1000;
+
+        input.crit_roll = None;
+        let normal = compute(&input);
+        assert!(!normal.critical);
+
+        input.crit_roll = Some(999);
+        assert_eq!(compute(&input).damage, normal.damage * 1535 / 1000);
+
+        input.attacker.cri_dmg = 800;
+        let weak = compute(&input);
+        assert!(weak.critical);
+        assert_eq!(weak.damage, normal.damage);
+    }
+
+    #[test]
+    fn might_and_rate_up_by_rank_and_moxie() {
+        let rate_up = RateUp {
+            skill: 100,
+            rank1: 200,
+            rank2: 300,
+            per_moxie: 50,
+        };
+
+        assert_eq!(rate_up.total(SkillKind::Incantation(1), 0), 300);
+        assert_eq!(rate_up.total(SkillKind::Incantation(2), 2), 500);
+        assert_eq!(rate_up.total(SkillKind::Incantation(3), 0), 100);
+        assert_eq!(rate_up.total(SkillKind::Ultimate, 5), 350);
+        assert_eq!(rate_up.total(SkillKind::Passive, 5), 0);
+
+        let attacker = CombatAttr {
+            normal_skill_rate: 500,
+            ..captured_attacker()
+        };
+        let mut input = hit(attacker, CombatAttr::default(), CAREER_INTELLECT);
+        input.crit_roll = None;
+        input.rate = 1000;
+        input.rate_up = rate_up;
+        input.kind = SkillKind::Incantation(1);
+        input.moxie = 2;
+
+        // (1000 + 100 + 200 + 2 * 50) rate, then 150% might
+        assert_eq!(compute(&input).damage, 1668 * 1400 / 1000 * 1500 / 1000);
+    }
+
+    #[test]
+    fn rate_up_from_behaviors() {
+        let mut rate_up = RateUp::default();
+        rate_up.add("SkillRateUp", 100);
+        rate_up.add("SkillRateUp2", 300);
+        rate_up.add("SkillRateUpExPoint", 50);
+        rate_up.add("AttrFix", 999);
+
+        assert_eq!(
+            rate_up,
+            RateUp {
+                skill: 100,
+                rank1: 0,
+                rank2: 300,
+                per_moxie: 50,
+            }
+        );
+    }
+}
@@ -7,6 +7,9 @@ use sonettobuf::{EquipRecord, FightEntityInfo, HeroAttribute};
 use sqlx::SqlitePool;
 use std::collections::HashMap;
 
+use super::passives::equipment;
+use super::resonance::{self, Resonance};
+
 pub async fn build_hero_entity(
     pool: &SqlitePool,
     hero_data: &HeroData,
@@ -19,8 +22,6 @@ pub async fn build_hero_entity(
     let equip_model = UserEquipmentModel::new(record.user_id, pool.clone());
     let equip_data = equip_model.get_equip(record.default_equip_uid).await.ok();
 
-    let equip_id = equip_data.as_ref().map(|equip| equip.equip_id);
-
     let game = configs::get();
     let hero_type = game
         .character
@@ -57,7 +58,7 @@ pub async fn build_hero_entity(
             )
         };
 
-    let passives = resolve_passives(hero_data, equip_id, destiny_exchange.as_ref());
+    let passives = resolve_passives(hero_data, equip_data.as_ref(), destiny_exchange.as_ref());
 
     if let Some(map) = &destiny_exchange {
         apply_destiny_exchange(&mut skill_group1, map);
@@ -133,7 +134,14 @@ fn build_attr(r: &HeroData, equip: Option<&Equipment>) -> HeroAttribute {
     let mut atk = ((r.record.base_attack as f32) * 1.0786).round() as i32;
     let mut def = ((r.record.base_defense as f32) * 1.0942857).round() as i32;
     let mut mdef = ((r.record.base_mdefense as f32) * 1.0942857).round() as i32;
-    let technic = ((r.record.base_technic as f32) * 1.395604).round() as i32;
+    let mut technic = ((r.record.base_technic as f32) * 1.395604).round() as i32;
+
+    let cubes = resonance::resonance(r);
+    hp = Resonance::scale(hp, cubes.hp);
+    atk = Resonance::scale(atk, cubes.attack);
+    def = Resonance::scale(def, cubes.defense);
+    mdef = Resonance::scale(mdef, cubes.mdefense);
+    technic = Resonance::scale(technic, cubes.technic);
 
     if let Some(equip) = equip {
         let game_data = configs::get();
@@ -148,6 +156,12 @@ fn build_attr(r: &HeroData, equip: Option<&Equipment>) -> HeroAttribute {
             def += strengthen.def;
             mdef += strengthen.mdef;
         }
+
+        // per mille stat lines of the refine level
+        if let Some(skill) = equipment::equip_skill(equip.equip_id, equip.refine_lv) {
+            hp = (hp as i64 * (1000 + skill.hp) as i64 / 1000) as i32;
+            atk = (atk as i64 * (1000 + skill.attack) as i64 / 1000) as i32;
+        }
     }
 
     HeroAttribute {
@@ -417,7 +431,7 @@ fn lookup_activity174_kit(hero_id: i32) -> Option<(Vec<i32>, Vec<i32>, Vec<i32>)
 
 fn resolve_passives(
     hero_data: &HeroData,
-    equip_id: Option<i32>,
+    equip: Option<&Equipment>,
     destiny_exchange: Option<&HashMap<i32, i32>>,
 ) -> Vec<i32> {
     let game = configs::get();
@@ -512,15 +526,10 @@ fn resolve_passives(
         passives.push(31260191);
     }
 
-    if let Some(eid) = equip_id
-        && let Some(e) = game.equip_skill.iter().find(|e| e.id == eid)
+    if let Some(equip) = equip
+        && let Some(skill) = equipment::equip_skill(equip.equip_id, equip.refine_lv)
     {
-        if e.skill != 0 {
-            passives.push(e.skill);
-        }
-        if e.skill2 != 0 {
-            passives.push(e.skill2);
-        }
+        passives.extend(equipment::passives(skill));
     }
 
     passives
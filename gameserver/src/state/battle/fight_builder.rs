@@ -1,5 +1,8 @@
 use super::BattleContext;
+use super::cloth;
 use super::entity_builder;
+use super::manager::{buff_mgr::AttrModifiers, stage_mgr::StageMgr};
+use super::resonance;
 use anyhow::Result;
 
 use database::models::game::heros::{HeroModel, UserHeroModel};
@@ -9,18 +12,20 @@ use sonettobuf::{Fight, FightTeam};
 use sqlx::SqlitePool;
 use std::collections::HashMap;
 
+/// The fight and the resonance modifiers of the heroes, by uid
 pub async fn build_fight(
     pool: &SqlitePool,
     ctx: &BattleContext,
     fight_group: &sonettobuf::FightGroup,
-) -> Result<Fight> {
+) -> Result<(Fight, HashMap<i64, AttrModifiers>)> {
     // Build attacker team (player)
-    let attacker = build_attacker_team(pool, ctx.player_id, fight_group).await?;
+    let (attacker, resonance) = build_attacker_team(pool, ctx.player_id, fight_group).await?;
+    StageMgr::new(ctx.battle_id).check_team(&attacker)?;
 
     // Build defender team (enemies from episode config)
     let defender = build_defender_team(ctx.episode_id).await?;
 
-    Ok(Fight {
+    let fight = Fight {
         attacker: Some(attacker),
         defender: Some(defender.team),
         cur_round: Some(1),
@@ -40,7 +45,9 @@ pub async fn build_fight(
         custom_data: vec![],
         fight_task_box: Some(sonettobuf::FightTaskBox { tasks: vec![] }),
         progress_list: vec![],
-    })
+    };
+
+    Ok((fight, resonance))
 }
 
 static TRIAL_UID_MAP: Lazy<HashMap<i64, i32>> = Lazy::new(|| {
@@ -62,9 +69,10 @@ async fn build_attacker_team(
     pool: &SqlitePool,
     user_id: i64,
     fight_group: &sonettobuf::FightGroup,
-) -> Result<FightTeam> {
+) -> Result<(FightTeam, HashMap<i64, AttrModifiers>)> {
     let mut entitys = Vec::new();
     let mut sub_entitys = Vec::new();
+    let mut resonance = HashMap::new();
     let hero = UserHeroModel::new(user_id, pool.clone());
 
     // Main heroes
@@ -76,6 +84,10 @@ async fn build_attacker_team(
         let entity =
             entity_builder::build_hero_entity(pool, &hero_data, (position + 1) as i32, 1, false)
                 .await;
+        resonance.insert(
+            hero_data.record.uid,
+            resonance::resonance(&hero_data).modifiers,
+        );
         entitys.push(entity);
     }
 
@@ -86,19 +98,25 @@ async fn build_attacker_team(
         }
         let hero_data = hero.get_uid(*hero_uid as i32).await?;
         let entity = entity_builder::build_hero_entity(pool, &hero_data, -1, 1, true).await;
+        resonance.insert(
+            hero_data.record.uid,
+            resonance::resonance(&hero_data).modifiers,
+        );
         sub_entitys.push(entity);
     }
 
     let player_entity = entity_builder::build_player_entity(user_id, 1);
 
-    Ok(build_fight_team(
+    let team = build_fight_team(
         entitys,
         sub_entitys,
         player_entity,
-        Some(15),
+        Some(cloth::initial_power(fight_group.cloth_id)),
         fight_group.cloth_id,
-        build_player_skills(fight_group.cloth_id),
-    ))
+        cloth::player_skills(fight_group.cloth_id),
+    );
+
+    Ok((team, resonance))
 }
 
 #[allow(dead_code)]
@@ -286,43 +304,53 @@ pub struct BattleSetup {
     pub team: FightTeam,
 }
 
-async fn build_defender_team(episode_id: i32) -> Result<BattleSetup> {
-    use config::configs;
-    let game_data = configs::get();
-
-    let episode = game_data
-        .episode
-        .iter()
-        .find(|e| e.id == episode_id)
-        .ok_or_else(|| anyhow::anyhow!("Episode {} not found", episode_id))?;
+/// Monster ids of every wave of `battle_id`. `monsterGroupIds` separates waves with
+/// `|` and the monsters of a wave with `#`.
+pub fn battle_waves(battle_id: i32) -> Result<Vec<Vec<i32>>> {
+    let game_data = config::configs::get();
 
     let battle = game_data
         .battle
         .iter()
-        .find(|b| b.id == episode.battle_id)
-        .ok_or_else(|| anyhow::anyhow!("Battle {} not found", episode.battle_id))?;
+        .find(|b| b.id == battle_id)
+        .ok_or_else(|| anyhow::anyhow!("Battle {} not found", battle_id))?;
 
-    let max_round = battle.max_round;
+    Ok(battle
+        .monster_group_ids
+        .split('|')
+        .map(|wave| {
+            wave.split('#')
+                .filter_map(|s| s.parse::<i32>().ok())
+                .collect::<Vec<i32>>()
+        })
+        .filter(|wave| !wave.is_empty())
+        .collect())
+}
 
-    tracing::info!(
-        "Loading battle {}: monsterGroupIds={}, maxRound={}",
-        episode.battle_id,
-        battle.monster_group_ids,
-        max_round
-    );
+fn episode_battle_id(episode_id: i32) -> Result<i32> {
+    config::configs::get()
+        .episode
+        .iter()
+        .find(|e| e.id == episode_id)
+        .map(|e| e.battle_id)
+        .ok_or_else(|| anyhow::anyhow!("Episode {} not found", episode_id))
+}
 
-    let monster_ids: Vec<i32> = battle
-        .monster_group_ids
-        .split('#')
-        .filter_map(|s| s.parse::<i32>().ok())
-        .collect();
+/// Enemies of wave `wave` (1 based). Uids keep counting down across waves so a new
+/// wave never reuses the uid of an enemy from an earlier one.
+fn build_wave_entities(
+    waves: &[Vec<i32>],
+    wave: usize,
+) -> Result<Vec<sonettobuf::FightEntityInfo>> {
+    let uid_offset: usize = waves[..wave - 1].iter().map(|w| w.len()).sum();
 
     let mut entitys = Vec::new();
-    for (idx, monster_id) in monster_ids.iter().enumerate() {
-        let entity = build_enemy_entity(*monster_id, idx, (idx + 1) as i32, 2)?;
+    for (idx, monster_id) in waves[wave - 1].iter().enumerate() {
+        let entity = build_enemy_entity(*monster_id, uid_offset + idx, (idx + 1) as i32, 2)?;
 
         tracing::info!(
-            "Enemy entity: monster_id={}, position={}, uid={:?}",
+            "Enemy entity: wave={}, monster_id={}, position={}, uid={:?}",
+            wave,
             monster_id,
             idx + 1,
             entity.uid
@@ -331,6 +359,45 @@ async fn build_defender_team(episode_id: i32) -> Result<BattleSetup> {
         entitys.push(entity);
     }
 
+    Ok(entitys)
+}
+
+/// Enemies of wave `wave` of the episode, `None` when the episode has no such wave
+pub fn build_wave(episode_id: i32, wave: i32) -> Result<Option<Vec<sonettobuf::FightEntityInfo>>> {
+    let waves = battle_waves(episode_battle_id(episode_id)?)?;
+
+    if wave < 1 || wave as usize > waves.len() {
+        return Ok(None);
+    }
+
+    build_wave_entities(&waves, wave as usize).map(Some)
+}
+
+async fn build_defender_team(episode_id: i32) -> Result<BattleSetup> {
+    let game_data = config::configs::get();
+
+    let battle_id = episode_battle_id(episode_id)?;
+    let max_round = game_data
+        .battle
+        .iter()
+        .find(|b| b.id == battle_id)
+        .map(|b| b.max_round)
+        .ok_or_else(|| anyhow::anyhow!("Battle {} not found", battle_id))?;
+
+    let waves = battle_waves(battle_id)?;
+    if waves.is_empty() {
+        anyhow::bail!("Battle {} has no monsters", battle_id);
+    }
+
+    tracing::info!(
+        "Loading battle {}: waves={:?}, maxRound={}",
+        battle_id,
+        waves,
+        max_round
+    );
+
+    let entitys = build_wave_entities(&waves, 1)?;
+
     tracing::info!("Built {} enemy entities", entitys.len());
 
     let player_entity = entity_builder::build_player_entity(0, 2);
@@ -554,53 +621,3 @@ fn parse_monster_skill_group(active_skill: &str, target_group: i32) -> Vec<i32>
 
     vec![]
 }
-
-fn build_player_skills(cloth_id: Option<i32>) -> Vec<sonettobuf::PlayerSkillInfo> {
-    use config::configs;
-
-    let game_data = configs::get();
-    let cloth_id = cloth_id.unwrap_or(1);
-
-    let cloth_level = game_data
-        .cloth_level
-        .iter()
-        .find(|c| c.id == cloth_id && c.level == 1);
-
-    if let Some(cloth) = cloth_level {
-        let mut skills = Vec::new();
-
-        // Skill 1
-        if cloth.skill1 != 0 {
-            skills.push(sonettobuf::PlayerSkillInfo {
-                skill_id: Some(cloth.skill1),
-                cd: Some(cloth.cd1),
-                need_power: Some(cloth.use_power1.first().copied().unwrap_or(0)),
-                r#type: Some(0),
-            });
-        }
-
-        // Skill 2
-        if cloth.skill2 != 0 {
-            skills.push(sonettobuf::PlayerSkillInfo {
-                skill_id: Some(cloth.skill2),
-                cd: Some(cloth.cd2),
-                need_power: Some(cloth.use_power2.first().copied().unwrap_or(0)),
-                r#type: Some(0),
-            });
-        }
-
-        // Skill 3
-        if cloth.skill3 != 0 {
-            skills.push(sonettobuf::PlayerSkillInfo {
-                skill_id: Some(cloth.skill3),
-                cd: Some(cloth.cd3),
-                need_power: None,
-                r#type: Some(0),
-            });
-        }
-
-        return skills;
-    }
-
-    vec![]
-}
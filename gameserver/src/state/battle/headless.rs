@@ -0,0 +1,389 @@
+//! Headless fights
+//!
+//! Runs a fight from start to finish without a client, for balance checks and bug reports.
+//! The team is either a player's saved heroes or a [`TeamSpec`] seeded into a scratch
+//! database with [`seed_team`]. Rounds come from an operation script, one list of
+//! `BeginRoundOper` per round, or from the same auto policy the `AutoRound` handler uses.
+//!
+//! The report is the `FightRound` of every round as the client would have received it,
+//! plus how every unit ended up and what it dealt, took and healed along the way.
+
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use database::db::game::equipment::{add_equipment, set_equipment_level};
+use database::db::user::account::{TokenInfo, create_user};
+use database::models::game::heros::{HeroModel, UserHeroModel};
+use serde::{Deserialize, Serialize};
+use sonettobuf::{BeginRoundOper, Fight, FightGroup, FightRound, FightStep};
+use sqlx::SqlitePool;
+
+use super::effects::effect_types::EffectType;
+use super::simulator::BattleSimulator;
+use super::{
+    BattleContext, create_battle, default_max_ap, generate_auto_opers, generate_initial_deck,
+};
+
+/// Rounds an auto fight gets when the battle sets no limit
+const AUTO_ROUND_CAP: i32 = 30;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TeamSpec {
+    pub heroes: Vec<HeroSpec>,
+    pub sub_heroes: Vec<HeroSpec>,
+    pub cloth_id: Option<i32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HeroSpec {
+    pub hero_id: i32,
+    #[serde(default = "default_level")]
+    pub level: i32,
+    /// Insight
+    #[serde(default = "default_level")]
+    pub rank: i32,
+    #[serde(default)]
+    pub portray: i32,
+    /// Resonance level, laid out with the scheme of the level
+    #[serde(default)]
+    pub resonance: i32,
+    pub psychube: Option<PsychubeSpec>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PsychubeSpec {
+    pub id: i32,
+    #[serde(default = "default_level")]
+    pub level: i32,
+    /// Amplification
+    #[serde(default = "default_level")]
+    pub refine: i32,
+}
+
+fn default_level() -> i32 {
+    1
+}
+
+pub enum Policy {
+    Auto,
+    /// Opers of every round in order, the fight stops when they run out
+    Script(Vec<Vec<BeginRoundOper>>),
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntityStats {
+    pub uid: i64,
+    pub model_id: i32,
+    /// 1 the heroes, 2 the enemies
+    pub team_type: i32,
+    pub hp: i32,
+    pub max_hp: i32,
+    pub dead: bool,
+    pub damage_dealt: i64,
+    pub damage_taken: i64,
+    pub healing_done: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulationReport {
+    pub episode_id: i32,
+    pub battle_id: i32,
+    pub seed: u64,
+    /// 1 for a win, 2 for a loss, 0 when the script or the round cap ran out before the end
+    pub result: i32,
+    pub rounds: i32,
+    pub timeline: Vec<FightRound>,
+    pub entities: Vec<EntityStats>,
+}
+
+/// Create a throwaway account owning the spec's heroes and return the group fielding them
+pub async fn seed_team(pool: &SqlitePool, user_id: i64, spec: &TeamSpec) -> Result<FightGroup> {
+    let now = common::time::ServerTime::now_ms();
+    let token = TokenInfo {
+        token: String::new(),
+        refresh_token: String::new(),
+        expires_at: now,
+    };
+    create_user(
+        pool,
+        user_id,
+        &format!("simulator{}@localhost", user_id),
+        "simulator",
+        &token,
+        now,
+    )
+    .await?;
+
+    let hero = UserHeroModel::new(user_id, pool.clone());
+    let mut group = FightGroup {
+        cloth_id: spec.cloth_id,
+        ..Default::default()
+    };
+
+    for (hero_spec, is_sub) in spec
+        .heroes
+        .iter()
+        .map(|h| (h, false))
+        .chain(spec.sub_heroes.iter().map(|h| (h, true)))
+    {
+        let uid = seed_hero(pool, user_id, &hero, hero_spec).await?;
+        if is_sub {
+            group.sub_hero_list.push(uid);
+        } else {
+            group.hero_list.push(uid);
+        }
+    }
+
+    Ok(group)
+}
+
+async fn seed_hero(
+    pool: &SqlitePool,
+    user_id: i64,
+    hero: &UserHeroModel,
+    spec: &HeroSpec,
+) -> Result<i64> {
+    let game_data = config::configs::get();
+    let hero_id = spec.hero_id;
+
+    let uid = hero
+        .create_hero(hero_id)
+        .await
+        .with_context(|| format!("Couldn't create hero {}", hero_id))?;
+
+    // insight resets the level, so it goes first
+    if spec.rank > 1 {
+        hero.rank_up(hero_id, spec.rank).await?;
+    }
+
+    let stats = game_data
+        .character_level
+        .iter()
+        .filter(|l| l.hero_id == hero_id && l.level <= spec.level)
+        .max_by_key(|l| l.level)
+        .with_context(|| {
+            format!(
+                "No level stats for hero {} at level {}",
+                hero_id, spec.level
+            )
+        })?;
+    hero.level_up(hero_id, spec.level, stats).await?;
+
+    if spec.portray > 0 {
+        hero.upgrade_ex_skill(hero_id, spec.portray).await?;
+    }
+    if spec.resonance > 0 {
+        hero.update_talent(hero_id, spec.resonance).await?;
+    }
+
+    if let Some(psychube) = &spec.psychube {
+        let rare = game_data
+            .equip
+            .get(psychube.id)
+            .map(|e| e.rare)
+            .with_context(|| format!("Unknown psychube {}", psychube.id))?;
+        let break_lv = game_data
+            .equip_break_cost
+            .iter()
+            .filter(|b| b.rare == rare && b.level < psychube.level)
+            .map(|b| b.break_level)
+            .max()
+            .unwrap_or(0);
+
+        let equip_uid = add_equipment(pool, user_id, psychube.id, 1)
+            .await?
+            .first()
+            .copied()
+            .with_context(|| format!("Couldn't create psychube {}", psychube.id))?;
+        set_equipment_level(
+            pool,
+            user_id,
+            equip_uid,
+            psychube.level,
+            break_lv,
+            psychube.refine,
+        )
+        .await?;
+        hero.update_equipped_gear(hero_id, equip_uid).await?;
+    }
+
+    Ok(uid)
+}
+
+/// Fight the episode with the group and return the whole timeline
+pub async fn simulate(
+    pool: &SqlitePool,
+    user_id: i64,
+    fight_group: &FightGroup,
+    episode_id: i32,
+    seed: u64,
+    policy: Policy,
+) -> Result<SimulationReport> {
+    let game_data = config::configs::get();
+
+    let episode = game_data
+        .episode
+        .get(episode_id)
+        .ok_or_else(|| anyhow::anyhow!("Unknown episode {}", episode_id))?;
+
+    let hero_count = fight_group.hero_list.iter().filter(|&&u| u != 0).count();
+    let max_ap = default_max_ap(episode_id, hero_count);
+
+    let ctx = BattleContext {
+        player_id: user_id,
+        chapter_id: episode.chapter_id,
+        episode_id,
+        battle_id: episode.battle_id,
+        max_ap,
+        seed,
+    };
+
+    let card_push = generate_initial_deck(pool, user_id, fight_group, max_ap, seed).await?;
+    let mut deck = card_push.card_group;
+
+    let (fight, initial_round, fight_data_mgr, mut ai_deck) =
+        create_battle(pool, ctx, fight_group, deck.clone()).await?;
+
+    let max_round = match fight.max_round.unwrap_or(0) {
+        0 => AUTO_ROUND_CAP,
+        n => n,
+    };
+
+    let mut simulator = BattleSimulator::new(fight_data_mgr, seed);
+    let mut units = HashMap::new();
+    record_units(&fight, &mut units);
+    let mut timeline = vec![initial_round];
+    let mut rounds = 0;
+    let mut finished = false;
+
+    let mut script = match policy {
+        Policy::Auto => None,
+        Policy::Script(rounds) => Some(rounds.into_iter()),
+    };
+
+    for round_num in 1..=max_round {
+        let opers = match script.as_mut() {
+            Some(script) => match script.next() {
+                Some(opers) => opers,
+                None => break,
+            },
+            None => generate_auto_opers(&deck),
+        };
+
+        let round = simulator
+            .process_round(round_num, opers, deck, ai_deck)
+            .await?;
+
+        deck = round.team_a_cards1.clone();
+        ai_deck = round.ai_use_cards.clone();
+        rounds = round.cur_round.unwrap_or(round_num);
+        finished = round.is_finish.unwrap_or(false);
+        timeline.push(round);
+        record_units(&simulator.fight_snapshot(), &mut units);
+
+        if finished {
+            break;
+        }
+    }
+
+    let result = if finished {
+        simulator.fight_result()
+    } else {
+        0
+    };
+
+    Ok(SimulationReport {
+        episode_id,
+        battle_id: episode.battle_id,
+        seed,
+        result,
+        rounds,
+        entities: entity_stats(units, &timeline),
+        timeline,
+    })
+}
+
+/// Latest state of every unit of the fight, the enemies of earlier waves included
+fn record_units(fight: &Fight, stats: &mut HashMap<i64, EntityStats>) {
+    for (team_type, team) in [(1, &fight.attacker), (2, &fight.defender)] {
+        for entity in team
+            .iter()
+            .flat_map(|t| t.entitys.iter().chain(t.sub_entitys.iter()))
+        {
+            let uid = entity.uid.unwrap_or(0);
+            let hp = entity.current_hp.unwrap_or(0);
+            stats.insert(
+                uid,
+                EntityStats {
+                    uid,
+                    model_id: entity.model_id.unwrap_or(0),
+                    team_type,
+                    hp,
+                    max_hp: entity.attr.as_ref().and_then(|a| a.hp).unwrap_or(0),
+                    dead: hp <= 0,
+                    ..Default::default()
+                },
+            );
+        }
+    }
+}
+
+fn entity_stats(mut stats: HashMap<i64, EntityStats>, timeline: &[FightRound]) -> Vec<EntityStats> {
+    for step in timeline.iter().flat_map(|r| r.fight_step.iter()) {
+        tally_step(step, &mut stats);
+    }
+
+    let mut stats: Vec<_> = stats.into_values().collect();
+    stats.sort_by_key(|s| (s.team_type, s.uid));
+    stats
+}
+
+fn tally_step(step: &FightStep, stats: &mut HashMap<i64, EntityStats>) {
+    let from = step.from_id.unwrap_or(0);
+
+    for effect in &step.act_effect {
+        if let Some(nested) = &effect.fight_step {
+            tally_step(nested, stats);
+            continue;
+        }
+
+        let amount = effect.effect_num.unwrap_or(0) as i64;
+        let target = effect.target_id.unwrap_or(0);
+        // damage over time ticks in an effect step, the buff's caster is in the hurt info
+        let from = effect
+            .hurt_info
+            .as_ref()
+            .and_then(|h| h.from_uid)
+            .filter(|&uid| uid != 0)
+            .unwrap_or(from);
+
+        match EffectType::from(effect.effect_type.unwrap_or(0)) {
+            EffectType::Damage
+            | EffectType::Crit
+            | EffectType::Dot
+            | EffectType::DamageExtra
+            | EffectType::OriginDamage
+            | EffectType::OriginCrit
+            | EffectType::AdditionalDamage
+            | EffectType::AdditionalDamageCrit => {
+                if let Some(source) = stats.get_mut(&from) {
+                    source.damage_dealt += amount;
+                }
+                if let Some(target) = stats.get_mut(&target) {
+                    target.damage_taken += amount;
+                }
+            }
+            EffectType::Heal => {
+                if let Some(source) = stats.get_mut(&from) {
+                    source.healing_done += amount;
+                }
+            }
+            _ => {}
+        }
+    }
+}
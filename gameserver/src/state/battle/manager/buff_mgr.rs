@@ -1,17 +1,230 @@
+//! Buff state of a fight
+//!
+//! Rules come from `skill_buff`:
+//! - `duringTime` rounds before the buff expires, 0 for no round limit
+//! - `effectCount` triggers before the buff expires, 0 for no count limit
+//! - `typeId` groups buffs that share a slot, adding one replaces the others
+//! - `isGoodBuff` decides whether `Purify` (debuffs) or `Disperse` (buffs) removes it
+//! - `features` is a `|` separated list of `behavior#params` entries (behavior id from
+//!   `skill_behavior` or its type name). `AttrFix#attrId#value` modifies an attribute by
+//!   `value` per mille per layer, `Stackable#maxLayer` lets the buff stack up to `maxLayer`,
+//!   any other behavior is kept as a feature of the buff with its first param (`Taunt`,
+//!   `CantSelect`, `ExPointMaxAdd#2`, ...)
+//!
+//! Every change is returned as the `BuffAdd`/`BuffUpdate`/`BuffDel` effect the client
+//! expects, and `apply_effect` replays those effects so the state follows what was sent.
+
 use std::collections::HashMap;
 
-#[allow(dead_code)]
-#[derive(Default, Debug, Clone)]
+use serde::{Deserialize, Serialize};
+use sonettobuf::{ActEffect, BuffInfo, effect_type_enum::EffectType};
+
+use crate::state::battle::mechanics::status;
+use crate::state::battle::uids::UidCounter;
+
+// Attribute ids of `AttrFix` are the field tag in `HeroAttribute`, `HeroExAttribute` or
+// `HeroSpAttribute` plus 100, 200 or 300
+pub const ATTR_ATTACK: i32 = 102;
+pub const ATTR_DEFENSE: i32 = 103;
+pub const ATTR_MDEFENSE: i32 = 104;
+pub const ATTR_CRI: i32 = 201;
+pub const ATTR_RECRI: i32 = 202;
+pub const ATTR_CRI_DMG: i32 = 203;
+pub const ATTR_CRI_DEF: i32 = 204;
+pub const ATTR_ADD_DMG: i32 = 205;
+pub const ATTR_DROP_DMG: i32 = 206;
+pub const ATTR_HEAL: i32 = 302;
+pub const ATTR_DEFENSE_IGNORE: i32 = 304;
+pub const ATTR_NORMAL_SKILL_RATE: i32 = 308;
+pub const ATTR_BIG_SKILL_RATE: i32 = 328;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BuffRule {
+    pub type_id: i32,
+    pub duration: i32,
+    pub count: i32,
+    pub max_layer: i32,
+    pub is_good: bool,
+    /// (attr id, per mille per layer)
+    pub attrs: Vec<(i32, i32)>,
+    /// (behavior type, first param) of the other features, like `Taunt` or `ExPointMaxAdd`
+    pub features: Vec<(String, i32)>,
+}
+
+impl BuffRule {
+    pub fn of(buff_id: i32) -> Self {
+        let game_data = config::configs::get();
+
+        let Some(buff) = game_data.skill_buff.get(buff_id) else {
+            return Self {
+                max_layer: 1,
+                ..Default::default()
+            };
+        };
+
+        let mut rule = Self {
+            type_id: buff.type_id,
+            duration: buff.during_time,
+            count: buff.effect_count,
+            max_layer: 1,
+            is_good: buff.is_good_buff == 1,
+            attrs: Vec::new(),
+            features: Vec::new(),
+        };
+
+        for entry in buff.features.split('|').filter(|e| !e.is_empty()) {
+            let parts: Vec<&str> = entry.split('#').collect();
+            let param =
+                |i: usize| -> i32 { parts.get(i).and_then(|p| p.parse().ok()).unwrap_or(0) };
+
+            let kind = match parts[0].parse::<i32>() {
+                Ok(behavior_id) => game_data
+                    .skill_behavior
+                    .get(behavior_id)
+                    .map(|b| b.r#type.as_str())
+                    .unwrap_or(""),
+                Err(_) => parts[0],
+            };
+
+            match kind {
+                "AttrFix" | "AttrFixBuff" => rule.attrs.push((param(1), param(2))),
+                "Stackable" => rule.max_layer = param(1).max(1),
+                "" => {}
+                _ => rule.features.push((kind.to_string(), param(1))),
+            }
+        }
+
+        rule
+    }
+
+    /// Buffs without any limit are passive markers and can't be dispelled
+    pub fn is_permanent(&self) -> bool {
+        self.duration <= 0 && self.count <= 0
+    }
+
+    /// Whether a hit using `attr_ids` uses up a count. Buffs without attribute
+    /// modifiers (`Taunt`, shields, ...) count every hit of their holder.
+    fn counts_hit(&self, attr_ids: &[i32]) -> bool {
+        self.count > 0
+            && (self.attrs.is_empty() || self.attrs.iter().any(|(id, _)| attr_ids.contains(id)))
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct BuffInstance {
+    pub uid: i64,
     pub buff_id: i32,
     pub from_uid: i64,
     pub duration: i32,
-    pub stacks: i32,
+    pub count: i32,
+    pub layer: i32,
+    pub rule: BuffRule,
 }
 
-#[derive(Default, Debug, Clone)]
+impl BuffInstance {
+    pub fn to_info(&self) -> BuffInfo {
+        BuffInfo {
+            buff_id: Some(self.buff_id),
+            duration: Some(self.duration),
+            uid: Some(self.uid),
+            ex_info: Some(0),
+            from_uid: Some(self.from_uid),
+            count: Some(self.count),
+            layer: Some(self.layer),
+            r#type: Some(0),
+            act_common_params: Some(String::new()),
+            act_info: vec![],
+        }
+    }
+}
+
+/// Per mille attribute modifiers from all buffs of an entity
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttrModifiers {
+    pub attack: i32,
+    pub defense: i32,
+    pub mdefense: i32,
+    pub cri: i32,
+    pub recri: i32,
+    pub cri_dmg: i32,
+    pub cri_def: i32,
+    pub add_dmg: i32,
+    pub drop_dmg: i32,
+    pub heal: i32,
+    pub defense_ignore: i32,
+    pub normal_skill_rate: i32,
+    pub big_skill_rate: i32,
+}
+
+impl std::ops::AddAssign for AttrModifiers {
+    fn add_assign(&mut self, other: Self) {
+        self.attack += other.attack;
+        self.defense += other.defense;
+        self.mdefense += other.mdefense;
+        self.cri += other.cri;
+        self.recri += other.recri;
+        self.cri_dmg += other.cri_dmg;
+        self.cri_def += other.cri_def;
+        self.add_dmg += other.add_dmg;
+        self.drop_dmg += other.drop_dmg;
+        self.heal += other.heal;
+        self.defense_ignore += other.defense_ignore;
+        self.normal_skill_rate += other.normal_skill_rate;
+        self.big_skill_rate += other.big_skill_rate;
+    }
+}
+
+impl AttrModifiers {
+    fn add(&mut self, attr_id: i32, value: i32) {
+        match attr_id {
+            ATTR_ATTACK => self.attack += value,
+            ATTR_DEFENSE => self.defense += value,
+            ATTR_MDEFENSE => self.mdefense += value,
+            ATTR_CRI => self.cri += value,
+            ATTR_RECRI => self.recri += value,
+            ATTR_CRI_DMG => self.cri_dmg += value,
+            ATTR_CRI_DEF => self.cri_def += value,
+            ATTR_ADD_DMG => self.add_dmg += value,
+            ATTR_DROP_DMG => self.drop_dmg += value,
+            ATTR_HEAL => self.heal += value,
+            ATTR_DEFENSE_IGNORE => self.defense_ignore += value,
+            ATTR_NORMAL_SKILL_RATE => self.normal_skill_rate += value,
+            ATTR_BIG_SKILL_RATE => self.big_skill_rate += value,
+            _ => {}
+        }
+    }
+}
+
+/// Buff uids of a fight start at 2
+const FIRST_BUFF_UID: i64 = 2;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct BuffMgr {
     active: HashMap<i64, Vec<BuffInstance>>,
+    /// Modifiers a unit brings into the fight (resonance), no dispel touches them
+    base_modifiers: HashMap<i64, AttrModifiers>,
+    uids: UidCounter,
+}
+
+impl Default for BuffMgr {
+    fn default() -> Self {
+        Self {
+            active: HashMap::new(),
+            base_modifiers: HashMap::new(),
+            uids: UidCounter::starting_at(FIRST_BUFF_UID),
+        }
+    }
+}
+
+fn buff_effect(effect_type: EffectType, target_uid: i64, buff: &BuffInstance) -> ActEffect {
+    ActEffect {
+        effect_type: Some(effect_type as i32),
+        target_id: Some(target_uid),
+        effect_num: Some(buff.buff_id),
+        buff: Some(buff.to_info()),
+        ..Default::default()
+    }
 }
 
 #[allow(dead_code)]
@@ -20,43 +233,165 @@ impl BuffMgr {
         Self::default()
     }
 
-    pub fn add_buff(&mut self, target_uid: i64, buff_id: i32, from_uid: i64) {
-        let entry = self.active.entry(target_uid).or_default();
+    /// Uid of a buff this fight adds
+    pub fn next_uid(&self) -> i64 {
+        self.uids.next()
+    }
 
-        let game_data = config::configs::get();
+    /// Effects adding `buff_id` to `target_uid`: `BuffDel` for buffs of the same type it
+    /// replaces, then `BuffUpdate` when it refreshes or stacks an existing one or `BuffAdd`.
+    /// A target resisting a status of the buff only gets a `BuffReject`.
+    pub fn add_buff_effects(&self, target_uid: i64, buff_id: i32, from_uid: i64) -> Vec<ActEffect> {
+        self.rule_buff_effects(target_uid, buff_id, BuffRule::of(buff_id), from_uid)
+    }
 
-        let duration = game_data
-            .skill_buff
-            .iter()
-            .find(|b| b.id == buff_id)
-            .map(|b| b.during_time)
-            .unwrap_or(0);
+    fn rule_buff_effects(
+        &self,
+        target_uid: i64,
+        buff_id: i32,
+        rule: BuffRule,
+        from_uid: i64,
+    ) -> Vec<ActEffect> {
+        let buffs = self.get_buffs(target_uid);
+        let mut effects = Vec::new();
+
+        if status::resists(self, target_uid, &rule) {
+            return vec![ActEffect {
+                effect_type: Some(EffectType::Buffreject as i32),
+                target_id: Some(target_uid),
+                effect_num: Some(buff_id),
+                ..Default::default()
+            }];
+        }
+
+        if rule.type_id != 0 {
+            for other in buffs
+                .iter()
+                .filter(|b| b.buff_id != buff_id && b.rule.type_id == rule.type_id)
+            {
+                effects.push(buff_effect(EffectType::Buffdel, target_uid, other));
+            }
+        }
+
+        match buffs.iter().find(|b| b.buff_id == buff_id) {
+            Some(existing) => {
+                let updated = BuffInstance {
+                    duration: existing.duration.max(rule.duration),
+                    count: existing.count.max(rule.count),
+                    layer: (existing.layer + 1).min(rule.max_layer),
+                    from_uid,
+                    ..existing.clone()
+                };
+                effects.push(buff_effect(EffectType::Buffupdate, target_uid, &updated));
+            }
+            None => {
+                let added = BuffInstance {
+                    uid: self.next_uid(),
+                    buff_id,
+                    from_uid,
+                    duration: rule.duration,
+                    count: rule.count,
+                    layer: 1,
+                    rule,
+                };
+                effects.push(buff_effect(EffectType::Buffadd, target_uid, &added));
+            }
+        }
+
+        effects
+    }
+
+    /// Apply a `BuffAdd`, `BuffUpdate` or `BuffDel` effect
+    pub fn apply_effect(&mut self, effect: &ActEffect) {
+        let Some(target_uid) = effect.target_id else {
+            return;
+        };
+        let info = effect.buff.clone().unwrap_or_default();
+        let buff_id = info.buff_id.or(effect.effect_num).unwrap_or(0);
+        let effect_type = effect.effect_type.unwrap_or(0);
+
+        if effect_type == EffectType::Buffadd as i32 {
+            self.add_buff(target_uid, buff_id, info.from_uid.unwrap_or(0), info.uid);
+        } else if effect_type == EffectType::Buffupdate as i32 {
+            let entry = self.active.entry(target_uid).or_default();
+            if let Some(existing) = entry.iter_mut().find(|b| b.buff_id == buff_id) {
+                existing.duration = info.duration.unwrap_or(existing.duration);
+                existing.count = info.count.unwrap_or(existing.count);
+                existing.layer = info.layer.unwrap_or(existing.layer);
+                existing.from_uid = info.from_uid.unwrap_or(existing.from_uid);
+            } else {
+                self.add_buff(target_uid, buff_id, info.from_uid.unwrap_or(0), info.uid);
+            }
+        } else if effect_type == EffectType::Buffdel as i32 {
+            self.remove_buff(target_uid, info.uid, buff_id);
+        }
+    }
+
+    /// Add `buff_id` following its rule, without building effects
+    pub fn add_buff(&mut self, target_uid: i64, buff_id: i32, from_uid: i64, uid: Option<i64>) {
+        self.add_rule_buff(target_uid, buff_id, BuffRule::of(buff_id), from_uid, uid);
+    }
+
+    fn add_rule_buff(
+        &mut self,
+        target_uid: i64,
+        buff_id: i32,
+        rule: BuffRule,
+        from_uid: i64,
+        uid: Option<i64>,
+    ) {
+        let uid = uid.filter(|u| *u != 0);
+        if let Some(uid) = uid {
+            self.uids.observe(uid);
+        }
+        let entry = self.active.entry(target_uid).or_default();
+
+        if rule.type_id != 0 {
+            entry.retain(|b| b.buff_id == buff_id || b.rule.type_id != rule.type_id);
+        }
 
         if let Some(existing) = entry.iter_mut().find(|b| b.buff_id == buff_id) {
-            existing.stacks += 1;
-            existing.duration = existing.duration.max(duration);
+            existing.duration = existing.duration.max(rule.duration);
+            existing.count = existing.count.max(rule.count);
+            existing.layer = (existing.layer + 1).min(rule.max_layer);
+            existing.from_uid = from_uid;
         } else {
             entry.push(BuffInstance {
+                uid: uid.unwrap_or_else(|| self.uids.next()),
                 buff_id,
                 from_uid,
-                duration,
-                stacks: 1,
+                duration: rule.duration,
+                count: rule.count,
+                layer: 1,
+                rule,
             });
         }
 
         tracing::info!(
-            "[BuffMgr] Added buff {} to {} from {} (duration={})",
+            "[BuffMgr] Added buff {} to {} from {}",
             buff_id,
             target_uid,
-            from_uid,
-            duration
+            from_uid
         );
     }
 
+    fn remove_buff(&mut self, target_uid: i64, uid: Option<i64>, buff_id: i32) {
+        if let Some(buffs) = self.active.get_mut(&target_uid) {
+            match uid.filter(|u| *u != 0) {
+                Some(uid) => buffs.retain(|b| b.uid != uid),
+                None => buffs.retain(|b| b.buff_id != buff_id),
+            }
+        }
+    }
+
     pub fn get_buffs(&self, uid: i64) -> &[BuffInstance] {
         self.active.get(&uid).map(|v| v.as_slice()).unwrap_or(&[])
     }
 
+    pub fn buff_infos(&self, uid: i64) -> Vec<BuffInfo> {
+        self.get_buffs(uid).iter().map(|b| b.to_info()).collect()
+    }
+
     pub fn has_buff(&self, uid: i64, buff_id: i32) -> bool {
         self.active
             .get(&uid)
@@ -64,16 +399,463 @@ impl BuffMgr {
             .unwrap_or(false)
     }
 
-    pub fn on_round_end(&mut self) {
-        for buffs in self.active.values_mut() {
-            for b in buffs.iter_mut() {
-                b.duration -= 1;
+    /// Whether one of the buffs on `uid` has the `feature` behavior type
+    pub fn has_feature(&self, uid: i64, feature: &str) -> bool {
+        self.get_buffs(uid)
+            .iter()
+            .any(|b| b.rule.features.iter().any(|(f, _)| f == feature))
+    }
+
+    /// Sum of the `feature` params over the buffs on `uid`, counting every layer
+    pub fn feature_total(&self, uid: i64, feature: &str) -> i32 {
+        self.get_buffs(uid)
+            .iter()
+            .flat_map(|b| {
+                b.rule
+                    .features
+                    .iter()
+                    .filter(|(f, _)| f == feature)
+                    .map(move |(_, value)| value * b.layer.max(1))
+            })
+            .sum()
+    }
+
+    pub fn set_base_modifiers(&mut self, uid: i64, mods: AttrModifiers) {
+        self.base_modifiers.insert(uid, mods);
+    }
+
+    pub fn attr_modifiers(&self, uid: i64) -> AttrModifiers {
+        let mut mods = self.base_modifiers.get(&uid).copied().unwrap_or_default();
+
+        for buff in self.get_buffs(uid) {
+            for &(attr_id, value) in &buff.rule.attrs {
+                mods.add(attr_id, value * buff.layer.max(1));
             }
-            buffs.retain(|b| b.duration > 0);
         }
+
+        mods
+    }
+
+    /// Use up one trigger of every count limited buff on `uid` that touches one of
+    /// `attr_ids` or has no attribute modifier, removing the buffs that run out
+    pub fn consume_count(&mut self, uid: i64, attr_ids: &[i32]) -> Vec<ActEffect> {
+        let mut effects = Vec::new();
+
+        let Some(buffs) = self.active.get_mut(&uid) else {
+            return effects;
+        };
+
+        for buff in buffs.iter_mut().filter(|b| b.rule.counts_hit(attr_ids)) {
+            buff.count -= 1;
+            let effect_type = if buff.count > 0 {
+                EffectType::Buffupdate
+            } else {
+                EffectType::Buffdel
+            };
+            effects.push(buff_effect(effect_type, uid, buff));
+        }
+
+        buffs.retain(|b| b.rule.count <= 0 || b.count > 0);
+        effects
+    }
+
+    /// `BuffDel` effects for up to `max` debuffs of `uid` (newest first), 0 for all of them
+    pub fn purify(&self, uid: i64, max: i32) -> Vec<ActEffect> {
+        self.dispel(uid, max, false)
+    }
+
+    /// `BuffDel` effects for up to `max` buffs of `uid` (newest first), 0 for all of them
+    pub fn disperse(&self, uid: i64, max: i32) -> Vec<ActEffect> {
+        self.dispel(uid, max, true)
+    }
+
+    fn dispel(&self, uid: i64, max: i32, good: bool) -> Vec<ActEffect> {
+        let limit = if max > 0 { max as usize } else { usize::MAX };
+
+        self.get_buffs(uid)
+            .iter()
+            .rev()
+            .filter(|b| b.rule.is_good == good && !b.rule.is_permanent())
+            .take(limit)
+            .map(|b| buff_effect(EffectType::Buffdel, uid, b))
+            .collect()
+    }
+
+    /// Tick round limited buffs down, `BuffUpdate` for the ones still running and
+    /// `BuffDel` for the ones that expired
+    pub fn on_round_end(&mut self) -> Vec<ActEffect> {
+        let mut effects = Vec::new();
+
+        for (uid, buffs) in self.active.iter_mut() {
+            for buff in buffs.iter_mut().filter(|b| b.rule.duration > 0) {
+                buff.duration -= 1;
+                let effect_type = if buff.duration > 0 {
+                    EffectType::Buffupdate
+                } else {
+                    EffectType::Buffdel
+                };
+                effects.push(buff_effect(effect_type, *uid, buff));
+            }
+
+            buffs.retain(|b| b.rule.duration <= 0 || b.duration > 0);
+        }
+
+        effects.sort_by_key(|e| (e.target_id, e.buff.as_ref().and_then(|b| b.uid)));
+        effects
     }
 
     pub fn clear_dead(&mut self, uid: i64) {
         self.active.remove(&uid);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost::Message;
+    use sonettobuf::{HeroAttribute, HeroExAttribute, HeroSpAttribute};
+
+    const HERO: i64 = 1;
+    const ENEMY: i64 = -1;
+
+    fn rule(duration: i32, count: i32) -> BuffRule {
+        BuffRule {
+            duration,
+            count,
+            max_layer: 1,
+            ..Default::default()
+        }
+    }
+
+    fn debuff(duration: i32) -> BuffRule {
+        rule(duration, 0)
+    }
+
+    fn good(rule: BuffRule) -> BuffRule {
+        BuffRule {
+            is_good: true,
+            ..rule
+        }
+    }
+
+    fn effect_types(effects: &[ActEffect]) -> Vec<(EffectType, i32)> {
+        effects
+            .iter()
+            .map(|e| {
+                let effect_type = EffectType::try_from(e.effect_type.unwrap_or(0)).unwrap();
+                (
+                    effect_type,
+                    e.buff.as_ref().and_then(|b| b.buff_id).unwrap_or(0),
+                )
+            })
+            .collect()
+    }
+
+    fn layer(buffs: &BuffMgr, buff_id: i32) -> i32 {
+        buffs
+            .get_buffs(HERO)
+            .iter()
+            .find(|b| b.buff_id == buff_id)
+            .map_or(0, |b| b.layer)
+    }
+
+    #[test]
+    fn stacking_adds_layers_up_to_the_max_and_refreshes() {
+        let stackable = BuffRule {
+            max_layer: 2,
+            ..rule(2, 0)
+        };
+        let mut buffs = BuffMgr::new();
+
+        let effects = buffs.rule_buff_effects(HERO, 10, stackable.clone(), ENEMY);
+        assert_eq!(effect_types(&effects), [(EffectType::Buffadd, 10)]);
+        buffs.add_rule_buff(HERO, 10, stackable.clone(), ENEMY, None);
+        assert_eq!(layer(&buffs, 10), 1);
+
+        buffs.on_round_end();
+        let effects = buffs.rule_buff_effects(HERO, 10, stackable.clone(), ENEMY);
+        assert_eq!(effect_types(&effects), [(EffectType::Buffupdate, 10)]);
+        let update = effects[0].buff.as_ref().unwrap();
+        assert_eq!((update.layer, update.duration), (Some(2), Some(2)));
+
+        for _ in 0..3 {
+            buffs.add_rule_buff(HERO, 10, stackable.clone(), ENEMY, None);
+        }
+        assert_eq!(layer(&buffs, 10), 2);
+        assert_eq!(buffs.get_buffs(HERO).len(), 1);
+    }
+
+    #[test]
+    fn a_buff_replaces_the_others_of_its_type() {
+        let typed = |type_id| BuffRule {
+            type_id,
+            ..rule(2, 0)
+        };
+        let mut buffs = BuffMgr::new();
+        buffs.add_rule_buff(HERO, 10, typed(7), ENEMY, None);
+        buffs.add_rule_buff(HERO, 11, typed(8), ENEMY, None);
+
+        let effects = buffs.rule_buff_effects(HERO, 12, typed(7), ENEMY);
+        assert_eq!(
+            effect_types(&effects),
+            [(EffectType::Buffdel, 10), (EffectType::Buffadd, 12)]
+        );
+
+        buffs.add_rule_buff(HERO, 12, typed(7), ENEMY, None);
+        let ids: Vec<i32> = buffs.get_buffs(HERO).iter().map(|b| b.buff_id).collect();
+        assert_eq!(ids, [11, 12]);
+    }
+
+    #[test]
+    fn a_status_resist_rejects_the_buff() {
+        let stun = BuffRule {
+            features: vec![("Dizzy".to_string(), 0)],
+            ..rule(1, 0)
+        };
+        let immunity = BuffRule {
+            features: vec![("DizzyResist".to_string(), 0)],
+            ..good(rule(0, 0))
+        };
+        let mut buffs = BuffMgr::new();
+        buffs.add_rule_buff(HERO, 20, immunity, HERO, None);
+
+        let effects = buffs.rule_buff_effects(HERO, 21, stun.clone(), ENEMY);
+        assert_eq!(effects.len(), 1);
+        assert_eq!(effects[0].effect_type, Some(EffectType::Buffreject as i32));
+
+        let effects = buffs.rule_buff_effects(ENEMY, 21, stun, HERO);
+        assert_eq!(effect_types(&effects), [(EffectType::Buffadd, 21)]);
+    }
+
+    #[test]
+    fn count_limited_buffs_run_out_on_matching_hits() {
+        let offensive = BuffRule {
+            attrs: vec![(ATTR_ATTACK, 100)],
+            ..good(rule(0, 2))
+        };
+        let shield = good(rule(0, 1));
+        let mut buffs = BuffMgr::new();
+        buffs.add_rule_buff(HERO, 30, offensive, HERO, None);
+        buffs.add_rule_buff(HERO, 31, shield, HERO, None);
+
+        // a hit using defense only uses up the buff without attributes
+        let effects = buffs.consume_count(HERO, &[ATTR_DEFENSE]);
+        assert_eq!(effect_types(&effects), [(EffectType::Buffdel, 31)]);
+
+        let effects = buffs.consume_count(HERO, &[ATTR_ATTACK]);
+        assert_eq!(effect_types(&effects), [(EffectType::Buffupdate, 30)]);
+        assert_eq!(effects[0].buff.as_ref().unwrap().count, Some(1));
+
+        let effects = buffs.consume_count(HERO, &[ATTR_ATTACK]);
+        assert_eq!(effect_types(&effects), [(EffectType::Buffdel, 30)]);
+        assert!(buffs.get_buffs(HERO).is_empty());
+    }
+
+    #[test]
+    fn round_limited_buffs_expire_at_round_end() {
+        let mut buffs = BuffMgr::new();
+        buffs.add_rule_buff(HERO, 40, debuff(2), ENEMY, None);
+        buffs.add_rule_buff(HERO, 41, debuff(1), ENEMY, None);
+        buffs.add_rule_buff(HERO, 42, rule(0, 0), HERO, None);
+
+        let effects = buffs.on_round_end();
+        assert_eq!(
+            effect_types(&effects),
+            [(EffectType::Buffupdate, 40), (EffectType::Buffdel, 41)]
+        );
+
+        let effects = buffs.on_round_end();
+        assert_eq!(effect_types(&effects), [(EffectType::Buffdel, 40)]);
+
+        // no limit, a passive marker
+        let ids: Vec<i32> = buffs.get_buffs(HERO).iter().map(|b| b.buff_id).collect();
+        assert_eq!(ids, [42]);
+    }
+
+    #[test]
+    fn purify_and_disperse_pick_their_side_newest_first() {
+        let mut buffs = BuffMgr::new();
+        buffs.add_rule_buff(HERO, 50, debuff(2), ENEMY, None);
+        buffs.add_rule_buff(HERO, 51, good(rule(2, 0)), HERO, None);
+        buffs.add_rule_buff(HERO, 52, debuff(2), ENEMY, None);
+        buffs.add_rule_buff(HERO, 53, rule(0, 0), ENEMY, None);
+        buffs.add_rule_buff(HERO, 54, good(rule(0, 3)), HERO, None);
+
+        assert_eq!(
+            effect_types(&buffs.purify(HERO, 1)),
+            [(EffectType::Buffdel, 52)]
+        );
+        // permanent buffs can't be dispelled
+        assert_eq!(
+            effect_types(&buffs.purify(HERO, 0)),
+            [(EffectType::Buffdel, 52), (EffectType::Buffdel, 50)]
+        );
+        assert_eq!(
+            effect_types(&buffs.disperse(HERO, 0)),
+            [(EffectType::Buffdel, 54), (EffectType::Buffdel, 51)]
+        );
+
+        for effect in buffs.purify(HERO, 0) {
+            buffs.apply_effect(&effect);
+        }
+        let ids: Vec<i32> = buffs.get_buffs(HERO).iter().map(|b| b.buff_id).collect();
+        assert_eq!(ids, [51, 53, 54]);
+    }
+
+    #[test]
+    fn attr_modifiers_sum_base_and_layers() {
+        let might = BuffRule {
+            max_layer: 3,
+            attrs: vec![(ATTR_ATTACK, 100), (ATTR_CRI, 50)],
+            ..good(rule(2, 0))
+        };
+        let weaken = BuffRule {
+            attrs: vec![(ATTR_ATTACK, -30), (ATTR_DEFENSE, -200), (999, 1000)],
+            ..debuff(2)
+        };
+        let mut buffs = BuffMgr::new();
+        buffs.set_base_modifiers(
+            HERO,
+            AttrModifiers {
+                attack: 20,
+                heal: 150,
+                ..Default::default()
+            },
+        );
+        buffs.add_rule_buff(HERO, 60, might.clone(), HERO, None);
+        buffs.add_rule_buff(HERO, 60, might, HERO, None);
+        buffs.add_rule_buff(HERO, 61, weaken, ENEMY, None);
+
+        assert_eq!(
+            buffs.attr_modifiers(HERO),
+            AttrModifiers {
+                attack: 20 + 2 * 100 - 30,
+                cri: 2 * 50,
+                defense: -200,
+                heal: 150,
+                ..Default::default()
+            }
+        );
+        assert_eq!(buffs.attr_modifiers(ENEMY), AttrModifiers::default());
+    }
+
+    #[test]
+    fn buff_uids_follow_the_copy_a_round_works_on() {
+        let mut buffs = BuffMgr::new();
+        let round = buffs.clone();
+
+        let effects = round.rule_buff_effects(HERO, 70, debuff(1), ENEMY);
+        let uid = effects[0].buff.as_ref().and_then(|b| b.uid);
+        assert_eq!(uid, Some(FIRST_BUFF_UID));
+
+        buffs.add_rule_buff(HERO, 70, debuff(1), ENEMY, uid);
+        assert_eq!(buffs.get_buffs(HERO)[0].uid, FIRST_BUFF_UID);
+        assert_eq!(buffs.next_uid(), FIRST_BUFF_UID + 1);
+    }
+
+    fn tag(message: impl Message) -> i32 {
+        let buf = message.encode_to_vec();
+        let (tag, _) = prost::encoding::decode_key(&mut buf.as_slice()).unwrap();
+        tag as i32
+    }
+
+    #[test]
+    fn attr_ids_follow_the_protocol_fields() {
+        let base = |attr: HeroAttribute| 100 + tag(attr);
+        let ex = |attr: HeroExAttribute| 200 + tag(attr);
+        let sp = |attr: HeroSpAttribute| 300 + tag(attr);
+
+        let one = Some(1);
+        assert_eq!(
+            base(HeroAttribute {
+                attack: one,
+                ..Default::default()
+            }),
+            ATTR_ATTACK
+        );
+        assert_eq!(
+            base(HeroAttribute {
+                defense: one,
+                ..Default::default()
+            }),
+            ATTR_DEFENSE
+        );
+        assert_eq!(
+            base(HeroAttribute {
+                mdefense: one,
+                ..Default::default()
+            }),
+            ATTR_MDEFENSE
+        );
+        assert_eq!(
+            ex(HeroExAttribute {
+                cri: one,
+                ..Default::default()
+            }),
+            ATTR_CRI
+        );
+        assert_eq!(
+            ex(HeroExAttribute {
+                recri: one,
+                ..Default::default()
+            }),
+            ATTR_RECRI
+        );
+        assert_eq!(
+            ex(HeroExAttribute {
+                cri_dmg: one,
+                ..Default::default()
+            }),
+            ATTR_CRI_DMG
+        );
+        assert_eq!(
+            ex(HeroExAttribute {
+                cri_def: one,
+                ..Default::default()
+            }),
+            ATTR_CRI_DEF
+        );
+        assert_eq!(
+            ex(HeroExAttribute {
+                add_dmg: one,
+                ..Default::default()
+            }),
+            ATTR_ADD_DMG
+        );
+        assert_eq!(
+            ex(HeroExAttribute {
+                drop_dmg: one,
+                ..Default::default()
+            }),
+            ATTR_DROP_DMG
+        );
+        assert_eq!(
+            sp(HeroSpAttribute {
+                heal: one,
+                ..Default::default()
+            }),
+            ATTR_HEAL
+        );
+        assert_eq!(
+            sp(HeroSpAttribute {
+                defense_ignore: one,
+                ..Default::default()
+            }),
+            ATTR_DEFENSE_IGNORE
+        );
+        assert_eq!(
+            sp(HeroSpAttribute {
+                normal_skill_rate: one,
+                ..Default::default()
+            }),
+            ATTR_NORMAL_SKILL_RATE
+        );
+        assert_eq!(
+            sp(HeroSpAttribute {
+                big_skill_rate: one,
+                ..Default::default()
+            }),
+            ATTR_BIG_SKILL_RATE
+        );
+    }
+}
@@ -1,23 +1,47 @@
 use sonettobuf::{
-    ActEffect, Fight, FightExPointInfo, FightHeroSpAttributeInfo, FightStep, HeroSpAttribute,
-    PlayerSkillInfo,
+    ActEffect, Fight, FightEntityInfo, FightExPointInfo, FightHeroSpAttributeInfo, FightStep,
+    HeroSpAttribute,
 };
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use crate::state::battle::{
+    damage,
     effects::effect_types::EffectType,
     manager::{
         buff_mgr::BuffMgr,
         entity_mgr::{FightEntityDataMgr, get_entity_mut_by_location},
+        ex_point_mgr,
     },
     mechanics::bloodtithe::BloodtitheState,
+    passives::equipment,
+    targets,
 };
 
 #[derive(Default, Debug, Clone)]
 pub struct FightCalculateDataMgr {
     fight: Arc<Fight>,
     entity_mgr: FightEntityDataMgr,
-    buff_mgr: BuffMgr,
+    /// Last unit to damage each unit, carried from round to round
+    last_attackers: HashMap<i64, i64>,
+}
+
+/// Attributes of an entity with its buff modifiers applied. Rates are per mille.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CombatAttr {
+    pub attack: i32,
+    pub defense: i32,
+    pub mdefense: i32,
+    pub cri: i32,
+    pub recri: i32,
+    pub cri_dmg: i32,
+    pub cri_def: i32,
+    pub add_dmg: i32,
+    pub drop_dmg: i32,
+    pub heal: i32,
+    pub defense_ignore: i32,
+    pub normal_skill_rate: i32,
+    pub big_skill_rate: i32,
 }
 
 impl FightCalculateDataMgr {
@@ -25,10 +49,18 @@ impl FightCalculateDataMgr {
         Self {
             fight: fight.clone(),
             entity_mgr: FightEntityDataMgr::new(fight.clone()),
-            buff_mgr: BuffMgr::new(),
+            last_attackers: HashMap::new(),
         }
     }
 
+    pub fn last_attackers(&self) -> &HashMap<i64, i64> {
+        &self.last_attackers
+    }
+
+    pub fn set_last_attackers(&mut self, last_attackers: HashMap<i64, i64>) {
+        self.last_attackers = last_attackers;
+    }
+
     pub fn play_step_data(
         &mut self,
         step: &FightStep,
@@ -37,6 +69,10 @@ impl FightCalculateDataMgr {
         buff_mgr: &mut BuffMgr,
     ) -> Result<(), String> {
         for effect in &step.act_effect {
+            // nested steps note their own attacker when they're played
+            if effect.fight_step.is_none() {
+                targets::record_attack(step.from_id.unwrap_or(0), effect, &mut self.last_attackers);
+            }
             self.play_act_effect_data(effect, fight, bloodtithe, buff_mgr)?;
         }
         Ok(())
@@ -74,6 +110,7 @@ impl FightCalculateDataMgr {
 
             EffectType::Damage
             | EffectType::Crit
+            | EffectType::Dot
             | EffectType::DamageExtra
             | EffectType::OriginDamage
             | EffectType::OriginCrit
@@ -95,20 +132,63 @@ impl FightCalculateDataMgr {
             | EffectType::InjuryBankHeal
             | EffectType::SubHeroLifeChange => self.play_effect_heal(effect, fight),
 
-            EffectType::BuffAdd => self.play_effect_add_buff(effect),
-
-            EffectType::Dead => self.play_effect_death(effect, fight),
-            EffectType::Kill => self.play_effect_kill(effect, fight),
+            EffectType::BuffAdd | EffectType::BuffUpdate | EffectType::BuffDel => {
+                self.play_effect_buff(effect, fight, buff_mgr)
+            }
 
-            EffectType::Shield => self.play_effect_shield(effect, fight),
+            // the buffs they remove come as separate BuffDel effects
+            EffectType::Purify | EffectType::Disperse => Ok(()),
+
+            // statuses live on their buffs, a rejected buff never got added
+            EffectType::Dizzy
+            | EffectType::Frozen
+            | EffectType::Sleep
+            | EffectType::Petrified
+            | EffectType::Silence
+            | EffectType::Seal
+            | EffectType::Disarm
+            | EffectType::Forbid
+            | EffectType::DizzyResist
+            | EffectType::FrozenResist
+            | EffectType::SleepResist
+            | EffectType::PetrifiedResist
+            | EffectType::BuffReject => Ok(()),
+
+            EffectType::Dead => self.play_effect_death(effect, fight, buff_mgr),
+            EffectType::Kill => self.play_effect_kill(effect, fight, buff_mgr),
+
+            EffectType::Shield
+            | EffectType::ShieldChange
+            | EffectType::ShieldValueChange
+            | EffectType::ShieldDel
+            | EffectType::ShieldBroken
+            | EffectType::OverflowHealToShield => self.play_effect_shield(effect, fight),
 
             EffectType::AverageLife => self.play_effect_set_hp(effect, fight),
             EffectType::MaxHpChange => self.play_effect_set_max_hp(effect, fight),
             EffectType::CurrentHpChange => self.play_effect_set_current_hp(effect, fight),
 
-            EffectType::AddExPoint | EffectType::ExPointChange => {
-                self.play_effect_add_ex_point(effect, fight)
-            }
+            EffectType::AddExPoint
+            | EffectType::ExPointAdd
+            | EffectType::ExPointChange
+            | EffectType::ExPointDel => self.play_effect_ex_point(effect, fight, buff_mgr),
+
+            // moxie rules live on their buffs
+            EffectType::ExPointCantAdd
+            | EffectType::ExPointMaxAdd
+            | EffectType::ExPointCardMove
+            | EffectType::ExSkillNoConsumption => Ok(()),
+
+            // the hand is played on the round state
+            EffectType::AddCard
+            | EffectType::ReDealCard
+            | EffectType::CardLevelAdd
+            | EffectType::CardRemove
+            | EffectType::ChangeToTempCard
+            | EffectType::UniversalCard => Ok(()),
+
+            // cloth power lives on the team, see `cloth`
+            EffectType::PowerChange => Ok(()),
 
             EffectType::BloodPoolMaxCreate => self.play_effect_bloodtithe_enable(effect),
             EffectType::BloodPoolMaxChange => self.play_effect_bloodtithe_max(effect),
@@ -186,18 +266,35 @@ impl FightCalculateDataMgr {
         Ok(())
     }
 
-    fn play_effect_add_buff(&mut self, effect: &ActEffect) -> Result<(), String> {
+    fn play_effect_buff(
+        &mut self,
+        effect: &ActEffect,
+        fight: &mut Fight,
+        buff_mgr: &mut BuffMgr,
+    ) -> Result<(), String> {
         let target_id = effect.target_id.ok_or("No target ID")?;
-        let buff_id = effect.effect_num.ok_or("No buff ID")?;
-
-        let from_uid = effect.buff.as_ref().and_then(|b| b.from_uid).unwrap_or(0);
 
-        self.buff_mgr.add_buff(target_id, buff_id, from_uid);
+        buff_mgr.apply_effect(effect);
+        self.sync_entity_buffs(target_id, fight, buff_mgr);
 
         Ok(())
     }
 
-    fn play_effect_death(&mut self, effect: &ActEffect, fight: &mut Fight) -> Result<(), String> {
+    /// Keep `FightEntityInfo::buffs` in line with the buff manager
+    fn sync_entity_buffs(&self, uid: i64, fight: &mut Fight, buff_mgr: &BuffMgr) {
+        if let Some(location) = self.entity_mgr.get_location(uid)
+            && let Some(entity) = get_entity_mut_by_location(fight, location)
+        {
+            entity.buffs = buff_mgr.buff_infos(uid);
+        }
+    }
+
+    fn play_effect_death(
+        &mut self,
+        effect: &ActEffect,
+        fight: &mut Fight,
+        buff_mgr: &mut BuffMgr,
+    ) -> Result<(), String> {
         let target_id = effect.target_id.ok_or("No target ID")?;
 
         let location = self
@@ -209,13 +306,19 @@ impl FightCalculateDataMgr {
             .ok_or_else(|| format!("Failed to get entity {} mutably", target_id))?;
 
         entity.current_hp = Some(0);
-        self.buff_mgr.clear_dead(target_id);
+        entity.buffs.clear();
+        buff_mgr.clear_dead(target_id);
 
         tracing::trace!("Entity died: target={}", target_id);
         Ok(())
     }
 
-    fn play_effect_kill(&mut self, effect: &ActEffect, fight: &mut Fight) -> Result<(), String> {
+    fn play_effect_kill(
+        &mut self,
+        effect: &ActEffect,
+        fight: &mut Fight,
+        buff_mgr: &mut BuffMgr,
+    ) -> Result<(), String> {
         let target_id = effect.target_id.ok_or("No target ID")?;
 
         let location = self
@@ -227,18 +330,37 @@ impl FightCalculateDataMgr {
             .ok_or_else(|| format!("Failed to get entity {} mutably", target_id))?;
 
         entity.current_hp = Some(0);
-        self.buff_mgr.clear_dead(target_id);
+        entity.buffs.clear();
+        buff_mgr.clear_dead(target_id);
 
         tracing::trace!("Entity killed: target={}", target_id);
         Ok(())
     }
 
-    fn play_effect_shield(&mut self, effect: &ActEffect, _fight: &mut Fight) -> Result<(), String> {
+    /// `Shield` and `OverflowHealToShield` add to the shield, `ShieldChange` sets what is
+    /// left after a hit, `ShieldDel` and `ShieldBroken` clear it
+    fn play_effect_shield(&mut self, effect: &ActEffect, fight: &mut Fight) -> Result<(), String> {
         let target_id = effect.target_id.ok_or("No target ID")?;
-        let shield = effect.effect_num.ok_or("No shield amount")?;
+        let amount = effect.effect_num.unwrap_or(0);
+        let effect_type = EffectType::from(effect.effect_type.unwrap_or(0));
 
-        tracing::trace!("Shield applied: target={}, shield={}", target_id, shield);
-        // TODO: Add shield to entity
+        let location = self
+            .entity_mgr
+            .get_location(target_id)
+            .ok_or_else(|| format!("Entity {} not found", target_id))?;
+
+        let entity = get_entity_mut_by_location(fight, location)
+            .ok_or_else(|| format!("Failed to get entity {} mutably", target_id))?;
+
+        let current = entity.shield_value.unwrap_or(0);
+        let shield = match effect_type {
+            EffectType::Shield | EffectType::OverflowHealToShield => current + amount,
+            EffectType::ShieldChange | EffectType::ShieldValueChange => amount,
+            _ => 0,
+        };
+        entity.shield_value = Some(shield.max(0));
+
+        tracing::trace!("Shield set: target={}, shield={}", target_id, shield);
         Ok(())
     }
 
@@ -295,16 +417,29 @@ impl FightCalculateDataMgr {
         self.play_effect_set_hp(effect, fight)
     }
 
-    fn play_effect_add_ex_point(
+    fn play_effect_ex_point(
         &mut self,
         effect: &ActEffect,
-        _fight: &mut Fight,
+        fight: &mut Fight,
+        buff_mgr: &BuffMgr,
     ) -> Result<(), String> {
         let target_id = effect.target_id.ok_or("No target ID")?;
-        let ex_point = effect.effect_num.unwrap_or(0);
 
-        tracing::trace!("EX point added: target={}, amount={}", target_id, ex_point);
-        // TODO: Add EX points to entity
+        let location = self
+            .entity_mgr
+            .get_location(target_id)
+            .ok_or_else(|| format!("Entity {} not found", target_id))?;
+
+        let entity = get_entity_mut_by_location(fight, location)
+            .ok_or_else(|| format!("Failed to get entity {} mutably", target_id))?;
+
+        ex_point_mgr::apply_effect(entity, effect, buff_mgr);
+
+        tracing::trace!(
+            "EX point changed: target={}, ex_point={:?}",
+            target_id,
+            entity.ex_point
+        );
         Ok(())
     }
 
@@ -352,41 +487,17 @@ impl FightCalculateDataMgr {
         let mut info = Vec::new();
 
         if let Some(ref attacker) = fight.attacker {
-            for entity in &attacker.entitys {
-                info.push(FightExPointInfo {
-                    uid: entity.uid,
-                    ex_point: entity.ex_point,
-                    power_infos: vec![],
-                    current_hp: entity.current_hp,
-                    ex_point_type: if entity.model_id == Some(3120) {
-                        Some(1)
-                    } else {
-                        Some(0)
-                    },
-                });
-            }
-
-            for entity in &attacker.sub_entitys {
-                info.push(FightExPointInfo {
-                    uid: entity.uid,
-                    ex_point: entity.ex_point,
-                    power_infos: vec![],
-                    current_hp: entity.current_hp,
-                    ex_point_type: Some(0),
-                });
-            }
+            info.extend(
+                attacker
+                    .entitys
+                    .iter()
+                    .chain(attacker.sub_entitys.iter())
+                    .map(ex_point_mgr::ex_point_info),
+            );
         }
 
         if let Some(ref defender) = fight.defender {
-            for entity in &defender.entitys {
-                info.push(FightExPointInfo {
-                    uid: entity.uid,
-                    ex_point: entity.ex_point,
-                    power_infos: vec![],
-                    current_hp: entity.current_hp,
-                    ex_point_type: Some(0),
-                });
-            }
+            info.extend(defender.entitys.iter().map(ex_point_mgr::ex_point_info));
         }
 
         info
@@ -436,27 +547,46 @@ impl FightCalculateDataMgr {
 
         attrs
     }
-
-    pub fn build_player_skills(&mut self) -> Vec<PlayerSkillInfo> {
-        vec![
-            PlayerSkillInfo {
-                skill_id: Some(30010201),
-                cd: Some(0),
-                need_power: Some(40),
-                r#type: Some(0),
-            },
-            PlayerSkillInfo {
-                skill_id: Some(30010202),
-                cd: Some(0),
-                need_power: Some(25),
-                r#type: Some(0),
-            },
-        ]
-    }
 }
 
 impl FightCalculateDataMgr {
-    pub fn on_round_end(&mut self) {
-        self.buff_mgr.on_round_end();
+    /// Attributes `entity` fights with, its base attributes scaled by its buffs and the
+    /// stat lines of its psychube
+    pub fn combat_attr(entity: &FightEntityInfo, buff_mgr: &BuffMgr) -> CombatAttr {
+        let attr = entity.attr.unwrap_or_default();
+        let ex = damage::base_ex_attr(entity);
+        let mut mods = buff_mgr.attr_modifiers(entity.uid.unwrap_or(0));
+        mods += equipment::attr_modifiers(entity);
+        let scale =
+            |base: i32, rate: i32| (base as i64 * (1000 + rate) as i64 / 1000).max(0) as i32;
+
+        CombatAttr {
+            attack: scale(attr.attack.unwrap_or(0), mods.attack),
+            defense: scale(attr.defense.unwrap_or(0), mods.defense),
+            mdefense: scale(attr.mdefense.unwrap_or(0), mods.mdefense),
+            cri: ex.cri.unwrap_or(0) + mods.cri,
+            recri: ex.recri.unwrap_or(0) + mods.recri,
+            cri_dmg: ex.cri_dmg.unwrap_or(0) + mods.cri_dmg,
+            cri_def: ex.cri_def.unwrap_or(0) + mods.cri_def,
+            add_dmg: ex.add_dmg.unwrap_or(0) + mods.add_dmg,
+            drop_dmg: ex.drop_dmg.unwrap_or(0) + mods.drop_dmg,
+            heal: mods.heal,
+            defense_ignore: mods.defense_ignore,
+            normal_skill_rate: mods.normal_skill_rate,
+            big_skill_rate: mods.big_skill_rate,
+        }
+    }
+
+    /// Tick buffs down at the end of a round and return the effects for the changes
+    pub fn on_round_end(&mut self, fight: &mut Fight, buff_mgr: &mut BuffMgr) -> Vec<ActEffect> {
+        let effects = buff_mgr.on_round_end();
+
+        let mut uids: Vec<i64> = effects.iter().filter_map(|e| e.target_id).collect();
+        uids.dedup();
+        for uid in uids {
+            self.sync_entity_buffs(uid, fight, buff_mgr);
+        }
+
+        effects
     }
 }
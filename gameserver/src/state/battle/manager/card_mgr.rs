@@ -1,17 +1,24 @@
 use anyhow::Result;
-use rand::Rng;
 use rand::rngs::StdRng;
-use sonettobuf::{ActEffect, BeginRoundOper, Fight, FightStep, fight_step};
+use sonettobuf::{ActEffect, BeginRoundOper, CardInfo, Fight, FightStep, fight_step};
 use std::sync::Arc;
 
 use crate::state::battle::{
-    effects::effect_types::EffectType, manager::skill_mgr::FightSkillMgr, round::RoundState,
+    ai,
+    effects::effect_types::EffectType,
+    manager::{buff_mgr::BuffMgr, deck_mgr, ex_point_mgr, skill_mgr::FightSkillMgr},
+    mechanics::status,
+    round::RoundState,
+    targets,
+    uids::UidCounter,
 };
 
 #[derive(Default, Debug, Clone)]
 pub struct FightCardMgr {
     fight: Arc<Fight>,
     skill_mgr: FightSkillMgr,
+    /// Uids of the cards dealt during the fight
+    pub uids: UidCounter,
 }
 
 impl FightCardMgr {
@@ -19,6 +26,7 @@ impl FightCardMgr {
         Self {
             skill_mgr: FightSkillMgr::new(fight.clone()),
             fight,
+            uids: UidCounter::default(),
         }
     }
 
@@ -64,6 +72,8 @@ impl FightCardMgr {
             2 => {
                 if oper.to_id.unwrap_or(0) != 0 {
                     self.play_card(rng, state, oper).await
+                } else if oper.param2.is_some() {
+                    Ok(self.move_card(state, oper))
                 } else {
                     Ok(self.select_card(oper))
                 }
@@ -90,9 +100,51 @@ impl FightCardMgr {
         }
     }
 
+    /// Move the card at `param1` to `param2`, merging whatever ends up side by side
+    fn move_card(&self, state: &mut RoundState, oper: BeginRoundOper) -> FightStep {
+        let from = oper.param1.unwrap_or(0).max(0) as usize;
+        let to = oper.param2.unwrap_or(0).max(0) as usize;
+
+        if state.act_point <= 0 {
+            tracing::warn!("Card move {} -> {} without AP", from, to);
+            return FightStep::default();
+        }
+
+        let Some(hero_id) = state.player_deck.get(from).and_then(|c| c.hero_id) else {
+            return FightStep::default();
+        };
+        if !deck_mgr::move_card(&mut state.player_deck, from, to) {
+            return FightStep::default();
+        }
+
+        state.act_point -= 1;
+        state.move_num += 1;
+
+        let mut effects = Vec::new();
+        if let Some(hero_uid) = state.hero_uids.iter().copied().find(|uid| {
+            state
+                .get_entity(*uid)
+                .is_some_and(|e| e.model_id == Some(hero_id))
+        }) {
+            effects.extend(ex_point_mgr::on_card_move(state, hero_uid));
+        }
+        effects.extend(deck_mgr::merge_hand(state));
+
+        if effects.is_empty() {
+            return FightStep::default();
+        }
+
+        FightStep {
+            act_type: Some(fight_step::ActType::Effect.into()),
+            act_effect: effects,
+            card_index: Some(to as i32),
+            ..Default::default()
+        }
+    }
+
     async fn play_card(
         &self,
-        _rng: &mut StdRng,
+        rng: &mut StdRng,
         state: &mut RoundState,
         oper: BeginRoundOper,
     ) -> Result<FightStep> {
@@ -104,22 +156,45 @@ impl FightCardMgr {
             None => return Ok(FightStep::default()),
         };
 
-        // remove it from hand
-        state.player_deck.remove(card_index);
+        if state.act_point <= 0 {
+            tracing::warn!("Card {} played without AP", card_index);
+            return Ok(FightStep::default());
+        }
 
         let hero_id = card.hero_id.unwrap_or(0);
+        let skill_id = card.skill_id.unwrap_or(0);
 
-        let caster_uid = state
+        let caster = state
             .iter_entities()
             .find(|e| e.model_id == Some(hero_id) && e.uid.unwrap_or(0) > 0)
-            .and_then(|e| e.uid)
             .ok_or_else(|| anyhow::anyhow!("No entity for hero {}", hero_id))?;
+        let caster_uid = caster.uid.unwrap_or(0);
 
-        let skill_id = card.skill_id.unwrap_or(0);
+        if !status::can_use_skill(&state.buff_mgr, caster, skill_id) {
+            tracing::warn!(
+                "Card {} of {} blocked by {:?}",
+                skill_id,
+                caster_uid,
+                status::statuses(&state.buff_mgr, caster_uid)
+            );
+            return Ok(FightStep::default());
+        }
+
+        // remove it from hand
+        state.player_deck.remove(card_index);
+
+        let ex_point_effects = ex_point_mgr::on_card_played(state, caster_uid, skill_id);
 
         let mut step = self
             .skill_mgr
-            .execute_skill(state, caster_uid, target_uid, skill_id)?;
+            .execute_skill(state, caster_uid, target_uid, skill_id, rng)?;
+        ex_point_mgr::apply_step(state, &step);
+        targets::record_attacks(&step, &mut state.last_attackers);
+        step.act_effect.splice(0..0, ex_point_effects);
+
+        // the cards left and right of the played one may merge now
+        let merge_effects = deck_mgr::apply_step(state, &step);
+        step.act_effect.extend(merge_effects);
 
         state.used_cards.push(card_index as i32);
         state.act_point = (state.act_point - 1).max(0);
@@ -135,53 +210,51 @@ impl FightCardMgr {
     ) -> Result<Vec<FightStep>> {
         let mut steps = Vec::new();
 
-        let players: Vec<i64> = state
-            .iter_entities()
-            .filter(|e| e.uid.unwrap_or(0) > 0 && e.current_hp.unwrap_or(0) > 0)
-            .filter_map(|e| e.uid)
-            .collect();
-
-        if players.is_empty() {
-            return Ok(steps);
-        }
-
         for i in 0..state.ai_cards.len() {
-            let (caster_uid, skill_id, target_uid_opt) = {
+            let (caster_uid, skill_id, planned_target) = {
                 let card = &state.ai_cards[i];
                 (
                     card.uid.unwrap_or(0),
                     card.skill_id.unwrap_or(0),
-                    card.target_uid,
+                    card.target_uid.unwrap_or(0),
                 )
             };
 
-            if caster_uid >= 0 {
+            if caster_uid >= 0 || skill_id == 0 {
                 continue;
             }
 
             let Some(caster) = state.get_entity(caster_uid) else {
                 continue;
             };
-            if caster.current_hp.unwrap_or(0) <= 0 {
+            if caster.current_hp.unwrap_or(0) <= 0
+                || !status::can_use_skill(&state.buff_mgr, caster, skill_id)
+            {
                 continue;
             }
 
-            if skill_id == 0 {
-                continue;
-            }
-
-            let target_uid = match target_uid_opt {
-                Some(t) if t != 0 => t,
-                _ => {
-                    let t = players[rng.gen_range(0..players.len())];
-                    state.ai_cards[i].target_uid = Some(t);
-                    t
+            // the planned target may have died or lost its selectability since
+            let target_uid = {
+                let entities: Vec<_> = state.iter_entities().collect();
+                if ai::is_valid_target(&entities, &state.buff_mgr, planned_target) {
+                    Some(planned_target)
+                } else {
+                    ai::pick_target(&entities, &state.buff_mgr, skill_id, rng)
                 }
             };
+            let Some(target_uid) = target_uid else {
+                break;
+            };
+            state.ai_cards[i].target_uid = Some(target_uid);
+
+            let ex_point_effects = ex_point_mgr::on_card_played(state, caster_uid, skill_id);
 
-            let step = self
+            let mut step = self
                 .skill_mgr
-                .execute_skill(state, caster_uid, target_uid, skill_id)?;
+                .execute_skill(state, caster_uid, target_uid, skill_id, rng)?;
+            ex_point_mgr::apply_step(state, &step);
+            targets::record_attacks(&step, &mut state.last_attackers);
+            step.act_effect.splice(0..0, ex_point_effects);
 
             steps.push(step);
         }
@@ -189,6 +262,22 @@ impl FightCardMgr {
         Ok(steps)
     }
 
+    /// Enemy cards for the fight's current round, sent to the client as the enemy intent
+    pub fn plan_ai_cards(
+        &self,
+        rng: &mut StdRng,
+        fight: &Fight,
+        buff_mgr: &BuffMgr,
+    ) -> Vec<CardInfo> {
+        let entities: Vec<_> = [&fight.attacker, &fight.defender]
+            .into_iter()
+            .flatten()
+            .flat_map(|team| team.entitys.iter())
+            .collect();
+
+        ai::plan_cards(&entities, buff_mgr, fight.cur_round.unwrap_or(1), rng)
+    }
+
     fn change_hero(&self) -> FightStep {
         FightStep {
             act_type: Some(fight_step::ActType::Changehero.into()),
@@ -0,0 +1,348 @@
+//! Player hand
+//!
+//! The hand is the card list the client shows, the server keeps it in the same order:
+//! - Cards rank up along the skill group of their hero: `skill_group[0]` is rank 1,
+//!   `skill_group[1]` rank 2 and `skill_group[2]` rank 3
+//! - Two adjacent cards of the same skill and rank merge into one card a rank higher,
+//!   again and again until no pair is left. A universal card merges with any incantation
+//!   next to it, ultimates never merge.
+//! - Playing or moving a card costs one AP, moving it may lead to new merges
+//! - At the end of each round the cards of dead heroes leave the hand and it is refilled
+//!   up to the hand size with rank 1 cards of the living heroes
+//!
+//! Card effects of skills act on the hand: `CardLevelAdd` ranks up the first `n` cards
+//! that can still rank up, `CardRemove` drops the last `n` cards, `ChangeToTempCard`
+//! makes the first `n` cards temporary and `UniversalCard` makes the first `n` cards
+//! universal.
+//!
+//! Mind's Eye skills draw cards past the hand size or redeal the incantations in it.
+
+use rand::Rng;
+use rand::rngs::StdRng;
+use sonettobuf::{ActEffect, CardInfo, FightEntityInfo, FightStep, effect_type_enum::EffectType};
+
+use crate::state::battle::{
+    cards, damage::SkillKind, manager::ex_point_mgr, round::RoundState, uids::UidCounter,
+};
+
+pub const MAX_RANK: i32 = 3;
+
+/// `card_effect` of a universal card
+const UNIVERSAL_CARD_EFFECT: i32 = 1;
+
+fn hero_of<'a>(heroes: &[&'a FightEntityInfo], card: &CardInfo) -> Option<&'a FightEntityInfo> {
+    heroes.iter().copied().find(|h| h.model_id == card.hero_id)
+}
+
+/// Rank of an incantation card, `None` for ultimates and unknown skills
+fn rank_of(heroes: &[&FightEntityInfo], card: &CardInfo) -> Option<i32> {
+    let hero = hero_of(heroes, card)?;
+
+    match SkillKind::of(hero, card.skill_id.unwrap_or(0)) {
+        SkillKind::Incantation(rank) => Some(rank),
+        _ => None,
+    }
+}
+
+/// `skill_id` one rank higher, `None` at the top rank
+fn ranked_up(heroes: &[&FightEntityInfo], card: &CardInfo) -> Option<i32> {
+    let hero = hero_of(heroes, card)?;
+    let skill_id = card.skill_id.unwrap_or(0);
+
+    [&hero.skill_group1, &hero.skill_group2]
+        .into_iter()
+        .find_map(|group| {
+            let idx = group.iter().position(|&id| id == skill_id)?;
+            group.get(idx + 1).copied()
+        })
+        .filter(|_| rank_of(heroes, card).is_some_and(|r| r < MAX_RANK))
+}
+
+fn is_universal(card: &CardInfo) -> bool {
+    card.card_effect == Some(UNIVERSAL_CARD_EFFECT)
+}
+
+fn can_merge(heroes: &[&FightEntityInfo], left: &CardInfo, right: &CardInfo) -> bool {
+    let (Some(left_rank), Some(right_rank)) = (rank_of(heroes, left), rank_of(heroes, right))
+    else {
+        return false;
+    };
+
+    if is_universal(left) {
+        return right_rank < MAX_RANK;
+    }
+    if is_universal(right) {
+        return left_rank < MAX_RANK;
+    }
+
+    left.hero_id == right.hero_id && left.skill_id == right.skill_id && left_rank < MAX_RANK
+}
+
+/// Merge adjacent pairs until none are left, returns the hero of every merge
+fn merge(hand: &mut Vec<CardInfo>, heroes: &[&FightEntityInfo]) -> Vec<i32> {
+    let mut merged = Vec::new();
+    let mut i = 0;
+
+    while i + 1 < hand.len() {
+        if !can_merge(heroes, &hand[i], &hand[i + 1]) {
+            i += 1;
+            continue;
+        }
+
+        // a universal card takes the shape of the card it merges with
+        let right = hand.remove(i + 1);
+        if is_universal(&hand[i]) && !is_universal(&right) {
+            hand[i] = right;
+        }
+        hand[i].card_effect = Some(0);
+
+        if let Some(skill_id) = ranked_up(heroes, &hand[i]) {
+            hand[i].skill_id = Some(skill_id);
+        }
+        merged.push(hand[i].hero_id.unwrap_or(0));
+
+        i = i.saturating_sub(1);
+    }
+
+    merged
+}
+
+fn new_card(uids: &UidCounter, hero: &FightEntityInfo, skill_id: i32) -> CardInfo {
+    CardInfo {
+        uid: Some(uids.next()),
+        hero_id: hero.model_id,
+        skill_id: Some(skill_id),
+        card_type: Some(0),
+        status: Some(0),
+        temp_card: Some(hero.uid.unwrap_or(0) < 0),
+        enchants: vec![],
+        target_uid: Some(0),
+        energy: Some(0),
+        extra_infos: vec![],
+        area_red_or_blue: Some(0),
+        heat_id: Some(0),
+        card_effect: None,
+        extra_info: None,
+    }
+}
+
+fn alive<'a>(heroes: &[&'a FightEntityInfo]) -> Vec<&'a FightEntityInfo> {
+    heroes
+        .iter()
+        .copied()
+        .filter(|h| h.current_hp.unwrap_or(0) > 0)
+        .collect()
+}
+
+/// Push rank 1 cards of random living heroes until the hand holds `size` cards
+fn deal(
+    rng: &mut StdRng,
+    uids: &UidCounter,
+    hand: &mut Vec<CardInfo>,
+    alive: &[&FightEntityInfo],
+    size: usize,
+) {
+    let candidates: Vec<(&FightEntityInfo, i32)> = alive
+        .iter()
+        .flat_map(|h| {
+            [&h.skill_group1, &h.skill_group2]
+                .into_iter()
+                .filter_map(move |group| group.first().map(|&skill_id| (*h, skill_id)))
+        })
+        .collect();
+    if candidates.is_empty() {
+        return;
+    }
+
+    while hand.len() < size {
+        let (hero, skill_id) = candidates[rng.gen_range(0..candidates.len())];
+        hand.push(new_card(uids, hero, skill_id));
+    }
+}
+
+/// Drop the cards of dead heroes and refill the hand with rank 1 cards of the living ones
+fn refill(
+    rng: &mut StdRng,
+    uids: &UidCounter,
+    hand: &mut Vec<CardInfo>,
+    heroes: &[&FightEntityInfo],
+) {
+    let alive = alive(heroes);
+
+    hand.retain(|card| hero_of(&alive, card).is_some());
+
+    let hand_size = cards::compute_max_cards(alive.len());
+    deal(rng, uids, hand, &alive, hand_size);
+}
+
+/// Move the card at `from` to `to`, `false` when either index is outside the hand
+pub fn move_card(hand: &mut Vec<CardInfo>, from: usize, to: usize) -> bool {
+    if from >= hand.len() || to >= hand.len() {
+        return false;
+    }
+
+    let card = hand.remove(from);
+    hand.insert(to, card);
+    true
+}
+
+fn card_effect(effect_type: EffectType, caster_uid: i64, count: i32) -> ActEffect {
+    ActEffect {
+        effect_type: Some(effect_type as i32),
+        target_id: Some(caster_uid),
+        effect_num: Some(count),
+        ..Default::default()
+    }
+}
+
+/// Hand effect of a card behavior, `None` for behaviors that don't touch the hand
+pub fn behavior_effect(behavior_type: &str, caster_uid: i64, count: i32) -> Option<ActEffect> {
+    let effect_type = match behavior_type {
+        "CardLevelAdd" => EffectType::Cardleveladd,
+        "CardRemove" => EffectType::Cardremove,
+        "ChangeToTempCard" => EffectType::Changetotempcard,
+        "UniversalCard" => EffectType::Universalcard,
+        _ => return None,
+    };
+
+    Some(card_effect(effect_type, caster_uid, count.max(1)))
+}
+
+/// Apply a card effect to the hand
+fn apply_effect(hand: &mut Vec<CardInfo>, heroes: &[&FightEntityInfo], effect: &ActEffect) {
+    let count = effect.effect_num.unwrap_or(1).max(0) as usize;
+    let effect_type = effect.effect_type.unwrap_or(0);
+
+    if effect_type == EffectType::Cardleveladd as i32 {
+        let upgrades: Vec<(usize, i32)> = hand
+            .iter()
+            .enumerate()
+            .filter_map(|(i, card)| ranked_up(heroes, card).map(|skill_id| (i, skill_id)))
+            .take(count)
+            .collect();
+
+        for (i, skill_id) in upgrades {
+            hand[i].skill_id = Some(skill_id);
+        }
+    } else if effect_type == EffectType::Cardremove as i32 {
+        hand.truncate(hand.len().saturating_sub(count));
+    } else if effect_type == EffectType::Changetotempcard as i32 {
+        for card in hand.iter_mut().take(count) {
+            card.temp_card = Some(true);
+        }
+    } else if effect_type == EffectType::Universalcard as i32 {
+        for card in hand
+            .iter_mut()
+            .filter(|c| rank_of(heroes, c).is_some())
+            .take(count)
+        {
+            card.card_effect = Some(UNIVERSAL_CARD_EFFECT);
+        }
+    }
+}
+
+fn heroes_of(state: &RoundState) -> Vec<FightEntityInfo> {
+    state
+        .hero_uids
+        .iter()
+        .filter_map(|uid| state.get_entity(*uid))
+        .cloned()
+        .collect()
+}
+
+/// Merge the hand and give every merged hero its moxie
+pub fn merge_hand(state: &mut RoundState) -> Vec<ActEffect> {
+    let heroes = heroes_of(state);
+    let heroes: Vec<&FightEntityInfo> = heroes.iter().collect();
+
+    let merged = merge(&mut state.player_deck, &heroes);
+
+    merged
+        .into_iter()
+        .filter_map(|hero_id| {
+            let uid = heroes.iter().find(|h| h.model_id == Some(hero_id))?.uid?;
+            ex_point_mgr::on_merge(state, uid)
+        })
+        .collect()
+}
+
+/// Refill the hand for the next round, then merge it
+pub fn refill_hand(rng: &mut StdRng, state: &mut RoundState) -> Vec<ActEffect> {
+    let heroes = heroes_of(state);
+    let heroes: Vec<&FightEntityInfo> = heroes.iter().collect();
+
+    refill(rng, &state.card_uids, &mut state.player_deck, &heroes);
+
+    merge_hand(state)
+}
+
+/// Draw `count` rank 1 cards past the hand size, then merge the hand
+pub fn draw_cards(rng: &mut StdRng, state: &mut RoundState, count: usize) -> Vec<ActEffect> {
+    let heroes = heroes_of(state);
+    let heroes: Vec<&FightEntityInfo> = heroes.iter().collect();
+
+    let start = state.player_deck.len();
+    deal(
+        rng,
+        &state.card_uids,
+        &mut state.player_deck,
+        &alive(&heroes),
+        start + count,
+    );
+
+    let mut effects = vec![ActEffect {
+        effect_type: Some(EffectType::Addcard as i32),
+        card_info_list: state.player_deck[start..].to_vec(),
+        team_type: Some(1),
+        ..Default::default()
+    }];
+    effects.extend(merge_hand(state));
+    effects
+}
+
+/// Swap every incantation in the hand for a new rank 1 card, then merge the hand.
+/// Ultimates stay.
+pub fn redeal_hand(rng: &mut StdRng, state: &mut RoundState) -> Vec<ActEffect> {
+    let heroes = heroes_of(state);
+    let heroes: Vec<&FightEntityInfo> = heroes.iter().collect();
+
+    let size = state.player_deck.len();
+    state
+        .player_deck
+        .retain(|card| rank_of(&heroes, card).is_none());
+    deal(
+        rng,
+        &state.card_uids,
+        &mut state.player_deck,
+        &alive(&heroes),
+        size,
+    );
+
+    let mut effects = vec![ActEffect {
+        effect_type: Some(EffectType::Redealcard as i32),
+        card_info_list: state.player_deck.clone(),
+        team_type: Some(1),
+        ..Default::default()
+    }];
+    effects.extend(merge_hand(state));
+    effects
+}
+
+/// Play the card effects of a skill on the hand, then merge it
+pub fn apply_step(state: &mut RoundState, step: &FightStep) -> Vec<ActEffect> {
+    let heroes = heroes_of(state);
+    let heroes: Vec<&FightEntityInfo> = heroes.iter().collect();
+
+    apply_card_effects(&mut state.player_deck, &heroes, step);
+
+    merge_hand(state)
+}
+
+fn apply_card_effects(hand: &mut Vec<CardInfo>, heroes: &[&FightEntityInfo], step: &FightStep) {
+    for effect in &step.act_effect {
+        match &effect.fight_step {
+            Some(nested) => apply_card_effects(hand, heroes, nested),
+            None => apply_effect(hand, heroes, effect),
+        }
+    }
+}
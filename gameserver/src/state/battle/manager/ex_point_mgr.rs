@@ -0,0 +1,230 @@
+//! Moxie (EX points)
+//!
+//! Every moxie change goes through here so it stays between 0 and the cap of the unit:
+//! - The cap is `uniqueSkillPoint` of `character` (heroes) or `monster_skill_template`
+//!   (enemies), plus `expoint_max_add` of the entity and its `ExPointMaxAdd` buffs
+//! - Playing an incantation gives its caster 1 moxie, merging two cards gives 1 to their
+//!   hero, moving a card gives 1 only under an `ExPointCardMove` buff
+//! - `ExPointCantAdd` blocks every gain
+//! - Casting the ultimate uses up the cap worth of moxie, unless `ExSkillNoConsumption`
+//! - A hero at full moxie gets its ultimate card added to the hand at the end of the round
+//!
+//! Gains go out as `AddExPoint`, consumption as `ExPointDel`, both carrying the amount
+//! that actually changed, and `apply_effect` replays them on the fight.
+
+use sonettobuf::{
+    ActEffect, CardInfo, Fight, FightEntityInfo, FightExPointInfo, FightStep,
+    effect_type_enum::EffectType,
+};
+
+use crate::state::battle::{damage::SkillKind, manager::buff_mgr::BuffMgr, round::RoundState};
+
+const DEFAULT_MAX_EX_POINT: i32 = 5;
+const CARD_GAIN: i32 = 1;
+const MERGE_GAIN: i32 = 1;
+const MOVE_GAIN: i32 = 1;
+
+const EX_POINT_MAX_ADD: &str = "ExPointMaxAdd";
+const EX_POINT_CANT_ADD: &str = "ExPointCantAdd";
+const EX_POINT_CARD_MOVE: &str = "ExPointCardMove";
+const EX_SKILL_NO_CONSUMPTION: &str = "ExSkillNoConsumption";
+
+/// `uniqueSkillPoint` of the unit, before any bonus
+fn base_max_ex_point(entity: &FightEntityInfo) -> i32 {
+    let game_data = config::configs::get();
+    let model_id = entity.model_id.unwrap_or(0);
+
+    let point = if entity.entity_type == Some(2) {
+        game_data
+            .monster
+            .get(model_id)
+            .and_then(|m| game_data.monster_skill_template.get(m.skill_template))
+            .map(|t| t.unique_skill_point)
+    } else {
+        game_data.character.get(model_id).and_then(|c| {
+            c.unique_skill_point
+                .split('#')
+                .next()
+                .and_then(|p| p.parse().ok())
+        })
+    };
+
+    point.filter(|p| *p > 0).unwrap_or(DEFAULT_MAX_EX_POINT)
+}
+
+pub fn max_ex_point(entity: &FightEntityInfo, buff_mgr: &BuffMgr) -> i32 {
+    let uid = entity.uid.unwrap_or(0);
+
+    base_max_ex_point(entity)
+        + entity.expoint_max_add.unwrap_or(0)
+        + buff_mgr.feature_total(uid, EX_POINT_MAX_ADD)
+}
+
+pub fn is_full(entity: &FightEntityInfo, buff_mgr: &BuffMgr) -> bool {
+    entity.ex_point.unwrap_or(0) >= max_ex_point(entity, buff_mgr)
+}
+
+fn ex_point_effect(effect_type: EffectType, uid: i64, amount: i32) -> ActEffect {
+    ActEffect {
+        effect_type: Some(effect_type as i32),
+        target_id: Some(uid),
+        effect_num: Some(amount),
+        ..Default::default()
+    }
+}
+
+/// How much of `amount` the unit can still take
+fn allowed_gain(entity: &FightEntityInfo, buff_mgr: &BuffMgr, amount: i32) -> i32 {
+    if buff_mgr.has_feature(entity.uid.unwrap_or(0), EX_POINT_CANT_ADD) {
+        return 0;
+    }
+
+    let room = max_ex_point(entity, buff_mgr) - entity.ex_point.unwrap_or(0);
+    amount.min(room).max(0)
+}
+
+/// Give `uid` up to `amount` moxie right away, `None` when nothing was gained
+pub fn gain(state: &mut RoundState, uid: i64, amount: i32) -> Option<ActEffect> {
+    let entity = state.get_entity(uid)?;
+    let gained = allowed_gain(entity, &state.buff_mgr, amount);
+    if gained <= 0 {
+        return None;
+    }
+
+    let entity = state.get_entity_mut(uid)?;
+    entity.ex_point = Some(entity.ex_point.unwrap_or(0) + gained);
+
+    Some(ex_point_effect(EffectType::Addexpoint, uid, gained))
+}
+
+/// Moxie change of `caster` playing `skill_id`: the ultimate consumes, anything else gains
+pub fn on_card_played(state: &mut RoundState, caster_uid: i64, skill_id: i32) -> Vec<ActEffect> {
+    let Some(caster) = state.get_entity(caster_uid) else {
+        return vec![];
+    };
+
+    if SkillKind::of(caster, skill_id) != SkillKind::Ultimate {
+        return gain(state, caster_uid, CARD_GAIN).into_iter().collect();
+    }
+
+    if state
+        .buff_mgr
+        .has_feature(caster_uid, EX_SKILL_NO_CONSUMPTION)
+    {
+        return vec![];
+    }
+
+    let cost = max_ex_point(caster, &state.buff_mgr).min(caster.ex_point.unwrap_or(0));
+    if cost <= 0 {
+        return vec![];
+    }
+
+    if let Some(caster) = state.get_entity_mut(caster_uid) {
+        caster.ex_point = Some(caster.ex_point.unwrap_or(0) - cost);
+    }
+
+    vec![ex_point_effect(EffectType::Expointdel, caster_uid, cost)]
+}
+
+pub fn on_merge(state: &mut RoundState, hero_uid: i64) -> Option<ActEffect> {
+    gain(state, hero_uid, MERGE_GAIN)
+}
+
+pub fn on_card_move(state: &mut RoundState, hero_uid: i64) -> Option<ActEffect> {
+    if !state.buff_mgr.has_feature(hero_uid, EX_POINT_CARD_MOVE) {
+        return None;
+    }
+
+    gain(state, hero_uid, MOVE_GAIN)
+}
+
+/// Replay a moxie effect on `entity`. Changes from passives (`ExPointChange`) are
+/// clamped here as well.
+pub fn apply_effect(entity: &mut FightEntityInfo, effect: &ActEffect, buff_mgr: &BuffMgr) {
+    let amount = effect.effect_num.unwrap_or(0);
+    let current = entity.ex_point.unwrap_or(0);
+    let effect_type = effect.effect_type.unwrap_or(0);
+
+    let next = if effect_type == EffectType::Expointdel as i32 {
+        current - amount
+    } else if amount > 0 {
+        current + allowed_gain(entity, buff_mgr, amount)
+    } else {
+        current + amount
+    };
+
+    entity.ex_point = Some(next.clamp(0, max_ex_point(entity, buff_mgr).max(current)));
+}
+
+/// Replay the moxie effects of a skill on the round state, so later cards of the round
+/// see them
+pub fn apply_step(state: &mut RoundState, step: &FightStep) {
+    for effect in &step.act_effect {
+        if let Some(nested) = &effect.fight_step {
+            apply_step(state, nested);
+            continue;
+        }
+
+        if !is_ex_point_effect(effect) {
+            continue;
+        }
+
+        let uid = effect.target_id.unwrap_or(0);
+        let buff_mgr = state.buff_mgr.clone();
+        if let Some(entity) = state.get_entity_mut(uid) {
+            apply_effect(entity, effect, &buff_mgr);
+        }
+    }
+}
+
+/// Whether `effect` changes moxie
+fn is_ex_point_effect(effect: &ActEffect) -> bool {
+    let effect_type = effect.effect_type.unwrap_or(0);
+
+    [
+        EffectType::Addexpoint,
+        EffectType::Expointadd,
+        EffectType::Expointchange,
+        EffectType::Expointdel,
+    ]
+    .iter()
+    .any(|t| *t as i32 == effect_type)
+}
+
+/// Ultimate cards for the living heroes at full moxie that don't hold theirs yet
+pub fn ultimate_cards(fight: &Fight, buff_mgr: &BuffMgr, hand: &[CardInfo]) -> Vec<CardInfo> {
+    fight
+        .attacker
+        .iter()
+        .flat_map(|team| team.entitys.iter())
+        .filter(|e| e.current_hp.unwrap_or(0) > 0 && e.ex_skill.unwrap_or(0) != 0)
+        .filter(|e| is_full(e, buff_mgr))
+        .filter(|e| !hand.iter().any(|c| c.skill_id == e.ex_skill))
+        .map(|e| CardInfo {
+            uid: e.uid,
+            skill_id: e.ex_skill,
+            card_effect: Some(0),
+            temp_card: Some(false),
+            enchants: vec![],
+            card_type: Some(0),
+            hero_id: e.model_id,
+            status: Some(0),
+            target_uid: Some(0),
+            extra_info: None,
+            energy: Some(0),
+            extra_infos: vec![],
+            area_red_or_blue: Some(0),
+            heat_id: Some(0),
+        })
+        .collect()
+}
+
+pub fn ex_point_info(entity: &FightEntityInfo) -> FightExPointInfo {
+    FightExPointInfo {
+        uid: entity.uid,
+        ex_point: entity.ex_point,
+        power_infos: entity.power_infos.clone(),
+        current_hp: entity.current_hp,
+        ex_point_type: entity.ex_point_type.or(Some(0)),
+    }
+}
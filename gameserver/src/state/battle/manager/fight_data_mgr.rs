@@ -1,19 +1,24 @@
 use std::sync::Arc;
 
 use crate::state::battle::{
-    effects::effect_types::EffectType, manager::{
+    cloth, default_max_ap, effects::effect_types::EffectType, manager::{
         blood_pool_mgr::FightBloodPoolDataMgr, buff_mgr::BuffMgr,
         calculate_mgr::FightCalculateDataMgr, card_mgr::FightCardMgr,
         entity_mgr::FightEntityDataMgr, round_mgr::FightRoundMgr,
     }, mechanics::{
         Mechanics,
         bloodtithe::{BloodtitheState, fight_enables_bloodtithe},
-    }, passives, step_builder::FightStepBuilder
+    }, passives, step_builder::FightStepBuilder, uids::UidCounter
 };
 use anyhow::Result;
+use rand::rngs::StdRng;
+use serde::{Deserialize, Serialize};
 use sonettobuf::{ActEffect, CardInfo, Fight, FightRound, FightStep};
+use std::collections::HashMap;
 
-#[derive(Default, Debug, Clone)]
+/// Serializes as [`SavedFightData`], the managers are rebuilt around the fight on load
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(into = "SavedFightData", from = "SavedFightData")]
 pub struct FightDataMgr {
     fight: Arc<Fight>,
     mechanics: Mechanics,
@@ -25,6 +30,42 @@ pub struct FightDataMgr {
     pub buff_mgr: BuffMgr,
 }
 
+/// The state of a fight the managers can't derive from the fight itself, the buff uids
+/// are saved with `buff_mgr`
+#[derive(Serialize, Deserialize)]
+struct SavedFightData {
+    fight: Fight,
+    mechanics: Mechanics,
+    buff_mgr: BuffMgr,
+    last_attackers: HashMap<i64, i64>,
+    /// Starts over for states saved before it was kept
+    #[serde(default)]
+    card_uids: UidCounter,
+}
+
+impl From<FightDataMgr> for SavedFightData {
+    fn from(data: FightDataMgr) -> Self {
+        Self {
+            last_attackers: data.calculate_mgr.last_attackers().clone(),
+            fight: Arc::unwrap_or_clone(data.fight),
+            mechanics: data.mechanics,
+            buff_mgr: data.buff_mgr,
+            card_uids: data.card_mgr.uids,
+        }
+    }
+}
+
+impl From<SavedFightData> for FightDataMgr {
+    fn from(saved: SavedFightData) -> Self {
+        let mut data = FightDataMgr::new(saved.fight);
+        data.mechanics = saved.mechanics;
+        data.buff_mgr = saved.buff_mgr;
+        data.calculate_mgr.set_last_attackers(saved.last_attackers);
+        data.card_mgr.uids = saved.card_uids;
+        data
+    }
+}
+
 impl FightDataMgr {
     pub fn new(fight: Fight) -> Self {
         let fight_arc = Arc::new(fight);
@@ -48,11 +89,17 @@ impl FightDataMgr {
 
     pub fn build_initial_round(
             &mut self,
+            rng: &mut StdRng,
             player_deck: Vec<CardInfo>,
             ai_deck: Vec<CardInfo>,
         ) -> Result<FightRound> {
             let mut batch: Vec<ActEffect> = vec![];
             let mut steps: Vec<FightStep> = vec![];
+
+            // cards dealt later in the fight follow the opening deck
+            if let Some(last_uid) = player_deck.iter().filter_map(|c| c.uid).max() {
+                self.card_mgr.uids.observe(last_uid);
+            }
     
             let round_result = {
                 let fight = Arc::make_mut(&mut self.fight);
@@ -61,7 +108,7 @@ impl FightDataMgr {
                 let bootstrap_effects: Vec<ActEffect> = if let Some(attacker) = &fight.attacker {
                     let mut all = Vec::new();
                     for entity in &attacker.entitys {
-                        all.extend(passives::build_bootstrap(entity)?);
+                        all.extend(passives::build_bootstrap(entity, &self.buff_mgr)?);
                     }
                     all
                 } else {
@@ -80,14 +127,31 @@ impl FightDataMgr {
                     steps.push(FightStepBuilder::new_effect().add_effects(effs).build());
                 }
     
+                // Stage rules
+                let stage_effects = self.round_mgr.stage().rule_buff_effects(fight, &self.buff_mgr);
+    
+                if !stage_effects.is_empty() {
+                    let effs = process_effects(
+                        stage_effects,
+                        fight,
+                        &mut self.calculate_mgr,
+                        &mut self.mechanics.bloodtithe,
+                        &mut self.buff_mgr,
+                    )?;
+    
+                    steps.push(FightStepBuilder::new_effect().add_effects(effs).build());
+                }
+    
                 // Battle start effects (normal battle containers)
                 let battle_start_effects: Vec<ActEffect> = if let Some(attacker) = &fight.attacker {
                     let mut all = Vec::new();
                     for entity in &attacker.entitys {
                         all.extend(passives::build_battle_start_passives(
+                            rng,
                             entity,
                             fight,
                             &mut self.mechanics.bloodtithe,
+                            &self.buff_mgr,
                         )?);
                     }
                     all
@@ -132,9 +196,10 @@ impl FightDataMgr {
                     let mut all = Vec::new();
                     for entity in &attacker.entitys {
                         all.extend(passives::build_round_start_passives(
+                            rng,
                             entity,
                             fight,
-                            &mut self.mechanics.bloodtithe,
+                            &self.buff_mgr,
                         )?);
                     }
                     all
@@ -174,7 +239,7 @@ impl FightDataMgr {
                 let post_power_effects: Vec<ActEffect> = if let Some(attacker) = &fight.attacker {
                     let mut all = Vec::new();
                     for entity in &attacker.entitys {
-                        all.extend(passives::build_post_power_passives(entity, fight)?);
+                        all.extend(passives::build_post_power_passives(rng, entity, fight, &self.buff_mgr)?);
                     }
                     all
                 } else {
@@ -197,13 +262,16 @@ impl FightDataMgr {
     
                 FightRound {
                     fight_step: steps,
-                    act_point: Some(3),
+                    act_point: Some(default_max_ap(
+                        fight.episode_id.unwrap_or(0),
+                        fight.attacker.as_ref().map(|a| a.entitys.len()).unwrap_or(0),
+                    )),
                     is_finish: Some(false),
                     move_num: Some(0),
                     ex_point_info: self.calculate_mgr.build_ex_point_info(fight),
                     ai_use_cards: ai_deck,
-                    power: Some(20),
-                    skill_infos: self.calculate_mgr.build_player_skills(),
+                    power: Some(cloth::power(fight)),
+                    skill_infos: cloth::skill_infos(fight),
                     before_cards1: vec![],
                     team_a_cards1: player_deck,
                     before_cards2: vec![],
@@ -2,7 +2,9 @@ pub mod blood_pool_mgr;
 pub mod buff_mgr;
 pub mod calculate_mgr;
 pub mod card_mgr;
+pub mod deck_mgr;
 pub mod entity_mgr;
+pub mod ex_point_mgr;
 pub mod fight_data_mgr;
 pub mod round_mgr;
 pub mod skill_mgr;
@@ -1,25 +1,71 @@
 use anyhow::Result;
 use rand::rngs::StdRng;
 use sonettobuf::{
-    ActEffect, BeginRoundOper, CardInfo, Fight, FightRound, FightStep,
+    ActEffect, BeginRoundOper, CardInfo, Fight, FightEntityInfo, FightRound, FightStep, FightTeam,
     effect_type_enum::EffectType, fight_step,
 };
+use std::collections::HashSet;
 use std::sync::Arc;
 
 use crate::state::battle::{
-    manager::{buff_mgr::BuffMgr, calculate_mgr::FightCalculateDataMgr, card_mgr::FightCardMgr},
-    mechanics::bloodtithe::BloodtitheState,
+    cloth,
+    damage::SkillKind,
+    fight_builder,
+    manager::{
+        buff_mgr::{
+            ATTR_ADD_DMG, ATTR_ATTACK, ATTR_BIG_SKILL_RATE, ATTR_CRI, ATTR_CRI_DEF, ATTR_CRI_DMG,
+            ATTR_DEFENSE, ATTR_DEFENSE_IGNORE, ATTR_DROP_DMG, ATTR_MDEFENSE,
+            ATTR_NORMAL_SKILL_RATE, ATTR_RECRI, BuffMgr,
+        },
+        calculate_mgr::FightCalculateDataMgr,
+        card_mgr::FightCardMgr,
+        deck_mgr, ex_point_mgr,
+        stage_mgr::StageMgr,
+    },
+    mechanics::{
+        bloodtithe::BloodtitheState,
+        ledger::{self, EffectLedger},
+    },
+    passives,
     round::{RoundSnapshot, RoundState},
+    step_builder::FightStepBuilder,
+    uids::UidCounter,
+    utils::find_entity_by_uid,
 };
 
+/// Count limited buffs are used up when their holder attacks (offensive attributes)
+/// or gets hit (defensive attributes)
+const OFFENSIVE_ATTRS: &[i32] = &[
+    ATTR_ATTACK,
+    ATTR_CRI,
+    ATTR_CRI_DMG,
+    ATTR_ADD_DMG,
+    ATTR_DEFENSE_IGNORE,
+    ATTR_NORMAL_SKILL_RATE,
+    ATTR_BIG_SKILL_RATE,
+];
+const DEFENSIVE_ATTRS: &[i32] = &[
+    ATTR_DEFENSE,
+    ATTR_MDEFENSE,
+    ATTR_RECRI,
+    ATTR_CRI_DEF,
+    ATTR_DROP_DMG,
+];
+
 #[derive(Default, Debug, Clone)]
 pub struct FightRoundMgr {
     fight: Arc<Fight>,
+    stage: StageMgr,
 }
 
 impl FightRoundMgr {
     pub fn new(fight: Arc<Fight>) -> Self {
-        Self { fight }
+        let stage = StageMgr::new(fight.battle_id.unwrap_or(0));
+        Self { fight, stage }
+    }
+
+    pub fn stage(&self) -> &StageMgr {
+        &self.stage
     }
 
     pub fn update_fight(&mut self, fight: Arc<Fight>) {
@@ -38,9 +84,12 @@ impl FightRoundMgr {
         ai_deck: Vec<CardInfo>,
         buff_mgr: &mut BuffMgr,
     ) -> Result<FightRound> {
-        let (steps, round_snapshot) = {
+        let (mut steps, mut round_snapshot) = {
             let mut state = RoundState::new(&*fight)?;
 
+            state.buff_mgr = buff_mgr.clone();
+            state.card_uids = card_mgr.uids.clone();
+            state.last_attackers = calc.last_attackers().clone();
             state.player_deck = current_deck.clone();
             state.ai_cards = ai_deck.clone();
 
@@ -62,15 +111,264 @@ impl FightRoundMgr {
             }
 
             state.is_finish = self.check_battle_end(&state);
+            card_mgr.uids.catch_up(&state.card_uids);
 
             (steps, state.export_snapshot())
         };
 
+        let alive_before = alive_uids(fight);
+
+        let mut ledger = EffectLedger::new(fight);
+        for step in &mut steps {
+            ledger.settle(step, buff_mgr);
+        }
+
         calc.play_step_data_list(&steps, fight, bloodtithe, buff_mgr)
             .map_err(anyhow::Error::msg)?;
-        calc.on_round_end();
 
-        Ok(self.build_round_response(steps, round_snapshot, current_deck))
+        let mut hits = Vec::new();
+        let mut crits = Vec::new();
+        let mut ultimates = Vec::new();
+        for step in &steps {
+            collect_hits(step, &mut hits, &mut crits);
+            collect_ultimates(step, fight, &mut ultimates);
+        }
+        dedup_keep_last(&mut hits);
+        dedup_keep_last(&mut crits);
+
+        let events = RoundEvents {
+            hits: &hits,
+            crits: &crits,
+            ultimates: &ultimates,
+            alive_before: &alive_before,
+        };
+        if let Some(mut step) = self.build_reaction_step(rng, &events, fight, buff_mgr)? {
+            ledger.settle(&mut step, buff_mgr);
+            calc.play_step_data(&step, fight, bloodtithe, buff_mgr)
+                .map_err(anyhow::Error::msg)?;
+            steps.push(step);
+        }
+
+        // over time effects tick before the buffs carrying them run out
+        let mut buff_effects = ledger::tick(fight, buff_mgr);
+        for (target_uid, attacker_uid) in &hits {
+            buff_effects.extend(buff_mgr.consume_count(*attacker_uid, OFFENSIVE_ATTRS));
+            buff_effects.extend(buff_mgr.consume_count(*target_uid, DEFENSIVE_ATTRS));
+        }
+        buff_effects.extend(calc.on_round_end(fight, buff_mgr));
+
+        if !buff_effects.is_empty() {
+            let mut step = FightStepBuilder::new_effect()
+                .add_effects(buff_effects)
+                .build();
+            ledger.settle(&mut step, buff_mgr);
+            calc.play_step_data(&step, fight, bloodtithe, buff_mgr)
+                .map_err(anyhow::Error::msg)?;
+            steps.push(step);
+        }
+
+        // damage over time may end the fight after the cards didn't
+        if is_fight_over(fight) {
+            round_snapshot.is_finish = true;
+        }
+
+        if self.spawn_next_wave(fight)? {
+            // the new enemies need their locations before anything hits them
+            calc.update_fight(Arc::new(fight.clone()));
+            round_snapshot.is_finish = false;
+        }
+
+        if !round_snapshot.is_finish && self.stage.round_limit_reached(fight) {
+            tracing::info!("Round limit {} reached", fight.max_round.unwrap_or(0));
+            round_snapshot.is_finish = true;
+        }
+        fight.is_finish = Some(round_snapshot.is_finish);
+
+        if !round_snapshot.is_finish {
+            // dealt after the wave change, a cleared wave goes on with a full hand
+            let merge_effects = refill_hand(
+                rng,
+                fight,
+                buff_mgr,
+                &card_mgr.uids,
+                &mut round_snapshot.player_deck,
+            );
+            if !merge_effects.is_empty() {
+                let mut step = FightStepBuilder::new_effect()
+                    .add_effects(merge_effects)
+                    .build();
+                EffectLedger::new(fight).settle(&mut step, buff_mgr);
+                calc.play_step_data(&step, fight, bloodtithe, buff_mgr)
+                    .map_err(anyhow::Error::msg)?;
+                steps.push(step);
+            }
+
+            let ultimates =
+                ex_point_mgr::ultimate_cards(fight, buff_mgr, &round_snapshot.player_deck);
+
+            if !ultimates.is_empty() {
+                steps.push(
+                    FightStepBuilder::new_effect()
+                        .add_effect(ActEffect {
+                            effect_type: Some(EffectType::Addcard as i32),
+                            card_info_list: ultimates.clone(),
+                            team_type: Some(1),
+                            ..Default::default()
+                        })
+                        .build(),
+                );
+                round_snapshot.player_deck.extend(ultimates);
+            }
+        }
+
+        let next_round_begin_step = if round_snapshot.is_finish {
+            vec![]
+        } else {
+            let mut step = self.build_round_start_step(rng, fight, buff_mgr)?;
+            if let Some(step) = &mut step {
+                EffectLedger::new(fight).settle(step, buff_mgr);
+                calc.play_step_data(step, fight, bloodtithe, buff_mgr)
+                    .map_err(anyhow::Error::msg)?;
+            }
+            step.into_iter().collect()
+        };
+
+        // the fight holds the moxie after passives and clamping, the state only the cards
+        round_snapshot.ex_point_info = calc.build_ex_point_info(fight);
+
+        cloth::on_round_end(
+            fight,
+            round_snapshot.used_cards.len(),
+            round_snapshot.move_num,
+        );
+        round_snapshot.power = cloth::power(fight);
+
+        let is_finish = round_snapshot.is_finish;
+        let mut round = self.build_round_response(steps, round_snapshot, current_deck);
+        round.next_round_begin_step = next_round_begin_step;
+        round.skill_infos = cloth::skill_infos(fight);
+
+        round.ai_use_cards = if is_finish {
+            vec![]
+        } else {
+            fight.cur_round = Some(fight.cur_round.unwrap_or(1) + 1);
+            card_mgr.plan_ai_cards(rng, fight, buff_mgr)
+        };
+
+        Ok(round)
+    }
+
+    /// Bring in the next wave once every enemy is dead. Heroes keep their HP, moxie and
+    /// buffs, the buffs of the dead enemies are already gone.
+    fn spawn_next_wave(&self, fight: &mut Fight) -> Result<bool> {
+        if team_alive(&fight.defender) || !team_alive(&fight.attacker) {
+            return Ok(false);
+        }
+
+        let wave = fight.cur_wave.unwrap_or(1) + 1;
+        let Some(entitys) = fight_builder::build_wave(fight.episode_id.unwrap_or(0), wave)? else {
+            return Ok(false);
+        };
+
+        tracing::info!("Wave {} spawned with {} enemies", wave, entitys.len());
+        enter_wave(fight, wave, entitys);
+
+        Ok(true)
+    }
+
+    /// On hit, crit, ultimate, kill and death passives for what happened in the round's steps
+    fn build_reaction_step(
+        &self,
+        rng: &mut StdRng,
+        events: &RoundEvents,
+        fight: &Fight,
+        buff_mgr: &BuffMgr,
+    ) -> Result<Option<FightStep>> {
+        let mut effects = Vec::new();
+
+        for &(target_uid, attacker_uid) in events.hits {
+            if let Some(entity) = find_entity_by_uid(fight, target_uid) {
+                effects.extend(passives::build_on_hit_passives(
+                    rng,
+                    entity,
+                    attacker_uid,
+                    fight,
+                    buff_mgr,
+                )?);
+            }
+        }
+
+        for &(target_uid, attacker_uid) in events.crits {
+            if let Some(entity) = find_entity_by_uid(fight, attacker_uid) {
+                effects.extend(passives::build_on_crit_passives(
+                    rng, entity, target_uid, fight, buff_mgr,
+                )?);
+            }
+        }
+
+        for uid in events.ultimates {
+            if let Some(entity) = find_entity_by_uid(fight, *uid) {
+                effects.extend(passives::build_on_ultimate_passives(
+                    rng, entity, fight, buff_mgr,
+                )?);
+            }
+        }
+
+        for uid in events.alive_before {
+            let Some(entity) = find_entity_by_uid(fight, *uid) else {
+                continue;
+            };
+            if entity.current_hp.unwrap_or(0) > 0 {
+                continue;
+            }
+
+            effects.extend(passives::build_on_death_passives(
+                rng, entity, fight, buff_mgr,
+            )?);
+
+            // the last one to hit it takes the kill
+            let killer = events.hits.iter().rev().find(|(t, _)| t == uid);
+            if let Some(killer) = killer.and_then(|(_, a)| find_entity_by_uid(fight, *a)) {
+                effects.extend(passives::build_on_kill_passives(
+                    rng, killer, *uid, fight, buff_mgr,
+                )?);
+            }
+        }
+
+        if effects.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(
+            FightStepBuilder::new_effect().add_effects(effects).build(),
+        ))
+    }
+
+    /// Stage rule buffs and round start passives for the next round, played at its
+    /// beginning by the client
+    fn build_round_start_step(
+        &self,
+        rng: &mut StdRng,
+        fight: &Fight,
+        buff_mgr: &BuffMgr,
+    ) -> Result<Option<FightStep>> {
+        let mut effects = self.stage.rule_buff_effects(fight, buff_mgr);
+
+        for uid in alive_uids(fight) {
+            if let Some(entity) = find_entity_by_uid(fight, uid) {
+                effects.extend(passives::build_round_start_passives(
+                    rng, entity, fight, buff_mgr,
+                )?);
+            }
+        }
+
+        if effects.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(
+            FightStepBuilder::new_effect().add_effects(effects).build(),
+        ))
     }
 
     fn check_battle_end(&self, state: &RoundState) -> bool {
@@ -134,3 +432,187 @@ impl FightRoundMgr {
         }
     }
 }
+
+/// What the steps of a round did, for the passives reacting to it
+struct RoundEvents<'a> {
+    /// (target, attacker) of every hit
+    hits: &'a [(i64, i64)],
+    /// (target, attacker) of the crits among them
+    crits: &'a [(i64, i64)],
+    /// casters of ultimates
+    ultimates: &'a [i64],
+    alive_before: &'a [i64],
+}
+
+/// Put the enemies of `wave` in place of the cleared ones
+fn enter_wave(fight: &mut Fight, wave: i32, entitys: Vec<FightEntityInfo>) {
+    if let Some(defender) = fight.defender.as_mut() {
+        defender.entitys = entitys;
+    }
+    fight.cur_wave = Some(wave);
+}
+
+/// Refill the hand for the next round and merge it, returns the moxie of the merges
+fn refill_hand(
+    rng: &mut StdRng,
+    fight: &Fight,
+    buff_mgr: &BuffMgr,
+    card_uids: &UidCounter,
+    hand: &mut Vec<CardInfo>,
+) -> Vec<ActEffect> {
+    let mut state = RoundState::between_rounds(fight);
+    state.buff_mgr = buff_mgr.clone();
+    state.card_uids = card_uids.clone();
+    state.player_deck = std::mem::take(hand);
+
+    let effects = deck_mgr::refill_hand(rng, &mut state);
+    card_uids.catch_up(&state.card_uids);
+    *hand = state.player_deck;
+    effects
+}
+
+fn team_alive(team: &Option<FightTeam>) -> bool {
+    team.iter()
+        .flat_map(|t| t.entitys.iter())
+        .any(|e| e.current_hp.unwrap_or(0) > 0)
+}
+
+fn is_fight_over(fight: &Fight) -> bool {
+    !team_alive(&fight.attacker) || !team_alive(&fight.defender)
+}
+
+fn alive_uids(fight: &Fight) -> Vec<i64> {
+    [&fight.attacker, &fight.defender]
+        .into_iter()
+        .flatten()
+        .flat_map(|team| team.entitys.iter())
+        .filter(|e| e.current_hp.unwrap_or(0) > 0)
+        .filter_map(|e| e.uid)
+        .collect()
+}
+
+/// Drop repeated (target, attacker) pairs, keeping the last one so the order still
+/// ends on the latest hit of each target
+fn dedup_keep_last(pairs: &mut Vec<(i64, i64)>) {
+    let mut seen = HashSet::new();
+    pairs.reverse();
+    pairs.retain(|pair| seen.insert(*pair));
+    pairs.reverse();
+}
+
+/// (target, attacker) for every damage effect in the step and its nested steps, the
+/// crits also go into `crits`
+fn collect_hits(step: &FightStep, out: &mut Vec<(i64, i64)>, crits: &mut Vec<(i64, i64)>) {
+    let attacker_uid = step.from_id.unwrap_or(0);
+
+    for effect in &step.act_effect {
+        if let Some(nested) = &effect.fight_step {
+            collect_hits(nested, out, crits);
+            continue;
+        }
+
+        let effect_type = effect.effect_type.unwrap_or(0);
+        let is_crit = effect_type == EffectType::Crit as i32;
+        if (effect_type == EffectType::Damage as i32 || is_crit)
+            && let Some(target_uid) = effect.target_id
+            && target_uid != attacker_uid
+        {
+            out.push((target_uid, attacker_uid));
+            if is_crit {
+                crits.push((target_uid, attacker_uid));
+            }
+        }
+    }
+}
+
+/// Casters of the ultimate skill steps in the step and its nested steps
+fn collect_ultimates(step: &FightStep, fight: &Fight, out: &mut Vec<i64>) {
+    if step.act_type == Some(fight_step::ActType::Skill.into())
+        && let Some(caster) = find_entity_by_uid(fight, step.from_id.unwrap_or(0))
+        && SkillKind::of(caster, step.act_id.unwrap_or(0)) == SkillKind::Ultimate
+    {
+        out.push(step.from_id.unwrap_or(0));
+    }
+
+    for effect in &step.act_effect {
+        if let Some(nested) = &effect.fight_step {
+            collect_ultimates(nested, fight, out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::battle::cards;
+    use rand::SeedableRng;
+
+    const SKILL: i32 = 30010111;
+
+    /// A hero whose only card is its ultimate, ultimates never merge so the hand keeps
+    /// every card dealt
+    fn hero(uid: i64) -> FightEntityInfo {
+        FightEntityInfo {
+            uid: Some(uid),
+            model_id: Some(3000 + uid as i32),
+            current_hp: Some(100),
+            skill_group1: vec![SKILL + uid as i32],
+            ex_skill: Some(SKILL + uid as i32),
+            ..Default::default()
+        }
+    }
+
+    fn enemy(uid: i64, hp: i32) -> FightEntityInfo {
+        FightEntityInfo {
+            uid: Some(uid),
+            current_hp: Some(hp),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn next_wave_starts_with_a_full_hand() {
+        let mut fight = Fight {
+            attacker: Some(FightTeam {
+                entitys: vec![hero(1), hero(2)],
+                ..Default::default()
+            }),
+            defender: Some(FightTeam {
+                entitys: vec![enemy(-1, 0)],
+                ..Default::default()
+            }),
+            cur_wave: Some(1),
+            ..Default::default()
+        };
+        let last_card = CardInfo {
+            uid: Some(1),
+            hero_id: Some(3001),
+            skill_id: Some(SKILL + 1),
+            ..Default::default()
+        };
+        let mut hand = vec![last_card.clone()];
+
+        // the cards end the first wave
+        let state = RoundState::between_rounds(&fight);
+        assert!(FightRoundMgr::default().check_battle_end(&state));
+        assert!(is_fight_over(&fight));
+
+        enter_wave(&mut fight, 2, vec![enemy(-2, 100)]);
+        assert!(!is_fight_over(&fight));
+        assert_eq!(fight.cur_wave, Some(2));
+
+        let mut rng = StdRng::seed_from_u64(7);
+        let card_uids = UidCounter::starting_at(5);
+        let merges = refill_hand(&mut rng, &fight, &BuffMgr::new(), &card_uids, &mut hand);
+
+        assert!(merges.is_empty());
+        assert_eq!(hand.len(), cards::compute_max_cards(2));
+        assert_eq!(hand[0], last_card);
+
+        // the dealt cards take the next uids of the fight
+        let dealt: Vec<i64> = hand[1..].iter().filter_map(|c| c.uid).collect();
+        let expected: Vec<i64> = (5..5 + dealt.len() as i64).collect();
+        assert_eq!(dealt, expected);
+        assert_eq!(card_uids.peek(), 5 + dealt.len() as i64);
+    }
+}
@@ -1,5 +1,7 @@
 use crate::state::battle::{round::RoundState, skill_executor::SkillExecutor};
 use anyhow::Result;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use sonettobuf::{Fight, FightStep};
 use std::sync::Arc;
 
@@ -23,9 +25,11 @@ impl FightSkillMgr {
         caster_uid: i64,
         target_uid: i64,
         skill_id: i32,
+        rng: &mut StdRng,
     ) -> Result<FightStep> {
         let snapshot = state.snapshot_entities_map();
-        let executor = SkillExecutor::new(snapshot);
+        let mut executor = SkillExecutor::new(snapshot, StdRng::seed_from_u64(rng.r#gen()))
+            .with_last_attackers(state.last_attackers.clone());
         executor.execute_skill(caster_uid, target_uid, skill_id, &state.buff_mgr)
     }
 }
@@ -0,0 +1,172 @@
+//! Stage rules
+//!
+//! Read from the `battle` row of the fight:
+//! - `additionRule` (listed in the client) and `hiddenRule` are `|` separated
+//!   `target#ruleId` entries. The rule id is the `skill_buff` every unit of the target
+//!   side carries for the whole fight: 1 the heroes, 2 the enemies, 3 both. Enemy attribute
+//!   scaling comes through these as `AttrFix` buffs
+//! - `restrictRoles` is the `#` separated afflatus (career) ids a team may field, empty
+//!   when any goes
+//! - `maxRound` is the round limit, the heroes lose when the enemies outlive it
+//! - `monsterGroupIds` has one `|` separated entry per wave, the heroes win once the
+//!   last wave is dead
+//!
+//! Rule buffs go out at battle start and are topped up at every round start, so a new
+//! wave or a dispelled rule gets them back.
+
+use anyhow::Result;
+use sonettobuf::{ActEffect, Fight, FightTeam};
+
+use crate::state::battle::{fight_builder, manager::buff_mgr::BuffMgr, utils::get_team_uids};
+
+const TARGET_ATTACKER: i32 = 1;
+const TARGET_DEFENDER: i32 = 2;
+const TARGET_BOTH: i32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StageRule {
+    pub target: i32,
+    pub buff_id: i32,
+}
+
+impl StageRule {
+    fn applies_to(&self, team_type: i32) -> bool {
+        self.target == TARGET_BOTH || self.target == team_type
+    }
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct StageMgr {
+    battle_id: i32,
+    max_round: i32,
+    waves: i32,
+    rules: Vec<StageRule>,
+    allowed_careers: Vec<i32>,
+}
+
+fn parse_rules(rules: &str) -> impl Iterator<Item = StageRule> + '_ {
+    rules.split('|').filter_map(|entry| {
+        let mut parts = entry.split('#').map(|p| p.trim().parse::<i32>());
+        let target = parts.next()?.ok()?;
+        let buff_id = parts.next()?.ok()?;
+
+        (buff_id > 0 && (TARGET_ATTACKER..=TARGET_BOTH).contains(&target))
+            .then_some(StageRule { target, buff_id })
+    })
+}
+
+impl StageMgr {
+    pub fn new(battle_id: i32) -> Self {
+        let game_data = config::configs::get();
+
+        let Some(battle) = game_data.battle.get(battle_id) else {
+            return Self {
+                battle_id,
+                ..Default::default()
+            };
+        };
+
+        let rules = parse_rules(&battle.addition_rule)
+            .chain(parse_rules(&battle.hidden_rule))
+            .collect();
+
+        let allowed_careers = battle
+            .restrict_roles
+            .split('#')
+            .filter_map(|c| c.trim().parse().ok())
+            .collect();
+
+        let waves = fight_builder::battle_waves(battle_id)
+            .map(|waves| waves.len() as i32)
+            .unwrap_or(1);
+
+        Self {
+            battle_id,
+            max_round: battle.max_round,
+            waves,
+            rules,
+            allowed_careers,
+        }
+    }
+
+    /// Reject a team fielding a hero of an afflatus the stage doesn't allow
+    pub fn check_team(&self, team: &FightTeam) -> Result<()> {
+        if self.allowed_careers.is_empty() {
+            return Ok(());
+        }
+
+        for entity in team.entitys.iter().chain(team.sub_entitys.iter()) {
+            let career = entity.career.unwrap_or(0);
+            if !self.allowed_careers.contains(&career) {
+                anyhow::bail!(
+                    "Battle {} doesn't allow afflatus {} (hero {})",
+                    self.battle_id,
+                    career,
+                    entity.model_id.unwrap_or(0)
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// `BuffAdd` effects for every living unit missing one of its rule buffs
+    pub fn rule_buff_effects(&self, fight: &Fight, buff_mgr: &BuffMgr) -> Vec<ActEffect> {
+        let mut effects = Vec::new();
+
+        for team_type in [TARGET_ATTACKER, TARGET_DEFENDER] {
+            let team = if team_type == TARGET_ATTACKER {
+                &fight.attacker
+            } else {
+                &fight.defender
+            };
+            let alive = |uid: &i64| {
+                team.iter()
+                    .flat_map(|t| t.entitys.iter())
+                    .any(|e| e.uid == Some(*uid) && e.current_hp.unwrap_or(0) > 0)
+            };
+
+            for uid in get_team_uids(fight, team_type).iter().filter(|u| alive(u)) {
+                for rule in self.rules.iter().filter(|r| r.applies_to(team_type)) {
+                    if !buff_mgr.has_buff(*uid, rule.buff_id) {
+                        effects.extend(buff_mgr.add_buff_effects(*uid, rule.buff_id, 0));
+                    }
+                }
+            }
+        }
+
+        effects
+    }
+
+    /// The heroes ran out of rounds with enemies still standing
+    pub fn round_limit_reached(&self, fight: &Fight) -> bool {
+        let max_round = fight.max_round.unwrap_or(self.max_round);
+        if max_round <= 0 || fight.cur_round.unwrap_or(1) < max_round {
+            return false;
+        }
+
+        fight
+            .defender
+            .iter()
+            .flat_map(|t| t.entitys.iter())
+            .any(|e| e.current_hp.unwrap_or(0) > 0)
+    }
+
+    /// 1 when the heroes killed the last wave, 2 when they died or ran out of rounds,
+    /// 0 while the fight goes on
+    pub fn fight_result(&self, fight: &Fight) -> i32 {
+        let alive = |team: &Option<FightTeam>| {
+            team.iter()
+                .flat_map(|t| t.entitys.iter())
+                .any(|e| e.current_hp.unwrap_or(0) > 0)
+        };
+
+        if !alive(&fight.attacker) || self.round_limit_reached(fight) {
+            2
+        } else if !alive(&fight.defender) && fight.cur_wave.unwrap_or(1) >= self.waves {
+            1
+        } else {
+            0
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
 use sonettobuf::{ActEffect, Fight, FightEntityInfo, effect_type_enum::EffectType};
 use std::collections::HashMap;
 use std::sync::Mutex;
@@ -12,7 +13,7 @@ const PER_ALLY_BONUS: i32 = 16;
 
 static GAINED: Lazy<Mutex<i32>> = Lazy::new(|| Mutex::new(0));
 
-#[derive(Debug, Default, Clone, PartialEq)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub struct BloodtitheState {
     value: HashMap<i32, i32>,       // team_type -> current
     max: HashMap<i32, i32>,         // team_type -> max
@@ -0,0 +1,212 @@
+//! Effects ledger
+//!
+//! Keeps HP and shield of every unit while the steps of a round are settled, before the
+//! calculate manager replays them on the fight:
+//! - `Shield` adds to the shield of its target, `ShieldDel` removes it
+//! - Damage goes into the shield before HP. The damage effect then carries the HP loss
+//!   only (`hurt_info.reduce_shield` has the absorbed part) and is followed by a
+//!   `ShieldChange` with the shield left, or a `ShieldBroken` once it's gone
+//! - Healing past max HP turns into shield for units with an `OverflowHealToShield` buff
+//!
+//! Buffs with a `Poison`, `Burn` or `Dot` feature deal `value` per mille of their
+//! source's attack per layer at the end of every round, `Hot` heals the same way.
+//! They tick before the buff durations do, so a one round buff still hits once.
+
+use std::collections::HashMap;
+
+use sonettobuf::{
+    ActEffect, Fight, FightHurtInfo, FightStep, effect_type_enum::EffectType, fight_hurt_info,
+};
+
+use crate::state::battle::{
+    manager::{buff_mgr::BuffMgr, calculate_mgr::FightCalculateDataMgr},
+    utils::{VfxConfig, find_entity_by_uid},
+};
+
+const DOT_FEATURES: &[&str] = &["Poison", "Burn", "Dot"];
+const HOT_FEATURE: &str = "Hot";
+const OVERFLOW_HEAL_TO_SHIELD: &str = "OverflowHealToShield";
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Ledger {
+    hp: i32,
+    max_hp: i32,
+    shield: i32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EffectLedger {
+    entries: HashMap<i64, Ledger>,
+}
+
+fn is_damage(effect_type: i32) -> bool {
+    [EffectType::Damage, EffectType::Crit, EffectType::Dot]
+        .iter()
+        .any(|t| *t as i32 == effect_type)
+}
+
+fn shield_effect(effect_type: EffectType, uid: i64, amount: i32) -> ActEffect {
+    ActEffect {
+        effect_type: Some(effect_type as i32),
+        target_id: Some(uid),
+        effect_num: Some(amount),
+        ..Default::default()
+    }
+}
+
+impl EffectLedger {
+    pub fn new(fight: &Fight) -> Self {
+        let entries = [&fight.attacker, &fight.defender]
+            .into_iter()
+            .flatten()
+            .flat_map(|team| team.entitys.iter())
+            .filter_map(|e| {
+                let hp = e.current_hp.unwrap_or(0);
+                let ledger = Ledger {
+                    hp,
+                    max_hp: e.attr.as_ref().and_then(|a| a.hp).unwrap_or(hp),
+                    shield: e.shield_value.unwrap_or(0),
+                };
+                Some((e.uid?, ledger))
+            })
+            .collect();
+
+        Self { entries }
+    }
+
+    /// Run the effects of `step` and its nested steps through the ledger in order
+    pub fn settle(&mut self, step: &mut FightStep, buff_mgr: &BuffMgr) {
+        let effects = std::mem::take(&mut step.act_effect);
+
+        for mut effect in effects {
+            if let Some(nested) = effect.fight_step.as_mut() {
+                self.settle(nested, buff_mgr);
+                step.act_effect.push(effect);
+                continue;
+            }
+
+            let follow_up = self.settle_effect(&mut effect, buff_mgr);
+            step.act_effect.push(effect);
+            step.act_effect.extend(follow_up);
+        }
+    }
+
+    /// Settle one effect, returns the shield effects that follow it
+    fn settle_effect(&mut self, effect: &mut ActEffect, buff_mgr: &BuffMgr) -> Vec<ActEffect> {
+        let uid = effect.target_id.unwrap_or(0);
+        let amount = effect.effect_num.unwrap_or(0);
+        let effect_type = effect.effect_type.unwrap_or(0);
+
+        let Some(entry) = self.entries.get_mut(&uid) else {
+            return vec![];
+        };
+
+        if is_damage(effect_type) {
+            let absorbed = amount.min(entry.shield).max(0);
+            let hp_loss = amount - absorbed;
+            entry.hp = (entry.hp - hp_loss).max(0);
+            if absorbed == 0 {
+                return vec![];
+            }
+            entry.shield -= absorbed;
+
+            effect.effect_num = Some(hp_loss);
+            if let Some(hurt) = effect.hurt_info.as_mut() {
+                hurt.reduce_hp = Some(hp_loss);
+                hurt.reduce_shield = Some(absorbed);
+            }
+
+            return vec![if entry.shield > 0 {
+                shield_effect(EffectType::Shieldchange, uid, entry.shield)
+            } else {
+                shield_effect(EffectType::Shieldbrocken, uid, absorbed)
+            }];
+        }
+
+        if effect_type == EffectType::Shield as i32 {
+            entry.shield += amount.max(0);
+        } else if effect_type == EffectType::Shielddel as i32 {
+            entry.shield = 0;
+        } else if effect_type == EffectType::Heal as i32
+            || effect_type == EffectType::Healcrit as i32
+        {
+            let overflow = (entry.hp + amount - entry.max_hp).max(0);
+            entry.hp = (entry.hp + amount).min(entry.max_hp);
+
+            if overflow > 0 && buff_mgr.has_feature(uid, OVERFLOW_HEAL_TO_SHIELD) {
+                entry.shield += overflow;
+                return vec![shield_effect(
+                    EffectType::Overflowhealtoshield,
+                    uid,
+                    overflow,
+                )];
+            }
+        }
+
+        vec![]
+    }
+}
+
+/// Round end damage and healing of the over time buffs on living units
+pub fn tick(fight: &Fight, buff_mgr: &BuffMgr) -> Vec<ActEffect> {
+    let mut effects = Vec::new();
+
+    let mut targets: Vec<_> = [&fight.attacker, &fight.defender]
+        .into_iter()
+        .flatten()
+        .flat_map(|team| team.entitys.iter())
+        .filter(|e| e.current_hp.unwrap_or(0) > 0)
+        .filter_map(|e| e.uid)
+        .collect();
+    targets.sort_unstable();
+
+    for uid in targets {
+        for buff in buff_mgr.get_buffs(uid) {
+            let Some(source) = find_entity_by_uid(fight, buff.from_uid) else {
+                continue;
+            };
+            let source_attr = FightCalculateDataMgr::combat_attr(source, buff_mgr);
+            let per_layer = |value: i32| {
+                source_attr.attack as i64 * value as i64 / 1000 * buff.layer.max(1) as i64
+            };
+
+            for (feature, value) in &buff.rule.features {
+                if DOT_FEATURES.contains(&feature.as_str()) {
+                    let damage = per_layer(*value).clamp(1, i32::MAX as i64) as i32;
+                    effects.push(dot_effect(uid, buff.from_uid, damage));
+                } else if feature == HOT_FEATURE {
+                    let heal = per_layer(*value) * (1000 + source_attr.heal).max(0) as i64 / 1000;
+                    effects.push(ActEffect {
+                        effect_type: Some(EffectType::Heal as i32),
+                        target_id: Some(uid),
+                        effect_num: Some(heal.clamp(1, i32::MAX as i64) as i32),
+                        config_effect: Some(VfxConfig::Heal as i32),
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+    }
+
+    effects
+}
+
+fn dot_effect(target_uid: i64, from_uid: i64, damage: i32) -> ActEffect {
+    ActEffect {
+        effect_type: Some(EffectType::Dot as i32),
+        target_id: Some(target_uid),
+        effect_num: Some(damage),
+        config_effect: Some(VfxConfig::Damage as i32),
+        hurt_info: Some(FightHurtInfo {
+            damage: Some(damage),
+            reduce_hp: Some(damage),
+            reduce_shield: Some(0),
+            hurt_effect: Some(EffectType::Dot as i32),
+            damage_from_type: Some(fight_hurt_info::DamageFromType::Buff.into()),
+            config_effect: Some(VfxConfig::Damage as i32),
+            from_uid: Some(from_uid),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
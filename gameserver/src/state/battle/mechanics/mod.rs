@@ -1,8 +1,11 @@
 pub mod bloodtithe;
+pub mod ledger;
+pub mod status;
 
 use bloodtithe::BloodtitheState;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, PartialEq, Default)]
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
 pub struct Mechanics {
     pub bloodtithe: BloodtitheState,
 }
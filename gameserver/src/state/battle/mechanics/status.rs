@@ -0,0 +1,112 @@
+//! Control statuses
+//!
+//! A status is a feature of a buff (`Dizzy`, `Silence`, ...) and lasts as long as the
+//! buff carrying it. Dizzy, Frozen, Sleep and Petrified units skip their cards, Silence
+//! blocks ultimates, Disarm blocks attack incantations and Forbid the others, Seal keeps
+//! passives from firing. A unit with the matching resist feature (`DizzyResist`, ...)
+//! rejects any buff that would put the status on it.
+
+use sonettobuf::FightEntityInfo;
+
+use crate::state::battle::{
+    damage::SkillKind,
+    manager::buff_mgr::{BuffMgr, BuffRule},
+    skill_executor::SkillExecutor,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Dizzy,
+    Frozen,
+    Sleep,
+    Petrified,
+    Silence,
+    Seal,
+    Disarm,
+    Forbid,
+}
+
+impl Status {
+    pub const ALL: [Status; 8] = [
+        Status::Dizzy,
+        Status::Frozen,
+        Status::Sleep,
+        Status::Petrified,
+        Status::Silence,
+        Status::Seal,
+        Status::Disarm,
+        Status::Forbid,
+    ];
+
+    pub fn feature(self) -> &'static str {
+        match self {
+            Status::Dizzy => "Dizzy",
+            Status::Frozen => "Frozen",
+            Status::Sleep => "Sleep",
+            Status::Petrified => "Petrified",
+            Status::Silence => "Silence",
+            Status::Seal => "Seal",
+            Status::Disarm => "Disarm",
+            Status::Forbid => "Forbid",
+        }
+    }
+
+    fn resist_feature(self) -> Option<&'static str> {
+        match self {
+            Status::Dizzy => Some("DizzyResist"),
+            Status::Frozen => Some("FrozenResist"),
+            Status::Sleep => Some("SleepResist"),
+            Status::Petrified => Some("PetrifiedResist"),
+            _ => None,
+        }
+    }
+
+    /// Statuses that keep the unit from acting at all
+    pub fn is_stun(self) -> bool {
+        matches!(
+            self,
+            Status::Dizzy | Status::Frozen | Status::Sleep | Status::Petrified
+        )
+    }
+}
+
+pub fn has_status(buff_mgr: &BuffMgr, uid: i64, status: Status) -> bool {
+    buff_mgr.has_feature(uid, status.feature())
+}
+
+pub fn statuses(buff_mgr: &BuffMgr, uid: i64) -> Vec<Status> {
+    Status::ALL
+        .into_iter()
+        .filter(|s| has_status(buff_mgr, uid, *s))
+        .collect()
+}
+
+/// Whether `uid` is immune to one of the statuses a buff with `rule` would apply
+pub fn resists(buff_mgr: &BuffMgr, uid: i64, rule: &BuffRule) -> bool {
+    Status::ALL
+        .into_iter()
+        .filter(|s| rule.features.iter().any(|(f, _)| f == s.feature()))
+        .filter_map(Status::resist_feature)
+        .any(|resist| buff_mgr.has_feature(uid, resist))
+}
+
+pub fn is_sealed(buff_mgr: &BuffMgr, uid: i64) -> bool {
+    has_status(buff_mgr, uid, Status::Seal)
+}
+
+/// Whether `caster` may use `skill_id` as a card with its current statuses
+pub fn can_use_skill(buff_mgr: &BuffMgr, caster: &FightEntityInfo, skill_id: i32) -> bool {
+    let uid = caster.uid.unwrap_or(0);
+    let is_ultimate = SkillKind::of(caster, skill_id) == SkillKind::Ultimate;
+
+    statuses(buff_mgr, uid)
+        .into_iter()
+        .all(|status| match status {
+            s if s.is_stun() => false,
+            Status::Silence => !is_ultimate,
+            // Disarm and Forbid only touch incantations
+            Status::Disarm => is_ultimate || !SkillExecutor::is_attack_skill(skill_id),
+            Status::Forbid => is_ultimate || SkillExecutor::is_attack_skill(skill_id),
+            _ => true,
+        })
+}
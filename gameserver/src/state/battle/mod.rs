@@ -1,20 +1,31 @@
+mod ai;
 mod auto;
 mod cards;
 mod passives;
 
+pub mod cloth;
+pub mod conditions;
+pub mod damage;
 pub mod effects;
 pub mod end_fight;
 pub mod entity_builder;
 pub mod fight_builder;
+pub mod headless;
 pub mod manager;
 pub mod mechanics;
+pub mod resonance;
+pub mod resume;
 pub mod rewards;
+pub mod rng;
 pub mod round;
 pub mod round_builder;
 pub mod simulator;
 pub mod skill_executor;
 pub mod step_builder;
+pub mod targets;
+pub mod uids;
 pub mod utils;
+pub mod verify;
 
 use anyhow::Result;
 use sonettobuf::CardInfo;
@@ -22,10 +33,6 @@ use sonettobuf::Fight;
 use sonettobuf::FightRound;
 use sqlx::SqlitePool;
 
-use std::sync::atomic::AtomicI64;
-
-pub static BUFF_UID_COUNTER: AtomicI64 = AtomicI64::new(2);
-
 pub use auto::generate_auto_opers;
 
 pub use cards::{default_max_ap, generate_ai_initial_deck, generate_initial_deck};
@@ -39,6 +46,7 @@ pub struct BattleContext {
     pub episode_id: i32,
     pub battle_id: i32,
     pub max_ap: i32,
+    pub seed: u64,
 }
 
 pub async fn create_battle(
@@ -47,13 +55,18 @@ pub async fn create_battle(
     fight_group: &sonettobuf::FightGroup,
     player_deck: Vec<sonettobuf::CardInfo>,
 ) -> Result<(Fight, FightRound, FightDataMgr, Vec<CardInfo>)> {
-    let fight = fight_builder::build_fight(pool, &ctx, fight_group).await?;
+    let (fight, resonance) = fight_builder::build_fight(pool, &ctx, fight_group).await?;
 
-    let seed = (ctx.player_id as u64) ^ (ctx.episode_id as u64) ^ 0xA11C;
-    let ai_deck = generate_ai_initial_deck(&fight, seed).await;
+    let ai_deck = generate_ai_initial_deck(&fight, ctx.seed).await;
 
-    let (initial_round, modified_fight, fight_data_mgr) =
-        round_builder::build_initial_round(fight, player_deck, ai_deck.clone()).await?;
+    let (initial_round, modified_fight, fight_data_mgr) = round_builder::build_initial_round(
+        fight,
+        resonance,
+        player_deck,
+        ai_deck.clone(),
+        ctx.seed,
+    )
+    .await?;
 
     Ok((modified_fight, initial_round, fight_data_mgr, ai_deck))
 }
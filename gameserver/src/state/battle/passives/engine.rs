@@ -0,0 +1,127 @@
+//! Generic passive interpreter
+//!
+//! A hero's passives are the `skill_effect` rows listed for it in `skill_passive_level`
+//! (resolved into `FightEntityInfo::passive_skill` by the entity builder). Every behavior
+//! slot of a passive fires on one trigger point, picked from the condition of the slot.
+//! Passives with mechanics the tables can't express are handled by the per-hero modules
+//! and skipped here.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use sonettobuf::{ActEffect, Fight, FightEntityInfo};
+
+use crate::state::battle::{
+    conditions::Condition, manager::buff_mgr::BuffMgr, skill_executor::SkillExecutor,
+    step_builder::FightStepBuilder,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PassiveTrigger {
+    BattleStart,
+    RoundStart,
+    PostPower,
+    OnHit,
+    OnDeath,
+    OnKill,
+    OnCrit,
+    OnUltimate,
+}
+
+/// Condition ids that only say when a passive slot fires. The reactive triggers have
+/// no known condition id yet, they only run the per-hero modules until the tables
+/// name one (an unmapped id is listed by `report_unknown_conditions` at load).
+const TRIGGER_CONDITIONS: &[(i32, PassiveTrigger)] = &[
+    (203, PassiveTrigger::BattleStart),
+    (208, PassiveTrigger::RoundStart),
+];
+
+impl PassiveTrigger {
+    pub fn from_condition_id(cond_id: i32) -> Option<Self> {
+        TRIGGER_CONDITIONS
+            .iter()
+            .find(|(id, _)| *id == cond_id)
+            .map(|(_, trigger)| *trigger)
+    }
+
+    /// Trigger point of a passive slot. Unconditional slots apply once when the fight
+    /// starts, slots gated on fight state are re-checked at every round start.
+    pub fn of_condition(condition: &str) -> Self {
+        if condition.is_empty() {
+            return Self::BattleStart;
+        }
+
+        Condition::parse(condition)
+            .ok()
+            .and_then(|c| c.trigger())
+            .unwrap_or(Self::RoundStart)
+    }
+
+    /// Whether the condition is only a trigger marker with nothing left to check
+    pub fn is_trigger_condition(condition: &str) -> bool {
+        Condition::parse(condition).is_ok_and(|c| c.is_trigger_only())
+    }
+}
+
+pub fn entity_map(fight: &Fight) -> HashMap<i64, FightEntityInfo> {
+    [&fight.attacker, &fight.defender]
+        .into_iter()
+        .flatten()
+        .flat_map(|team| team.entitys.iter().chain(team.sub_entitys.iter()))
+        .filter_map(|e| e.uid.map(|uid| (uid, e.clone())))
+        .collect()
+}
+
+/// Run every table driven passive of `entity` that fires on `trigger`.
+/// `target_uid` is the other side of the event (the attacker for `OnHit`, the victim
+/// for `OnKill` and `OnCrit`), 0 when there is none.
+pub fn run_trigger(
+    rng: &mut StdRng,
+    entity: &FightEntityInfo,
+    fight: &Fight,
+    buff_mgr: &BuffMgr,
+    trigger: PassiveTrigger,
+    target_uid: i64,
+    skip: &[i32],
+) -> Result<Vec<ActEffect>> {
+    let uid = entity.uid.unwrap_or(0);
+
+    if trigger != PassiveTrigger::OnDeath && entity.current_hp.unwrap_or(0) <= 0 {
+        return Ok(vec![]);
+    }
+
+    let game_data = config::configs::get();
+    let mut executor = SkillExecutor::new(entity_map(fight), StdRng::seed_from_u64(rng.r#gen()));
+    let target_uid = if target_uid != 0 { target_uid } else { uid };
+
+    let mut effects = Vec::new();
+
+    for &passive_id in &entity.passive_skill {
+        if skip.contains(&passive_id) || game_data.skill_effect.get(passive_id).is_none() {
+            continue;
+        }
+
+        let inner = executor.execute_passive(uid, target_uid, passive_id, trigger, buff_mgr)?;
+        if inner.is_empty() {
+            continue;
+        }
+
+        tracing::debug!(
+            "Passive {} of {} fired on {:?} ({} effects)",
+            passive_id,
+            uid,
+            trigger,
+            inner.len()
+        );
+
+        effects.push(
+            FightStepBuilder::new_effect()
+                .add_battle_container(uid, passive_id, inner)
+                .build_as_act_effect(),
+        );
+    }
+
+    Ok(effects)
+}
@@ -1,9 +1,83 @@
+//! Psychubes
+//!
+//! The effects of a psychube are the `equip_skill` row of its `skillType` at the refine
+//! level of the equipped copy (the highest row at or below it):
+//! - `skill` and `skill2` are passives run by the passive engine like any hero passive,
+//!   on the trigger of their conditions (battle start, round start, crit, ultimate, kill)
+//! - `hp` and `attack` scale the base attributes in `entity_builder::build_attr`
+//! - the other stat lines are per mille modifiers added on top of the buffs
+//!
+//! A few passives drive client effects the tables can't express and are built here.
+
 use super::super::utils::*;
-use crate::state::battle::{effects::effect_types::EffectType, step_builder::FightStepBuilder};
+use crate::state::battle::{
+    effects::effect_types::EffectType,
+    manager::buff_mgr::{AttrModifiers, BuffMgr},
+    step_builder::FightStepBuilder,
+};
 use anyhow::Result;
+use config::equip_skill::EquipSkill;
 use sonettobuf::{ActEffect, Fight, FightEntityInfo};
 
-pub fn build_battle_start(entity: &FightEntityInfo, fight: &Fight) -> Result<Vec<ActEffect>> {
+/// Passives with mechanics the passive engine can't express
+pub const BESPOKE: &[i32] = &[433011, 434811, 435611, 435621];
+
+/// `equip_skill` row of psychube `equip_id` at `refine_lv`
+pub fn equip_skill(equip_id: i32, refine_lv: i32) -> Option<&'static EquipSkill> {
+    let game_data = config::configs::get();
+
+    let skill_type = game_data
+        .equip
+        .get(equip_id)
+        .map(|e| e.skill_type)
+        .filter(|t| *t != 0)
+        .unwrap_or(equip_id);
+
+    game_data
+        .equip_skill
+        .iter()
+        .filter(|s| s.id == skill_type && s.skill_lv <= refine_lv.max(1))
+        .max_by_key(|s| s.skill_lv)
+}
+
+/// `equip_skill` row of the psychube `entity` fights with
+pub fn entity_equip_skill(entity: &FightEntityInfo) -> Option<&'static EquipSkill> {
+    let equip = entity.equips.first()?;
+    equip_skill(equip.equip_id?, equip.refine_lv.unwrap_or(1))
+}
+
+/// Passive skill ids a psychube gives its wearer
+pub fn passives(skill: &EquipSkill) -> impl Iterator<Item = i32> {
+    [skill.skill, skill.skill2]
+        .into_iter()
+        .filter(|id| *id != 0)
+}
+
+/// Per mille stat lines of the psychube of `entity`
+pub fn attr_modifiers(entity: &FightEntityInfo) -> AttrModifiers {
+    let Some(skill) = entity_equip_skill(entity) else {
+        return AttrModifiers::default();
+    };
+
+    AttrModifiers {
+        cri: skill.cri,
+        recri: skill.recri,
+        cri_dmg: skill.cri_dmg,
+        cri_def: skill.cri_def,
+        add_dmg: skill.add_dmg,
+        drop_dmg: skill.drop_dmg,
+        heal: skill.heal,
+        defense_ignore: skill.defense_ignore,
+        normal_skill_rate: skill.normal_skill_rate,
+        ..Default::default()
+    }
+}
+
+pub fn build_battle_start(
+    entity: &FightEntityInfo,
+    fight: &Fight,
+    buff_mgr: &BuffMgr,
+) -> Result<Vec<ActEffect>> {
     let uid = entity.uid.unwrap_or(0);
     let team_type = entity.team_type.unwrap_or(1);
     let team_uids = get_team_uids(fight, team_type);
@@ -18,7 +92,7 @@ pub fn build_battle_start(entity: &FightEntityInfo, fight: &Fight) -> Result<Vec
                         uid,
                         passive_id,
                         vec![
-                            buff_add(uid, uid, passive_id),
+                            buff_add(buff_mgr, uid, uid, passive_id),
                             ActEffect {
                                 effect_type: Some(EffectType::TeammateInjuryCount as i32), // 210
                                 target_id: Some(uid),
@@ -36,9 +110,9 @@ pub fn build_battle_start(entity: &FightEntityInfo, fight: &Fight) -> Result<Vec
                         uid,
                         435611,
                         vec![
-                            buff_add(uid, uid, 435641),
+                            buff_add(buff_mgr, uid, uid, 435641),
                             attr_change(uid),
-                            buff_add(uid, uid, 435611),
+                            buff_add(buff_mgr, uid, uid, 435611),
                             ActEffect {
                                 effect_type: Some(EffectType::MasterHalo as i32), // 172
                                 target_id: Some(uid),
@@ -54,7 +128,7 @@ pub fn build_battle_start(entity: &FightEntityInfo, fight: &Fight) -> Result<Vec
                 let mut inner = vec![];
 
                 for target_uid in &team_uids {
-                    inner.push(buff_add(*target_uid, uid, 435621));
+                    inner.push(buff_add(buff_mgr, *target_uid, uid, 435621));
                     inner.push(ActEffect {
                         effect_type: Some(EffectType::SlaveHalo as i32), // 173
                         target_id: Some(*target_uid),
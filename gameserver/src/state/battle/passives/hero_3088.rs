@@ -1,28 +1,25 @@
 use crate::state::battle::{
-    effects::effect_types::EffectType, mechanics::bloodtithe::BloodtitheState, step_builder::FightStepBuilder
+    effects::effect_types::EffectType, manager::buff_mgr::BuffMgr, mechanics::bloodtithe::BloodtitheState, step_builder::FightStepBuilder
 };
 
 use super::super::utils::*;
 use anyhow::Result;
 use sonettobuf::{ActEffect, Fight, FightEntityInfo, fight_hurt_info};
 
+/// Passives with mechanics the passive engine can't express
+pub const BESPOKE: &[i32] = &[30880141, 308802111];
+
 pub fn build_battle_start(
     entity: &FightEntityInfo,
     fight: &Fight,
     bloodtithe: &mut BloodtitheState,
+    buff_mgr: &BuffMgr,
 ) -> Result<Vec<ActEffect>> {
-    let uid = entity.uid.unwrap_or(0);
-    let mut effects = vec![];
-
-    for &passive_id in &entity.passive_skill.clone() {
-        match passive_id {
-            30880141 => effects.extend(create_bloodtithe_buff(entity, fight, bloodtithe)?),
-            30880151 => effects.push(create_counter(uid)?),
-            _ => {}
-        }
+    if entity.passive_skill.contains(&30880141) {
+        create_bloodtithe_buff(entity, fight, bloodtithe, buff_mgr)
+    } else {
+        Ok(vec![])
     }
-
-    Ok(effects)
 }
 
 pub fn build_post_power(entity: &FightEntityInfo, _fight: &Fight) -> Result<Vec<ActEffect>> {
@@ -49,18 +46,11 @@ pub fn build_post_power(entity: &FightEntityInfo, _fight: &Fight) -> Result<Vec<
     }
 }
 
-pub fn build_round_start(
-    _entity: &FightEntityInfo,
-    _fight: &Fight,
-    _bloodtithe: &mut BloodtitheState,
-) -> Result<Vec<ActEffect>> {
-    Ok(vec![])
-}
-
 fn create_bloodtithe_buff(
     entity: &FightEntityInfo,
     fight: &Fight,
     bloodtithe: &mut BloodtitheState,
+    buff_mgr: &BuffMgr,
 ) -> Result<Vec<ActEffect>> {
     let uid = entity.uid.unwrap_or(0);
     let team = entity.team_type.unwrap_or(1);
@@ -122,7 +112,7 @@ fn create_bloodtithe_buff(
         effects.push(bloodtithe_effect);
 
         // Add team buffs
-        effects.extend(create_team_buffs(entity, fight)?);
+        effects.extend(create_team_buffs(entity, fight, buff_mgr)?);
 
         Ok(effects)
     } else {
@@ -130,7 +120,11 @@ fn create_bloodtithe_buff(
     }
 }
 
-fn create_team_buffs(entity: &FightEntityInfo, fight: &Fight) -> Result<Vec<ActEffect>> {
+fn create_team_buffs(
+    entity: &FightEntityInfo,
+    fight: &Fight,
+    buff_mgr: &BuffMgr,
+) -> Result<Vec<ActEffect>> {
     let uid = entity.uid.unwrap_or(0);
     let team_type = entity.team_type.unwrap_or(1);
     let team_uids = get_team_uids(fight, team_type);
@@ -166,7 +160,7 @@ fn create_team_buffs(entity: &FightEntityInfo, fight: &Fight) -> Result<Vec<ActE
             inner.push(current_hp_change(uid, new_current_hp));
             inner.push(max_hp_change(uid, new_max_hp, None));
             inner.push(current_hp_change(uid, new_current_hp));
-            inner.push(buff_add(uid, uid, 308801921));
+            inner.push(buff_add(buff_mgr, uid, uid, 308801921));
             inner.push(attr_change(uid));
             continue;
         }
@@ -181,7 +175,7 @@ fn create_team_buffs(entity: &FightEntityInfo, fight: &Fight) -> Result<Vec<ActE
             // Ally buffs
             inner.push(max_hp_change(target_uid, new_max, None));
             inner.push(current_hp_change(target_uid, new_cur));
-            inner.push(buff_add(target_uid, uid, 308801922));
+            inner.push(buff_add(buff_mgr, target_uid, uid, 308801922));
             inner.push(effect_none(target_uid));
         }
     }
@@ -196,15 +190,3 @@ fn create_team_buffs(entity: &FightEntityInfo, fight: &Fight) -> Result<Vec<ActE
 
     Ok(vec![team_buff_effect])
 }
-
-fn create_counter(uid: i64) -> Result<ActEffect> {
-    let effect = FightStepBuilder::new_effect()
-        .add_battle_container(
-            uid,
-            30880151,
-            vec![buff_add(uid, uid, 205), attr_change(uid)],
-        )
-        .build_as_act_effect();
-
-    Ok(effect)
-}
@@ -1,50 +1,30 @@
 use super::super::utils::*;
+use crate::state::battle::manager::buff_mgr::BuffMgr;
 use crate::state::battle::mechanics::bloodtithe::BloodtitheState;
 use crate::state::battle::step_builder::FightStepBuilder;
 use anyhow::Result;
 use sonettobuf::{ActEffect, Fight, FightEntityInfo, fight_hurt_info};
 
+/// Passives with mechanics the passive engine can't express
+pub const BESPOKE: &[i32] = &[31200146];
+
 pub fn build_battle_start(
     entity: &FightEntityInfo,
     _fight: &Fight,
     bloodtithe: &mut BloodtitheState,
+    buff_mgr: &BuffMgr,
 ) -> Result<Vec<ActEffect>> {
-    let uid = entity.uid.unwrap_or(0);
-    let mut effects = vec![];
-
-    for &passive_id in &entity.passive_skill.clone() {
-        match passive_id {
-            31200145 => effects.push(create_insight_i(uid)?),
-            31200146 => effects.extend(create_faith_mechanic(entity, bloodtithe)?),
-            31200149 => effects.extend(create_insight_iii(uid)?),
-            _ => {}
-        }
+    if entity.passive_skill.contains(&31200146) {
+        create_faith_mechanic(entity, bloodtithe, buff_mgr)
+    } else {
+        Ok(vec![])
     }
-
-    Ok(effects)
-}
-
-pub fn build_post_power(_entity: &FightEntityInfo, _fight: &Fight) -> Result<Vec<ActEffect>> {
-    Ok(vec![])
-}
-
-pub fn build_round_start(_entity: &FightEntityInfo, _fight: &Fight) -> Result<Vec<ActEffect>> {
-    Ok(vec![])
-}
-
-fn create_insight_i(uid: i64) -> Result<ActEffect> {
-    Ok(FightStepBuilder::new_effect()
-        .add_battle_container(
-            uid,
-            31200145,
-            vec![buff_add(uid, uid, 31200145), effect_none(uid)],
-        )
-        .build_as_act_effect())
 }
 
 fn create_faith_mechanic(
     entity: &FightEntityInfo,
     bloodtithe: &mut BloodtitheState,
+    buff_mgr: &BuffMgr,
 ) -> Result<Vec<ActEffect>> {
     let uid = entity.uid.unwrap_or(0);
     let current_hp = entity.current_hp.unwrap_or(0);
@@ -66,7 +46,7 @@ fn create_faith_mechanic(
     }
 
     let skill_effects = vec![
-        buff_add(uid, uid, 31200145),
+        buff_add(buff_mgr, uid, uid, 31200145),
         effect_none(uid),
         damage(uid, actual_hp_loss),
         hurt_detail(
@@ -77,7 +57,7 @@ fn create_faith_mechanic(
         ),
         moxie_change(uid, 1),
         bloodtithe_value_change(uid, 1, 2),
-        buff_add(uid, uid, 31200146),
+        buff_add(buff_mgr, uid, uid, 31200146),
         effect_none(uid),
     ];
 
@@ -99,15 +79,3 @@ fn create_faith_mechanic(
 
     Ok(out)
 }
-
-fn create_insight_iii(uid: i64) -> Result<Vec<ActEffect>> {
-    let battle_container_effect = FightStepBuilder::new_effect()
-        .add_battle_container(
-            uid,
-            31200149,
-            vec![buff_add(uid, uid, 31200184), effect_none(uid)],
-        )
-        .build_as_act_effect();
-
-    Ok(vec![battle_container_effect])
-}
@@ -1,66 +1,199 @@
 mod activity;
-mod equipment;
+pub mod engine;
+pub mod equipment;
 mod hero_3088;
 mod hero_3120;
 mod hero_3125;
 
 use anyhow::Result;
+use rand::rngs::StdRng;
 use sonettobuf::{ActEffect, Fight, FightEntityInfo};
 
-use crate::state::battle::mechanics::bloodtithe::BloodtitheState;
+use crate::state::battle::manager::buff_mgr::BuffMgr;
+use crate::state::battle::mechanics::{bloodtithe::BloodtitheState, status};
+use engine::PassiveTrigger;
+
+/// Passive ids of `model_id` that have their own module instead of going through the engine
+fn bespoke_passives(model_id: i32) -> &'static [i32] {
+    match model_id {
+        3088 => hero_3088::BESPOKE,
+        3120 => hero_3120::BESPOKE,
+        3125 => hero_3125::BESPOKE,
+        _ => &[],
+    }
+}
+
+fn is_sealed(entity: &FightEntityInfo, buff_mgr: &BuffMgr) -> bool {
+    status::is_sealed(buff_mgr, entity.uid.unwrap_or(0))
+}
+
+fn run_engine(
+    rng: &mut StdRng,
+    entity: &FightEntityInfo,
+    fight: &Fight,
+    buff_mgr: &BuffMgr,
+    trigger: PassiveTrigger,
+    target_uid: i64,
+) -> Result<Vec<ActEffect>> {
+    if is_sealed(entity, buff_mgr) {
+        return Ok(vec![]);
+    }
+
+    let skip: Vec<i32> = bespoke_passives(entity.model_id.unwrap_or(0))
+        .iter()
+        .chain(equipment::BESPOKE)
+        .copied()
+        .collect();
+    engine::run_trigger(rng, entity, fight, buff_mgr, trigger, target_uid, &skip)
+}
 
 pub fn build_battle_start_passives(
+    rng: &mut StdRng,
     entity: &FightEntityInfo,
     fight: &Fight,
     bloodtithe: &mut BloodtitheState,
+    buff_mgr: &BuffMgr,
 ) -> Result<Vec<ActEffect>> {
+    if is_sealed(entity, buff_mgr) {
+        return Ok(vec![]);
+    }
+
     let model_id = entity.model_id.unwrap_or(0);
 
     let mut effects = match model_id {
-        3088 => hero_3088::build_battle_start(entity, fight, bloodtithe)?,
-        3120 => hero_3120::build_battle_start(entity, fight, bloodtithe)?,
-        3125 => hero_3125::build_battle_start(entity, fight, bloodtithe)?,
+        3088 => hero_3088::build_battle_start(entity, fight, bloodtithe, buff_mgr)?,
+        3120 => hero_3120::build_battle_start(entity, fight, bloodtithe, buff_mgr)?,
+        3125 => hero_3125::build_battle_start(entity, fight, bloodtithe, buff_mgr)?,
         _ => vec![],
     };
 
-    effects.extend(equipment::build_battle_start(entity, fight)?);
+    effects.extend(run_engine(
+        rng,
+        entity,
+        fight,
+        buff_mgr,
+        PassiveTrigger::BattleStart,
+        0,
+    )?);
+    effects.extend(equipment::build_battle_start(entity, fight, buff_mgr)?);
 
     Ok(effects)
 }
 
 pub fn build_post_power_passives(
+    rng: &mut StdRng,
     entity: &FightEntityInfo,
     fight: &Fight,
+    buff_mgr: &BuffMgr,
 ) -> Result<Vec<ActEffect>> {
+    if is_sealed(entity, buff_mgr) {
+        return Ok(vec![]);
+    }
+
     let model_id = entity.model_id.unwrap_or(0);
 
-    match model_id {
-        3088 => hero_3088::build_post_power(entity, fight),
-        3120 => hero_3120::build_post_power(entity, fight),
-        3125 => hero_3125::build_post_power(entity, fight),
-        _ => Ok(vec![]),
-    }
+    let mut effects = match model_id {
+        3088 => hero_3088::build_post_power(entity, fight)?,
+        _ => vec![],
+    };
+
+    effects.extend(run_engine(
+        rng,
+        entity,
+        fight,
+        buff_mgr,
+        PassiveTrigger::PostPower,
+        0,
+    )?);
+
+    Ok(effects)
 }
 
 pub fn build_round_start_passives(
+    rng: &mut StdRng,
     entity: &FightEntityInfo,
     fight: &Fight,
-    bloodtithe: &mut BloodtitheState,
+    buff_mgr: &BuffMgr,
 ) -> Result<Vec<ActEffect>> {
-    let model_id = entity.model_id.unwrap_or(0);
+    run_engine(rng, entity, fight, buff_mgr, PassiveTrigger::RoundStart, 0)
+}
 
-    match model_id {
-        3088 => hero_3088::build_round_start(entity, fight, bloodtithe),
-        3120 => hero_3120::build_round_start(entity, fight),
-        3125 => hero_3125::build_round_start(entity, fight),
-        _ => Ok(vec![]),
-    }
+/// Passives of `entity` reacting to being hit by `attacker_uid`
+pub fn build_on_hit_passives(
+    rng: &mut StdRng,
+    entity: &FightEntityInfo,
+    attacker_uid: i64,
+    fight: &Fight,
+    buff_mgr: &BuffMgr,
+) -> Result<Vec<ActEffect>> {
+    run_engine(
+        rng,
+        entity,
+        fight,
+        buff_mgr,
+        PassiveTrigger::OnHit,
+        attacker_uid,
+    )
+}
+
+pub fn build_on_death_passives(
+    rng: &mut StdRng,
+    entity: &FightEntityInfo,
+    fight: &Fight,
+    buff_mgr: &BuffMgr,
+) -> Result<Vec<ActEffect>> {
+    run_engine(rng, entity, fight, buff_mgr, PassiveTrigger::OnDeath, 0)
+}
+
+/// Passives of `entity` reacting to killing `victim_uid`
+pub fn build_on_kill_passives(
+    rng: &mut StdRng,
+    entity: &FightEntityInfo,
+    victim_uid: i64,
+    fight: &Fight,
+    buff_mgr: &BuffMgr,
+) -> Result<Vec<ActEffect>> {
+    run_engine(
+        rng,
+        entity,
+        fight,
+        buff_mgr,
+        PassiveTrigger::OnKill,
+        victim_uid,
+    )
+}
+
+/// Passives of `entity` reacting to landing a crit on `target_uid`
+pub fn build_on_crit_passives(
+    rng: &mut StdRng,
+    entity: &FightEntityInfo,
+    target_uid: i64,
+    fight: &Fight,
+    buff_mgr: &BuffMgr,
+) -> Result<Vec<ActEffect>> {
+    run_engine(
+        rng,
+        entity,
+        fight,
+        buff_mgr,
+        PassiveTrigger::OnCrit,
+        target_uid,
+    )
+}
+
+pub fn build_on_ultimate_passives(
+    rng: &mut StdRng,
+    entity: &FightEntityInfo,
+    fight: &Fight,
+    buff_mgr: &BuffMgr,
+) -> Result<Vec<ActEffect>> {
+    run_engine(rng, entity, fight, buff_mgr, PassiveTrigger::OnUltimate, 0)
 }
 
-pub fn build_bootstrap(entity: &FightEntityInfo) -> Result<Vec<ActEffect>> {
+pub fn build_bootstrap(entity: &FightEntityInfo, buff_mgr: &BuffMgr) -> Result<Vec<ActEffect>> {
     let mut effects = Vec::new();
 
-    effects.extend(activity::build_battle_start(entity)?);
+    effects.extend(activity::build_battle_start(entity, buff_mgr)?);
 
     Ok(effects)
 }
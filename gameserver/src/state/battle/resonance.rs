@@ -0,0 +1,147 @@
+//! Resonance (talent cubes)
+//!
+//! Bonuses of the cubes in the hero's active template at its resonance level (`talent`):
+//! - Every cube adds its `talent_cube_attr` row of that level. `hp`, `attack`, `defense`,
+//!   `mdefense` and `technic` are per mille of the base attribute, the other stats are
+//!   per mille rates added on top of the buffs
+//! - The exclusive cube of the level (`character_talent.exclusive`) always counts, even on
+//!   a layout saved before it unlocked
+//! - An empty layout falls back to the `talent_scheme` of the level and its mould
+//! - The style of the active template replaces its cube with the `replaceCube` of
+//!   `talent_style`, for the styles `talent_style_cost` lists for the hero
+
+use database::models::game::heros::HeroData;
+
+use crate::state::battle::manager::buff_mgr::AttrModifiers;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Resonance {
+    /// Per mille of the base attributes
+    pub hp: i32,
+    pub attack: i32,
+    pub defense: i32,
+    pub mdefense: i32,
+    pub technic: i32,
+    pub modifiers: AttrModifiers,
+}
+
+impl Resonance {
+    pub fn scale(base: i32, rate: i32) -> i32 {
+        (base as i64 * (1000 + rate) as i64 / 1000) as i32
+    }
+}
+
+/// Cube ids of the active template, the scheme of the level when it has none
+fn layout_cubes(hero_data: &HeroData) -> Vec<i32> {
+    let game_data = config::configs::get();
+    let record = &hero_data.record;
+
+    if !hero_data.talent_cubes.is_empty() {
+        return hero_data.talent_cubes.iter().map(|c| c.cube_id).collect();
+    }
+
+    let Some(talent) = game_data
+        .character_talent
+        .iter()
+        .find(|t| t.hero_id == record.hero_id && t.talent_id == record.talent)
+    else {
+        return vec![];
+    };
+
+    game_data
+        .talent_scheme
+        .iter()
+        .find(|s| s.talent_id == record.talent && s.talent_mould == talent.talent_mould)
+        .map(|s| {
+            s.talen_scheme
+                .split('#')
+                .filter_map(|cube| cube.split(',').next()?.parse().ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn exclusive_cube(hero_data: &HeroData) -> Option<i32> {
+    let record = &hero_data.record;
+
+    config::configs::get()
+        .character_talent
+        .iter()
+        .find(|t| t.hero_id == record.hero_id && t.talent_id == record.talent)
+        .and_then(|t| t.exclusive.split('#').next()?.parse().ok())
+        .filter(|id| *id != 0)
+}
+
+/// Style of the active template, when it is one of the hero's styles
+fn active_style(hero_data: &HeroData) -> Option<i32> {
+    let record = &hero_data.record;
+
+    let style = hero_data
+        .talent_templates
+        .iter()
+        .find(|(t, _)| t.template_id == record.use_talent_template_id)
+        .map(|(t, _)| t.style)
+        .filter(|s| *s != 0)?;
+
+    config::configs::get()
+        .talent_style_cost
+        .iter()
+        .any(|c| c.hero_id == record.hero_id && c.style_id == style)
+        .then_some(style)
+}
+
+pub fn resonance(hero_data: &HeroData) -> Resonance {
+    let game_data = config::configs::get();
+    let level = hero_data.record.talent;
+
+    let mut cubes = layout_cubes(hero_data);
+    if let Some(exclusive) = exclusive_cube(hero_data)
+        && !cubes.contains(&exclusive)
+    {
+        cubes.push(exclusive);
+    }
+
+    if let Some(style) = active_style(hero_data) {
+        for cube in cubes.iter_mut() {
+            if let Some(replace) = game_data
+                .talent_style
+                .iter()
+                .find(|s| s.cube_id == *cube && s.style_id == style)
+            {
+                *cube = replace.replace_cube;
+            }
+        }
+    }
+
+    let mut total = Resonance::default();
+
+    for cube in cubes {
+        let Some(attr) = game_data
+            .talent_cube_attr
+            .iter()
+            .find(|a| a.id == cube && a.level == level)
+        else {
+            continue;
+        };
+
+        total.hp += attr.hp;
+        total.attack += attr.attack;
+        total.defense += attr.defense;
+        total.mdefense += attr.mdefense;
+        total.technic += attr.technic;
+        total.modifiers += AttrModifiers {
+            cri: attr.cri,
+            recri: attr.recri,
+            cri_dmg: attr.cri_dmg,
+            cri_def: attr.cri_def,
+            add_dmg: attr.add_dmg,
+            drop_dmg: attr.drop_dmg,
+            heal: attr.heal,
+            defense_ignore: attr.defense_ignore,
+            normal_skill_rate: attr.normal_skill_rate,
+            ..Default::default()
+        };
+    }
+
+    total
+}
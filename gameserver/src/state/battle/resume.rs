@@ -0,0 +1,172 @@
+//! Fight persistence
+//!
+//! The active battle is saved when the fight starts, after every round and after every
+//! cloth skill, and dropped when it ends. The saved row carries what the client is handed
+//! back on reconnect: the fight as of the last round, that round, the decks and the fight
+//! group.
+//!
+//! `FightDataMgr` is saved next to it and the fight resumes from that. Fights saved before
+//! it was kept are rebuilt the way the replay verifier does it: the fight is created again
+//! from the seed, every recorded round is played back and then the cloth skills used in
+//! the current round. A rebuilt fight that doesn't match the saved one can't be resumed.
+//!
+//! Replays of recorded fights are saved the same way. A rebuilt replay plays back the
+//! rounds of the fight it replays.
+
+use anyhow::Result;
+use database::db::game::battle::{delete_active_battle, load_round_operations, save_active_battle};
+use database::models::game::battle::SavedBattle;
+use sqlx::SqlitePool;
+
+use super::manager::fight_data_mgr::FightDataMgr;
+use super::simulator::BattleSimulator;
+use super::verify::{battle_outcome, compare_outcomes};
+use super::{BattleContext, create_battle, default_max_ap, generate_initial_deck};
+use crate::state::ActiveBattle;
+
+/// Save the fight at its current round boundary
+pub async fn persist_battle(pool: &SqlitePool, user_id: i64, battle: &ActiveBattle) -> Result<()> {
+    let saved = SavedBattle {
+        user_id,
+        battle_id: battle.fight_id.unwrap_or_default(),
+        episode_id: battle.episode_id,
+        chapter_id: battle.chapter_id,
+        tower_type: battle.tower_type,
+        tower_id: battle.tower_id,
+        layer_id: battle.layer_id,
+        difficulty: battle.difficulty,
+        talent_plan_id: battle.talent_plan_id,
+        current_round: battle.current_round,
+        act_point: battle.act_point,
+        power: battle.power,
+        multiplication: battle.multiplication,
+        seed: battle.seed,
+        fight: battle.fight.clone().unwrap_or_default(),
+        last_round: battle.last_round.clone(),
+        fight_group: battle.fight_group.clone().unwrap_or_default(),
+        current_deck: battle.current_deck.clone(),
+        ai_deck: battle.ai_deck.clone(),
+        cloth_opers: battle.cloth_opers.clone(),
+        fight_data: battle
+            .fight_data_mgr
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()?,
+        is_replay: battle.is_replay.unwrap_or(false),
+        replay_battle_id: battle.replay_battle_id,
+    };
+
+    save_active_battle(pool, &saved).await
+}
+
+/// Drop the saved fight once it's over
+pub async fn forget_battle(pool: &SqlitePool, user_id: i64) -> Result<()> {
+    delete_active_battle(pool, user_id).await
+}
+
+/// Bring a saved fight back with its fight state as of the saved round
+pub async fn restore_battle(pool: &SqlitePool, saved: SavedBattle) -> Result<ActiveBattle> {
+    let fight_data_mgr = match &saved.fight_data {
+        Some(fight_data) => serde_json::from_str(fight_data)?,
+        None => rebuild_fight_data(pool, &saved).await?,
+    };
+
+    Ok(ActiveBattle {
+        tower_type: saved.tower_type,
+        tower_id: saved.tower_id,
+        layer_id: saved.layer_id,
+        episode_id: saved.episode_id,
+        chapter_id: saved.chapter_id,
+        difficulty: saved.difficulty,
+        talent_plan_id: saved.talent_plan_id,
+        fight: Some(saved.fight),
+        last_round: saved.last_round,
+        current_round: saved.current_round,
+        act_point: saved.act_point,
+        power: saved.power,
+        current_deck: saved.current_deck,
+        fight_group: Some(saved.fight_group),
+        fight_id: Some(saved.battle_id),
+        is_replay: Some(saved.is_replay),
+        replay_battle_id: saved.replay_battle_id,
+        multiplication: saved.multiplication,
+        ai_deck: saved.ai_deck,
+        cloth_opers: saved.cloth_opers,
+        fight_data_mgr: Some(fight_data_mgr),
+        seed: saved.seed,
+    })
+}
+
+async fn rebuild_fight_data(pool: &SqlitePool, saved: &SavedBattle) -> Result<FightDataMgr> {
+    let game_data = config::configs::get();
+
+    let battle_id = game_data
+        .episode
+        .get(saved.episode_id)
+        .map(|e| e.battle_id)
+        .ok_or_else(|| anyhow::anyhow!("Unknown episode {}", saved.episode_id))?;
+
+    // same AP the start handlers give
+    let hero_count = saved
+        .fight_group
+        .hero_list
+        .iter()
+        .filter(|&&u| u != 0)
+        .count();
+    let max_ap = default_max_ap(saved.episode_id, hero_count);
+
+    let ctx = BattleContext {
+        player_id: saved.user_id,
+        chapter_id: saved.chapter_id,
+        episode_id: saved.episode_id,
+        battle_id,
+        max_ap,
+        seed: saved.seed,
+    };
+
+    let card_push =
+        generate_initial_deck(pool, saved.user_id, &saved.fight_group, max_ap, saved.seed).await?;
+    let mut deck = card_push.card_group;
+
+    let (_, _, fight_data_mgr, mut ai_deck) =
+        create_battle(pool, ctx, &saved.fight_group, deck.clone()).await?;
+
+    let mut simulator = BattleSimulator::new(fight_data_mgr, saved.seed);
+    let mut rounds = 0;
+
+    // a replay isn't recorded, its rounds are the ones of the fight it plays back
+    let recorded_id = saved.replay_battle_id.unwrap_or(saved.battle_id);
+    for (round_num, record) in load_round_operations(pool, saved.user_id, recorded_id).await? {
+        if round_num >= saved.current_round {
+            break;
+        }
+
+        let round = simulator
+            .replay_round(round_num, record, deck, ai_deck)
+            .await?;
+
+        deck = round.team_a_cards1;
+        ai_deck = round.ai_use_cards;
+        rounds = round.cur_round.unwrap_or(round_num);
+    }
+
+    // cloth skills already used in the round the player is in
+    for (index, oper) in saved.cloth_opers.iter().enumerate() {
+        simulator.use_cloth_skill(saved.current_round, index, oper, &mut deck)?;
+    }
+
+    let diffs = compare_outcomes(
+        &battle_outcome(&saved.fight, rounds),
+        &battle_outcome(&simulator.fight_snapshot(), rounds),
+    );
+    if !diffs.is_empty() {
+        anyhow::bail!(
+            "rebuilt fight {} of user {} differs from the saved one: {}",
+            saved.battle_id,
+            saved.user_id,
+            diffs.join("; ")
+        );
+    }
+
+    Ok(simulator.into_data())
+}
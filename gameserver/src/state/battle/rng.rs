@@ -0,0 +1,47 @@
+//! Battle randomness
+//!
+//! A fight rolls one seed when it starts. Everything random in the fight draws from
+//! a stream derived from that seed (the player's opening hand, the AI deck, the battle
+//! start passives, one stream per round and one per cloth skill used), so the seed plus
+//! the recorded opers replays the fight exactly.
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+#[derive(Debug, Clone, Copy)]
+pub enum RngStream {
+    PlayerDeck,
+    AiDeck,
+    /// Passives of the initial round
+    BattleStart,
+    Round(i32),
+    /// The `n`th cloth skill used before a round
+    ClothSkill(i32, usize),
+}
+
+impl RngStream {
+    fn salt(self) -> u64 {
+        match self {
+            RngStream::PlayerDeck => 1,
+            RngStream::AiDeck => 2,
+            RngStream::BattleStart => 3,
+            RngStream::Round(round) => 0x100 + round as u64,
+            RngStream::ClothSkill(round, n) => 0x1_0000 + ((round as u64) << 8) + n as u64,
+        }
+    }
+}
+
+pub fn new_battle_seed() -> u64 {
+    rand::random()
+}
+
+pub fn stream_rng(seed: u64, stream: RngStream) -> StdRng {
+    StdRng::seed_from_u64(splitmix64(seed ^ splitmix64(stream.salt())))
+}
+
+fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
@@ -2,12 +2,20 @@ use anyhow::Result;
 use sonettobuf::{ActEffect, CardInfo, Fight, FightEntityInfo};
 use std::collections::HashMap;
 
-use crate::state::battle::manager::buff_mgr::BuffMgr;
+use crate::state::battle::{
+    cards, cloth,
+    manager::{buff_mgr::BuffMgr, ex_point_mgr},
+    uids::UidCounter,
+};
 
 #[allow(dead_code)]
 pub struct RoundState {
     pub entities: HashMap<i64, FightEntityInfo>,
+    /// Heroes on the field, the ones whose cards make up the hand
+    pub hero_uids: Vec<i64>,
     pub buff_mgr: BuffMgr,
+    /// Uids of the cards dealt this round
+    pub card_uids: UidCounter,
     pub act_point: i32,
     pub power: i32,
     pub player_deck: Vec<CardInfo>,
@@ -17,14 +25,28 @@ pub struct RoundState {
     pub move_num: i32,
     pub is_finish: bool,
     pub pending_effects: Vec<ActEffect>,
+    /// Last unit to damage each unit
+    pub last_attackers: HashMap<i64, i64>,
 }
 
 #[allow(dead_code)]
 impl RoundState {
     pub fn new(fight: &Fight) -> Result<Self> {
+        let mut state = Self::between_rounds(fight);
+        state.act_point =
+            cards::default_max_ap(fight.episode_id.unwrap_or(0), state.hero_uids.len());
+
+        Ok(state)
+    }
+
+    /// The units of the fight without the AP of a round, enough to deal the hand
+    pub fn between_rounds(fight: &Fight) -> Self {
         let mut entities = HashMap::new();
+        let mut hero_uids = Vec::new();
 
         if let Some(attacker) = &fight.attacker {
+            hero_uids.extend(attacker.entitys.iter().filter_map(|e| e.uid));
+
             for e in attacker.entitys.iter().chain(attacker.sub_entitys.iter()) {
                 if let Some(uid) = e.uid {
                     entities.insert(uid, e.clone());
@@ -40,11 +62,13 @@ impl RoundState {
             }
         }
 
-        Ok(Self {
+        Self {
             entities,
+            hero_uids,
             buff_mgr: BuffMgr::new(),
-            act_point: 4,
-            power: 15,
+            card_uids: UidCounter::default(),
+            act_point: 0,
+            power: cloth::power(fight),
             player_deck: vec![],
             ai_cards: vec![],
             used_cards: vec![],
@@ -52,7 +76,8 @@ impl RoundState {
             move_num: 0,
             is_finish: false,
             pending_effects: vec![],
-        })
+            last_attackers: HashMap::new(),
+        }
     }
 
     pub fn get_entity(&self, uid: i64) -> Option<&FightEntityInfo> {
@@ -77,21 +102,7 @@ impl RoundState {
 
     pub fn build_ex_point_info(&self) -> Vec<sonettobuf::FightExPointInfo> {
         self.iter_entities()
-            .map(|entity| {
-                let ex_point_type = if entity.model_id == Some(3120) {
-                    Some(1)
-                } else {
-                    entity.ex_point_type.or(Some(0))
-                };
-
-                sonettobuf::FightExPointInfo {
-                    uid: entity.uid,
-                    ex_point: entity.ex_point,
-                    power_infos: entity.power_infos.clone(),
-                    current_hp: entity.current_hp,
-                    ex_point_type,
-                }
-            })
+            .map(ex_point_mgr::ex_point_info)
             .collect()
     }
 
@@ -1,16 +1,25 @@
-use crate::state::battle::manager::fight_data_mgr::FightDataMgr;
+use crate::state::battle::manager::{buff_mgr::AttrModifiers, fight_data_mgr::FightDataMgr};
+use crate::state::battle::rng::{RngStream, stream_rng};
 
 use anyhow::Result;
 use sonettobuf::{CardInfo, Fight, FightRound};
+use std::collections::HashMap;
 
 pub async fn build_initial_round(
     fight: Fight,
+    resonance: HashMap<i64, AttrModifiers>,
     player_deck: Vec<CardInfo>,
     ai_deck: Vec<CardInfo>,
+    seed: u64,
 ) -> Result<(FightRound, Fight, FightDataMgr)> {
     let mut fight_mgr = FightDataMgr::new(fight);
 
-    let round = fight_mgr.build_initial_round(player_deck, ai_deck)?;
+    for (uid, modifiers) in resonance {
+        fight_mgr.buff_mgr.set_base_modifiers(uid, modifiers);
+    }
+
+    let mut rng = stream_rng(seed, RngStream::BattleStart);
+    let round = fight_mgr.build_initial_round(&mut rng, player_deck, ai_deck)?;
 
     let updated_fight = fight_mgr.get_fight_owned();
 
@@ -1,19 +1,21 @@
 use anyhow::Result;
-use rand::SeedableRng;
-use rand::rngs::StdRng;
-use sonettobuf::{BeginRoundOper, CardInfo, FightRound};
+use sonettobuf::{
+    BeginRoundOper, CardInfo, FightRound, FightRoundOperRecord, UseClothSkillOperRecord,
+};
 
+use crate::state::battle::cloth;
 use crate::state::battle::manager::fight_data_mgr::FightDataMgr;
+use crate::state::battle::mechanics::ledger::EffectLedger;
+use crate::state::battle::rng::{RngStream, stream_rng};
+use crate::state::battle::round::RoundState;
 
 pub struct BattleSimulator {
-    rng: StdRng,
+    seed: u64,
     data: FightDataMgr,
 }
 
 impl BattleSimulator {
-    pub fn new(data: FightDataMgr) -> Self {
-        let seed = chrono::Utc::now().timestamp_millis() as u64;
-
+    pub fn new(data: FightDataMgr, seed: u64) -> Self {
         let fight = data.get_fight_snapshot();
         tracing::info!(
             "Initialized battle with {} player entities, {} enemy entities",
@@ -29,25 +31,46 @@ impl BattleSimulator {
                 .unwrap_or(0),
         );
 
-        Self {
-            rng: StdRng::seed_from_u64(seed),
-            data,
-        }
+        Self { seed, data }
+    }
+
+    pub fn fight_snapshot(&self) -> std::sync::Arc<sonettobuf::Fight> {
+        self.data.get_fight_snapshot()
+    }
+
+    /// Fight state as of the last processed round
+    pub fn into_data(self) -> FightDataMgr {
+        self.data
+    }
+
+    pub fn current_wave(&self) -> i32 {
+        self.data.get_fight_snapshot().cur_wave.unwrap_or(1)
+    }
+
+    /// 1 for a win, 2 for a loss, 0 while the fight goes on, see `StageMgr::fight_result`
+    pub fn fight_result(&self) -> i32 {
+        self.data
+            .round_mgr
+            .stage()
+            .fight_result(&self.data.get_fight_snapshot())
     }
 
     pub async fn process_round(
         &mut self,
+        round_num: i32,
         operations: Vec<BeginRoundOper>,
         current_deck: Vec<CardInfo>,
         ai_deck: Vec<CardInfo>,
     ) -> Result<FightRound> {
+        let mut rng = stream_rng(self.seed, RngStream::Round(round_num));
+
         let round = {
             let (round_mgr, card_mgr, calc, fight, bloodtithe, buff_mgr) =
                 self.data.split_all_mut();
 
             round_mgr
                 .process_round(
-                    &mut self.rng,
+                    &mut rng,
                     card_mgr,
                     calc,
                     fight,
@@ -63,4 +86,66 @@ impl BattleSimulator {
         self.data.update_managers();
         Ok(round)
     }
+
+    /// Use the `index`th cloth skill before round `round_num`, `hand` is the player's hand
+    /// before and after it
+    pub fn use_cloth_skill(
+        &mut self,
+        round_num: i32,
+        index: usize,
+        oper: &UseClothSkillOperRecord,
+        hand: &mut Vec<CardInfo>,
+    ) -> Result<FightRound> {
+        let mut rng = stream_rng(self.seed, RngStream::ClothSkill(round_num, index));
+
+        let round = {
+            let (_, card_mgr, calc, fight, bloodtithe, buff_mgr) = self.data.split_all_mut();
+
+            let mut state = RoundState::new(fight)?;
+            state.buff_mgr = buff_mgr.clone();
+            state.card_uids = card_mgr.uids.clone();
+            state.player_deck = hand.clone();
+
+            let mut step = cloth::use_skill(&mut rng, fight, &mut state, oper)?;
+            card_mgr.uids.catch_up(&state.card_uids);
+            EffectLedger::new(fight).settle(&mut step, buff_mgr);
+            calc.play_step_data(&step, fight, bloodtithe, buff_mgr)
+                .map_err(anyhow::Error::msg)?;
+
+            let before = std::mem::replace(hand, state.player_deck.clone());
+
+            FightRound {
+                fight_step: vec![step],
+                act_point: Some(state.act_point),
+                is_finish: Some(false),
+                move_num: Some(0),
+                ex_point_info: calc.build_ex_point_info(fight),
+                power: Some(cloth::power(fight)),
+                skill_infos: cloth::skill_infos(fight),
+                before_cards1: before,
+                team_a_cards1: state.player_deck,
+                cur_round: Some(round_num),
+                ..Default::default()
+            }
+        };
+
+        self.data.update_managers();
+        Ok(round)
+    }
+
+    /// Play a recorded round: its cloth skills, then its opers
+    pub async fn replay_round(
+        &mut self,
+        round_num: i32,
+        record: FightRoundOperRecord,
+        mut current_deck: Vec<CardInfo>,
+        ai_deck: Vec<CardInfo>,
+    ) -> Result<FightRound> {
+        for (index, oper) in record.cloth_skill_opers.iter().enumerate() {
+            self.use_cloth_skill(round_num, index, oper, &mut current_deck)?;
+        }
+
+        self.process_round(round_num, record.opers, current_deck, ai_deck)
+            .await
+    }
 }
@@ -1,29 +1,47 @@
 use anyhow::Result;
 use config::configs;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use sonettobuf::effect_type_enum::EffectType;
 use sonettobuf::{
-    ActEffect, BuffInfo, FightEntityInfo, FightHurtInfo, FightStep, fight_hurt_info, fight_step,
+    ActEffect, FightEntityInfo, FightHurtInfo, FightStep, fight_hurt_info, fight_step,
 };
 use std::collections::HashMap;
-use std::sync::atomic::{AtomicI64, Ordering};
 
+use crate::state::battle::conditions::Condition;
+use crate::state::battle::damage::{self, DamageInput, RateUp, Restraint, SkillKind};
 use crate::state::battle::manager::buff_mgr::BuffMgr;
+use crate::state::battle::manager::calculate_mgr::FightCalculateDataMgr;
+use crate::state::battle::manager::deck_mgr;
+use crate::state::battle::passives::engine::PassiveTrigger;
+use crate::state::battle::targets::{Target, TargetPool};
 
 use super::utils::VfxConfig;
 
-static BUFF_UID_COUNTER: AtomicI64 = AtomicI64::new(1000);
-
 pub struct SkillExecutor {
     entities: HashMap<i64, FightEntityInfo>,
+    /// Last unit to damage each unit, for the skills aimed back at it
+    last_attackers: HashMap<i64, i64>,
+    /// Crit rolls and random targets
+    rng: StdRng,
 }
 
 impl SkillExecutor {
-    pub fn new(entities: HashMap<i64, FightEntityInfo>) -> Self {
-        Self { entities }
+    pub fn new(entities: HashMap<i64, FightEntityInfo>, rng: StdRng) -> Self {
+        Self {
+            entities,
+            last_attackers: HashMap::new(),
+            rng,
+        }
+    }
+
+    pub fn with_last_attackers(mut self, last_attackers: HashMap<i64, i64>) -> Self {
+        self.last_attackers = last_attackers;
+        self
     }
 
     pub fn execute_skill(
-        &self,
+        &mut self,
         caster_uid: i64,
         target_uid: i64,
         skill_id: i32,
@@ -33,6 +51,11 @@ impl SkillExecutor {
         let game_data = configs::get();
 
         let skill_data = game_data.skill_effect.iter().find(|s| s.id == skill_id);
+        let kind = self
+            .entities
+            .get(&caster_uid)
+            .map(|caster| SkillKind::of(caster, skill_id))
+            .unwrap_or(SkillKind::Passive);
 
         tracing::warn!("=== EXECUTING SKILL {} ===", skill_id);
 
@@ -53,7 +76,7 @@ impl SkillExecutor {
             let behavior_target = self.get_behavior_target(skill_id, i);
             let condition_target = self.get_condition_target(skill_id, i);
             // 999 = inherit condition target (passive semantics)
-            let effective_target = if behavior_target == 999 {
+            let effective_target = if behavior_target.code == Target::INHERIT {
                 condition_target
             } else {
                 behavior_target
@@ -63,21 +86,33 @@ impl SkillExecutor {
                 "Behavior slot {}: behavior='{}', target={} (effective={})",
                 i,
                 behavior,
-                behavior_target,
-                effective_target
+                behavior_target.code,
+                effective_target.code
             );
 
             // Check condition if present
             if !condition.is_empty() {
                 let condition_target = self.get_condition_target(skill_id, i);
-                if !self.check_condition(caster_uid, &condition, condition_target, buff_mgr) {
+                if !self.check_condition(
+                    caster_uid,
+                    target_uid,
+                    &condition,
+                    condition_target,
+                    buff_mgr,
+                ) {
                     continue;
                 }
             }
 
             // Execute behavior
-            let behavior_effects =
-                self.execute_behavior(caster_uid, target_uid, &behavior, effective_target)?;
+            let behavior_effects = self.execute_behavior(
+                caster_uid,
+                target_uid,
+                &behavior,
+                effective_target,
+                kind,
+                buff_mgr,
+            )?;
             effects.extend(behavior_effects);
         }
 
@@ -91,9 +126,13 @@ impl SkillExecutor {
                 skill_id,
                 skill.damage_rate
             );
-            if let Some(damage_effect) =
-                self.calculate_damage_effect(caster_uid, target_uid, skill.damage_rate, false)
-            {
+            if let Some(damage_effect) = self.calculate_damage_effect(
+                caster_uid,
+                target_uid,
+                skill.damage_rate,
+                kind,
+                buff_mgr,
+            ) {
                 effects.push(damage_effect);
             }
         }
@@ -110,59 +149,115 @@ impl SkillExecutor {
         })
     }
 
-    fn check_condition(
-        &self,
+    /// Whether one of the skill's behaviors deals damage
+    pub fn is_attack_skill(skill_id: i32) -> bool {
+        let game_data = configs::get();
+        let probe = Self::new(HashMap::new(), StdRng::seed_from_u64(0));
+
+        (1..=20).any(|i| {
+            let behavior = probe.get_behavior(skill_id, i);
+            let behavior_id: i32 = behavior
+                .split('#')
+                .next()
+                .and_then(|id| id.parse().ok())
+                .unwrap_or(0);
+
+            game_data.skill_behavior.get(behavior_id).is_some_and(|b| {
+                matches!(
+                    b.r#type.as_str(),
+                    "Damage" | "Damage2" | "Detonate" | "Detonate2"
+                )
+            })
+        })
+    }
+
+    /// Effects of the behavior slots of a passive that fire on `trigger`
+    pub fn execute_passive(
+        &mut self,
         caster_uid: i64,
-        condition: &str,
-        condition_target: i32,
+        target_uid: i64,
+        skill_id: i32,
+        trigger: PassiveTrigger,
         buff_mgr: &BuffMgr,
-    ) -> bool {
-        if condition.is_empty() {
-            return true;
-        }
-
-        let parts: Vec<&str> = condition.split('#').collect();
-        let cond_id: i32 = parts[0].parse().unwrap_or(0);
-
-        match cond_id {
-            203 => true,
-            208 => true, // always true
-
-            57210 => {
-                // has buff
-                let buff_id: i32 = parts.get(1).and_then(|v| v.parse().ok()).unwrap_or(0);
+    ) -> Result<Vec<ActEffect>> {
+        let mut effects = Vec::new();
 
-                match condition_target {
-                    101 | 103 => {
-                        // all allies must have buff
-                        self.entities
-                            .values()
-                            .filter(|e| e.team_type == self.entities[&caster_uid].team_type)
-                            .all(|e| buff_mgr.has_buff(e.uid.unwrap(), buff_id))
-                    }
-                    _ => false,
-                }
+        for i in 1..=20 {
+            let behavior = self.get_behavior(skill_id, i);
+            if behavior.is_empty() {
+                continue;
             }
 
-            19210 => {
-                // does NOT have buff
-                let buff_id: i32 = parts.get(1).and_then(|v| v.parse().ok()).unwrap_or(0);
-                !buff_mgr.has_buff(caster_uid, buff_id)
+            let condition = self.get_condition(skill_id, i);
+            if PassiveTrigger::of_condition(&condition) != trigger {
+                continue;
             }
 
-            _ => {
-                tracing::warn!("Unknown condition: {}", condition);
-                false
+            let condition_target = self.get_condition_target(skill_id, i);
+            if !PassiveTrigger::is_trigger_condition(&condition)
+                && !self.check_condition(
+                    caster_uid,
+                    target_uid,
+                    &condition,
+                    condition_target,
+                    buff_mgr,
+                )
+            {
+                continue;
             }
+
+            let behavior_target = match self.get_behavior_target(skill_id, i) {
+                t if t.code == Target::INHERIT => condition_target,
+                t => t,
+            };
+
+            effects.extend(self.execute_behavior(
+                caster_uid,
+                target_uid,
+                &behavior,
+                behavior_target,
+                SkillKind::Passive,
+                buff_mgr,
+            )?);
         }
+
+        Ok(effects)
+    }
+
+    fn check_condition(
+        &mut self,
+        caster_uid: i64,
+        target_uid: i64,
+        condition: &str,
+        condition_target: Target,
+        buff_mgr: &BuffMgr,
+    ) -> bool {
+        let condition = match Condition::parse(condition) {
+            Ok(condition) => condition,
+            Err(e) => {
+                tracing::debug!("Skipping behavior, {}", e);
+                return false;
+            }
+        };
+
+        let targets = self.resolve_targets(caster_uid, target_uid, condition_target);
+        let units: Vec<_> = targets
+            .iter()
+            .filter_map(|uid| self.entities.get(uid))
+            .filter(|e| e.current_hp.unwrap_or(0) > 0)
+            .collect();
+
+        condition.holds(&units, buff_mgr)
     }
 
     fn execute_behavior(
-        &self,
+        &mut self,
         caster_uid: i64,
         target_uid: i64,
         behavior: &str,
-        behavior_target: i32,
+        behavior_target: Target,
+        kind: SkillKind,
+        buff_mgr: &BuffMgr,
     ) -> Result<Vec<ActEffect>> {
         let game_data = configs::get();
 
@@ -202,6 +297,11 @@ impl SkillExecutor {
                 targets = vec![caster_uid];
             }
 
+            // card behaviors act on the hand once, whoever the skill targets
+            "CardLevelAdd" | "CardRemove" | "ChangeToTempCard" | "UniversalCard" => {
+                targets = vec![caster_uid];
+            }
+
             _ => {}
         }
 
@@ -211,7 +311,7 @@ impl SkillExecutor {
             match behavior_type {
                 "Damage" | "Damage2" | "Detonate" | "Detonate2" => {
                     if let Some(effect) =
-                        self.calculate_damage_effect(caster_uid, target, param1, false)
+                        self.calculate_damage_effect(caster_uid, target, param1, kind, buff_mgr)
                     {
                         effects.push(effect);
                     }
@@ -219,12 +319,17 @@ impl SkillExecutor {
 
                 "Heal" | "HealCantCrit" => {
                     if let Some(effect) =
-                        self.calculate_heal_effect(caster_uid, target, param1, false)
+                        self.calculate_heal_effect(caster_uid, target, param1, false, buff_mgr)
                     {
                         effects.push(effect);
                     }
                 }
 
+                "Shield" => {
+                    effects
+                        .extend(self.calculate_shield_effect(caster_uid, target, param1, buff_mgr));
+                }
+
                 "HealByTwoAttr" => {
                     effects.extend(self.execute_heal_by_two_attr(caster_uid, target, &parts)?);
                 }
@@ -234,7 +339,7 @@ impl SkillExecutor {
                 | "AddBuffRound2"
                 | "ConsumeBloodAddBuff"
                 | "CreateAdditionalDamageAddBuff" => {
-                    effects.push(self.create_buff_effect(caster_uid, target, param1));
+                    effects.extend(buff_mgr.add_buff_effects(target, param1, caster_uid));
                 }
 
                 "AddExPoint" => {
@@ -246,6 +351,10 @@ impl SkillExecutor {
                     });
                 }
 
+                "CardLevelAdd" | "CardRemove" | "ChangeToTempCard" | "UniversalCard" => {
+                    effects.extend(deck_mgr::behavior_effect(behavior_type, caster_uid, param1));
+                }
+
                 "Purify1" | "Purify2" => {
                     effects.push(ActEffect {
                         effect_type: Some(EffectType::Purify as i32),
@@ -253,6 +362,17 @@ impl SkillExecutor {
                         effect_num: Some(param1),
                         ..Default::default()
                     });
+                    effects.extend(buff_mgr.purify(target, param1));
+                }
+
+                "Disperse1" | "Disperse2" => {
+                    effects.push(ActEffect {
+                        effect_type: Some(EffectType::Disperse as i32),
+                        target_id: Some(target),
+                        effect_num: Some(param1),
+                        ..Default::default()
+                    });
+                    effects.extend(buff_mgr.disperse(target, param1));
                 }
 
                 "Bloodlust" => {
@@ -392,42 +512,73 @@ impl SkillExecutor {
     }
 
     fn calculate_damage_effect(
-        &self,
+        &mut self,
         caster_uid: i64,
         target_uid: i64,
         base_param: i32,
-        is_crit: bool,
+        kind: SkillKind,
+        buff_mgr: &BuffMgr,
     ) -> Option<ActEffect> {
         let caster = self.entities.get(&caster_uid)?;
         let target = self.entities.get(&target_uid)?;
 
-        // Get stats
-        let caster_attack = caster.attr.as_ref()?.attack.unwrap_or(100);
-        let target_defense = target.attr.as_ref()?.defense.unwrap_or(50);
+        let input = DamageInput {
+            attacker: FightCalculateDataMgr::combat_attr(caster, buff_mgr),
+            target: FightCalculateDataMgr::combat_attr(target, buff_mgr),
+            attacker_career: caster.career.unwrap_or(0),
+            target_career: target.career.unwrap_or(0),
+            dmg_type: damage::dmg_type(caster),
+            rate: base_param,
+            rate_up: self.rate_up(caster),
+            moxie: caster.ex_point.unwrap_or(0),
+            kind,
+            crit_roll: (kind != SkillKind::Passive).then(|| self.rng.gen_range(0..1000)),
+        };
+        let result = damage::compute(&input);
 
-        // Simplified damage calculation
-        // TODO: Implement full formula with penetration, def reduction, dmg bonus, etc.
-        let penetration_rate = 0.0;
-        let def_reduction = 0.0;
-        let effective_defense =
-            (target_defense as f32 * (1.0 - penetration_rate) * (1.0 - def_reduction)) as i32;
-        let attack_contribution = (caster_attack - effective_defense).max(0);
+        tracing::debug!(
+            "Damage calc: atk={}, rate={}, kind={:?}, {:?}",
+            input.attacker.attack,
+            base_param,
+            kind,
+            result
+        );
 
-        let skill_multiplier = base_param as f32 / 1000.0;
-        let crit_multiplier = if is_crit { 1.5 } else { 1.0 };
+        Some(self.create_damage_effect(
+            target_uid,
+            result.damage,
+            result.critical,
+            result.restraint == Restraint::Advantage,
+        ))
+    }
 
-        let final_damage = (attack_contribution as f32 * skill_multiplier * crit_multiplier) as i32;
-        let final_damage = final_damage.max(1);
+    /// `SkillRateUp` behaviors of the unconditional slots of the caster's passives
+    fn rate_up(&self, caster: &FightEntityInfo) -> RateUp {
+        let game_data = configs::get();
+        let mut rate_up = RateUp::default();
 
-        tracing::debug!(
-            "Damage calc: atk={}, def={}, mult={}, final={}",
-            caster_attack,
-            target_defense,
-            skill_multiplier,
-            final_damage
-        );
+        for &passive_id in &caster.passive_skill {
+            for i in 1..=20 {
+                if !self.get_condition(passive_id, i).is_empty() {
+                    continue;
+                }
+
+                let behavior = self.get_behavior(passive_id, i);
+                let mut parts = behavior.split('#');
+                let Some(behavior) = parts
+                    .next()
+                    .and_then(|id| id.parse().ok())
+                    .and_then(|id| game_data.skill_behavior.get(id))
+                else {
+                    continue;
+                };
+                let value = parts.next().and_then(|p| p.parse().ok()).unwrap_or(0);
 
-        Some(self.create_damage_effect(target_uid, final_damage, is_crit))
+                rate_up.add(&behavior.r#type, value);
+            }
+        }
+
+        rate_up
     }
 
     fn calculate_heal_effect(
@@ -436,13 +587,17 @@ impl SkillExecutor {
         target_uid: i64,
         base_param: i32,
         is_crit: bool,
+        buff_mgr: &BuffMgr,
     ) -> Option<ActEffect> {
         let caster = self.entities.get(&caster_uid)?;
-        let caster_attack = caster.attr.as_ref()?.attack.unwrap_or(100);
+        let caster_attr = FightCalculateDataMgr::combat_attr(caster, buff_mgr);
+        let caster_attack = caster_attr.attack;
 
         // Simplified heal calculation
         let heal_contribution = (caster_attack as f32 * (base_param as f32 / 100.0)) as i32;
-        let final_heal = (base_param + heal_contribution).max(1);
+        let heal_multiplier = ((1000 + caster_attr.heal) as f32 / 1000.0).max(0.0);
+        let final_heal = (((base_param + heal_contribution) as f32) * heal_multiplier) as i32;
+        let final_heal = final_heal.max(1);
 
         tracing::debug!(
             "Heal calc: base={}, atk={}, final={}",
@@ -454,50 +609,21 @@ impl SkillExecutor {
         Some(self.create_heal_effect(target_uid, final_heal, is_crit))
     }
 
-    fn resolve_targets(&self, caster_uid: i64, target_uid: i64, behavior_target: i32) -> Vec<i64> {
-        match behavior_target {
-            // Self
-            0 | 102 => vec![caster_uid],
-
-            // Explicit selected target (card target)
-            1 | 2 => vec![target_uid],
-
-            // All allies (including self)
-            101 | 103 => self
-                .entities
-                .values()
-                .filter(|e| e.team_type == self.entities[&caster_uid].team_type)
-                .filter_map(|e| e.uid)
-                .collect(),
-
-            // All enemies
-            201 | 202 => self
-                .entities
-                .values()
-                .filter(|e| e.team_type != self.entities[&caster_uid].team_type)
-                .filter_map(|e| e.uid)
-                .collect(),
-
-            999 => {
-                if target_uid != 0 && self.entities.contains_key(&target_uid) {
-                    vec![target_uid]
-                } else {
-                    tracing::warn!("behavior_target=999 but no valid target; defaulting to self");
-                    vec![caster_uid]
-                }
-            }
-
-            _ => {
-                tracing::warn!(
-                    "Unknown behavior_target {}, defaulting to caster",
-                    behavior_target
-                );
-                vec![caster_uid]
-            }
-        }
+    fn resolve_targets(&mut self, caster_uid: i64, target_uid: i64, target: Target) -> Vec<i64> {
+        let pool = TargetPool {
+            entities: &self.entities,
+            last_attackers: &self.last_attackers,
+        };
+        pool.resolve(caster_uid, target_uid, target, &mut self.rng)
     }
 
-    fn create_damage_effect(&self, target_id: i64, damage: i32, is_crit: bool) -> ActEffect {
+    fn create_damage_effect(
+        &self,
+        target_id: i64,
+        damage: i32,
+        is_crit: bool,
+        career_restraint: bool,
+    ) -> ActEffect {
         ActEffect {
             effect_type: Some(if is_crit {
                 EffectType::Crit as i32
@@ -511,7 +637,7 @@ impl SkillExecutor {
                 damage: Some(damage),
                 reduce_hp: Some(damage),
                 reduce_shield: Some(0),
-                career_restraint: Some(false),
+                career_restraint: Some(career_restraint),
                 critical: Some(is_crit),
                 assassinate: Some(false),
                 hurt_effect: Some(if is_crit {
@@ -530,6 +656,26 @@ impl SkillExecutor {
         }
     }
 
+    /// Shield worth `rate` per mille of the caster's attack
+    fn calculate_shield_effect(
+        &self,
+        caster_uid: i64,
+        target_uid: i64,
+        rate: i32,
+        buff_mgr: &BuffMgr,
+    ) -> Option<ActEffect> {
+        let caster = self.entities.get(&caster_uid)?;
+        let attack = FightCalculateDataMgr::combat_attr(caster, buff_mgr).attack;
+        let shield = (attack as i64 * rate as i64 / 1000).clamp(1, i32::MAX as i64) as i32;
+
+        Some(ActEffect {
+            effect_type: Some(EffectType::Shield as i32),
+            target_id: Some(target_uid),
+            effect_num: Some(shield),
+            ..Default::default()
+        })
+    }
+
     fn create_heal_effect(&self, target_id: i64, heal: i32, is_crit: bool) -> ActEffect {
         ActEffect {
             effect_type: Some(if is_crit {
@@ -544,34 +690,6 @@ impl SkillExecutor {
         }
     }
 
-    fn create_buff_effect(&self, caster_uid: i64, target_id: i64, buff_id: i32) -> ActEffect {
-        let duration = config::configs::get()
-            .skill_buff
-            .iter()
-            .find(|b| b.id == buff_id)
-            .map(|b| b.during_time)
-            .unwrap_or(0);
-
-        ActEffect {
-            effect_type: Some(EffectType::Buffadd as i32),
-            target_id: Some(target_id),
-            effect_num: Some(buff_id),
-            buff: Some(BuffInfo {
-                buff_id: Some(buff_id),
-                duration: Some(duration),
-                uid: Some(BUFF_UID_COUNTER.fetch_add(1, Ordering::SeqCst)),
-                ex_info: Some(0),
-                from_uid: Some(caster_uid),
-                count: Some(0),
-                layer: Some(1),
-                r#type: Some(0),
-                act_common_params: Some(String::new()),
-                act_info: vec![],
-            }),
-            ..Default::default()
-        }
-    }
-
     fn get_condition(&self, skill_id: i32, index: i32) -> String {
         let game_data = configs::get();
         let skill = game_data.skill_effect.iter().find(|s| s.id == skill_id);
@@ -604,23 +722,25 @@ impl SkillExecutor {
         }
     }
 
-    fn get_condition_target(&self, skill_id: i32, index: i32) -> i32 {
+    fn get_condition_target(&self, skill_id: i32, index: i32) -> Target {
         let game_data = configs::get();
         let skill = game_data.skill_effect.iter().find(|s| s.id == skill_id);
-        let Some(skill) = skill else { return 0 };
+        let Some(skill) = skill else {
+            return Target::from(0);
+        };
 
         match index {
-            1 => skill.condition_target1.parse::<i32>().unwrap_or(0),
-            2 => skill.condition_target2.parse::<i32>().unwrap_or(0),
-            3 => skill.condition_target3.parse::<i32>().unwrap_or(0),
-            4 => skill.condition_target4.parse::<i32>().unwrap_or(0),
-            5 => skill.condition_target5.parse::<i32>().unwrap_or(0),
-            6 => skill.condition_target6.parse::<i32>().unwrap_or(0),
-            7 => skill.condition_target7.parse::<i32>().unwrap_or(0),
-            8 => skill.condition_target8.parse::<i32>().unwrap_or(0),
-            9 => skill.condition_target9.parse::<i32>().unwrap_or(0),
-
-            _ => 0,
+            1 => Target::parse(&skill.condition_target1),
+            2 => Target::parse(&skill.condition_target2),
+            3 => Target::parse(&skill.condition_target3),
+            4 => Target::parse(&skill.condition_target4),
+            5 => Target::parse(&skill.condition_target5),
+            6 => Target::parse(&skill.condition_target6),
+            7 => Target::parse(&skill.condition_target7),
+            8 => Target::parse(&skill.condition_target8),
+            9 => Target::parse(&skill.condition_target9),
+
+            _ => Target::from(0),
         }
     }
 
@@ -646,24 +766,26 @@ impl SkillExecutor {
         }
     }
 
-    fn get_behavior_target(&self, skill_id: i32, index: i32) -> i32 {
+    fn get_behavior_target(&self, skill_id: i32, index: i32) -> Target {
         let game_data = configs::get();
         let skill = game_data.skill_effect.iter().find(|s| s.id == skill_id);
-        let Some(skill) = skill else { return 0 };
+        let Some(skill) = skill else {
+            return Target::from(0);
+        };
 
         // game only tracks up to 9 even tho 20 is defined lmao
         match index {
-            1 => skill.behavior_target1.parse::<i32>().unwrap_or(0),
-            2 => skill.behavior_target2.parse::<i32>().unwrap_or(0),
-            3 => skill.behavior_target3.parse::<i32>().unwrap_or(0),
-            4 => skill.behavior_target4.parse::<i32>().unwrap_or(0),
-            5 => skill.behavior_target5.parse::<i32>().unwrap_or(0),
-            6 => skill.behavior_target6.parse::<i32>().unwrap_or(0),
-            7 => skill.behavior_target7.parse::<i32>().unwrap_or(0),
-            8 => skill.behavior_target8.parse::<i32>().unwrap_or(0),
-            9 => skill.behavior_target9.parse::<i32>().unwrap_or(0),
-
-            _ => 0,
+            1 => Target::parse(&skill.behavior_target1),
+            2 => Target::parse(&skill.behavior_target2),
+            3 => Target::parse(&skill.behavior_target3),
+            4 => Target::parse(&skill.behavior_target4),
+            5 => Target::parse(&skill.behavior_target5),
+            6 => Target::parse(&skill.behavior_target6),
+            7 => Target::parse(&skill.behavior_target7),
+            8 => Target::parse(&skill.behavior_target8),
+            9 => Target::parse(&skill.behavior_target9),
+
+            _ => Target::from(0),
         }
     }
 }
@@ -1,3 +1,4 @@
+use super::manager::buff_mgr::BuffMgr;
 use super::utils::*;
 use sonettobuf::{ActEffect, Fight, FightStep, effect_type_enum::EffectType, fight_step};
 
@@ -221,12 +222,13 @@ impl FightStepBuilder {
         fight: &Fight,
         team_type: i32,
         buff_id: i32,
+        buff_mgr: &BuffMgr,
     ) -> FightStep {
         let team_uids = get_team_uids(fight, team_type);
         let mut inner_effects = Vec::new();
 
         for target_uid in team_uids {
-            inner_effects.push(buff_add(target_uid, from_uid, buff_id));
+            inner_effects.push(buff_add(buff_mgr, target_uid, from_uid, buff_id));
         }
 
         FightStepBuilder::new_effect()
@@ -0,0 +1,414 @@
+//! Skill targets
+//!
+//! `behaviorTargetN` and `conditionTargetN` of a `skill_effect` row pick the units a slot
+//! acts on or checks. The value is a target code, the random picks take the unit count
+//! after a `#` (one when it's left out):
+//!
+//! | code     | units                                                          |
+//! |----------|----------------------------------------------------------------|
+//! | 0, 102   | the caster                                                     |
+//! | 1, 2     | the selected target                                            |
+//! | 101, 103 | the caster's side                                              |
+//! | 104      | the caster's side without the caster                           |
+//! | 105      | the ally with the lowest HP share                              |
+//! | 106      | the caster's neighbours                                        |
+//! | 107      | the caster's side's summoned units                             |
+//! | 201, 202 | the other side                                                 |
+//! | 203#N    | N random enemies                                               |
+//! | 204      | the enemy with the lowest HP share                             |
+//! | 205      | the selected target's neighbours                               |
+//! | 206      | the selected target and its neighbours                         |
+//! | 207      | the other side's summoned units                                |
+//! | 301      | whoever attacked the caster last                               |
+//! | 999      | the selected target, the caster when there is none             |
+//!
+//! Neighbours are the closest living units by position on either side. Everything but the
+//! whole side picks (101, 103, 201, 202) skips the dead and the benched.
+
+use std::collections::HashMap;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use sonettobuf::{FightEntityInfo, FightStep};
+
+use crate::state::battle::effects::effect_types::EffectType;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Target {
+    pub code: i32,
+    pub count: usize,
+}
+
+impl Target {
+    /// Slot target that takes over the condition target
+    pub const INHERIT: i32 = 999;
+
+    pub fn parse(target: &str) -> Self {
+        let mut parts = target.split('#').map(str::trim);
+        let code = parts.next().and_then(|c| c.parse().ok()).unwrap_or(0);
+        let count = parts.next().and_then(|c| c.parse().ok()).unwrap_or(1);
+
+        Self { code, count }
+    }
+}
+
+impl From<i32> for Target {
+    fn from(code: i32) -> Self {
+        Self { code, count: 1 }
+    }
+}
+
+/// Units a skill can pick from, with what the pickers need besides the units themselves
+pub struct TargetPool<'a> {
+    pub entities: &'a HashMap<i64, FightEntityInfo>,
+    /// Last unit to damage each unit
+    pub last_attackers: &'a HashMap<i64, i64>,
+}
+
+fn on_field(e: &FightEntityInfo) -> bool {
+    e.current_hp.unwrap_or(0) > 0 && e.position.unwrap_or(0) > 0
+}
+
+fn hp_share(e: &FightEntityInfo) -> (i64, i64) {
+    let hp = e.current_hp.unwrap_or(0) as i64;
+    let max_hp = e.attr.as_ref().and_then(|a| a.hp).unwrap_or(0).max(1) as i64;
+    (hp, max_hp)
+}
+
+impl TargetPool<'_> {
+    fn team_of(&self, uid: i64) -> Option<i32> {
+        self.entities.get(&uid).and_then(|e| e.team_type)
+    }
+
+    /// Units of the side, sorted by uid so seeded picks stay the same
+    fn side(&self, team_type: Option<i32>) -> Vec<&FightEntityInfo> {
+        let mut units: Vec<_> = self
+            .entities
+            .values()
+            .filter(|e| e.team_type == team_type)
+            .collect();
+        units.sort_by_key(|e| e.uid);
+        units
+    }
+
+    fn other_side(&self, team_type: Option<i32>) -> Vec<&FightEntityInfo> {
+        let mut units: Vec<_> = self
+            .entities
+            .values()
+            .filter(|e| e.team_type != team_type && e.team_type.is_some())
+            .collect();
+        units.sort_by_key(|e| e.uid);
+        units
+    }
+
+    fn living(units: Vec<&FightEntityInfo>) -> Vec<&FightEntityInfo> {
+        units.into_iter().filter(|e| on_field(e)).collect()
+    }
+
+    fn uids(units: Vec<&FightEntityInfo>) -> Vec<i64> {
+        units.into_iter().filter_map(|e| e.uid).collect()
+    }
+
+    fn lowest_hp(units: Vec<&FightEntityInfo>) -> Vec<i64> {
+        units
+            .into_iter()
+            .min_by(|a, b| {
+                let (hp_a, max_a) = hp_share(a);
+                let (hp_b, max_b) = hp_share(b);
+                (hp_a * max_b).cmp(&(hp_b * max_a)).then(a.uid.cmp(&b.uid))
+            })
+            .and_then(|e| e.uid)
+            .into_iter()
+            .collect()
+    }
+
+    /// Closest living units by position on either side of the unit
+    pub fn neighbours(&self, uid: i64) -> Vec<i64> {
+        let Some(unit) = self.entities.get(&uid) else {
+            return vec![];
+        };
+        let position = unit.position.unwrap_or(0);
+
+        let side = Self::living(self.side(unit.team_type));
+        let left = side
+            .iter()
+            .filter(|e| e.position.unwrap_or(0) < position)
+            .max_by_key(|e| e.position);
+        let right = side
+            .iter()
+            .filter(|e| e.position.unwrap_or(0) > position)
+            .min_by_key(|e| e.position);
+
+        left.into_iter()
+            .chain(right)
+            .filter_map(|e| e.uid)
+            .collect()
+    }
+
+    fn summoned(&self, units: Vec<&FightEntityInfo>) -> Vec<i64> {
+        units
+            .into_iter()
+            .flat_map(|e| e.summoned_list.iter())
+            .filter_map(|s| s.uid)
+            .collect()
+    }
+
+    pub fn resolve(
+        &self,
+        caster_uid: i64,
+        target_uid: i64,
+        target: Target,
+        rng: &mut StdRng,
+    ) -> Vec<i64> {
+        let team = self.team_of(caster_uid);
+
+        match target.code {
+            0 | 102 => vec![caster_uid],
+
+            1 | 2 => vec![target_uid],
+
+            101 | 103 => Self::uids(self.side(team)),
+
+            104 => Self::uids(Self::living(self.side(team)))
+                .into_iter()
+                .filter(|uid| *uid != caster_uid)
+                .collect(),
+
+            105 => Self::lowest_hp(Self::living(self.side(team))),
+
+            106 => self.neighbours(caster_uid),
+
+            107 => self.summoned(self.side(team)),
+
+            201 | 202 => Self::uids(self.other_side(team)),
+
+            203 => {
+                let mut enemies = Self::uids(Self::living(self.other_side(team)));
+                let count = target.count.min(enemies.len());
+                let (picked, _) = enemies.partial_shuffle(rng, count);
+                picked.to_vec()
+            }
+
+            204 => Self::lowest_hp(Self::living(self.other_side(team))),
+
+            205 => self.neighbours(target_uid),
+
+            206 => {
+                let mut units = vec![target_uid];
+                units.extend(self.neighbours(target_uid));
+                units
+            }
+
+            207 => self.summoned(self.other_side(team)),
+
+            301 => self
+                .last_attackers
+                .get(&caster_uid)
+                .filter(|uid| self.entities.get(uid).is_some_and(on_field))
+                .copied()
+                .into_iter()
+                .collect(),
+
+            Target::INHERIT => {
+                if target_uid != 0 && self.entities.contains_key(&target_uid) {
+                    vec![target_uid]
+                } else {
+                    tracing::warn!("behavior_target=999 but no valid target; defaulting to self");
+                    vec![caster_uid]
+                }
+            }
+
+            code => {
+                tracing::warn!("Unknown behavior_target {}, defaulting to caster", code);
+                vec![caster_uid]
+            }
+        }
+    }
+}
+
+/// Note who damaged whom in the step and its nested steps, in play order
+pub fn record_attacks(step: &FightStep, last_attackers: &mut HashMap<i64, i64>) {
+    let attacker_uid = step.from_id.unwrap_or(0);
+
+    for effect in &step.act_effect {
+        if let Some(nested) = &effect.fight_step {
+            record_attacks(nested, last_attackers);
+            continue;
+        }
+
+        record_attack(attacker_uid, effect, last_attackers);
+    }
+}
+
+/// Note the attacker of one effect, nested steps are left to the caller
+pub fn record_attack(
+    attacker_uid: i64,
+    effect: &sonettobuf::ActEffect,
+    last_attackers: &mut HashMap<i64, i64>,
+) {
+    let is_hit = matches!(
+        EffectType::from(effect.effect_type.unwrap_or(0)),
+        EffectType::Damage | EffectType::Crit
+    );
+
+    if is_hit
+        && let Some(target_uid) = effect.target_id
+        && target_uid != attacker_uid
+        && attacker_uid != 0
+    {
+        last_attackers.insert(target_uid, attacker_uid);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use sonettobuf::{HeroAttribute, SummonedInfo};
+
+    fn unit(uid: i64, team_type: i32, position: i32, hp: i32) -> FightEntityInfo {
+        FightEntityInfo {
+            uid: Some(uid),
+            team_type: Some(team_type),
+            position: Some(position),
+            current_hp: Some(hp),
+            attr: Some(HeroAttribute {
+                hp: Some(100),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn summoning(mut unit: FightEntityInfo, summoned_uid: i64) -> FightEntityInfo {
+        unit.summoned_list.push(SummonedInfo {
+            uid: Some(summoned_uid),
+            ..Default::default()
+        });
+        unit
+    }
+
+    /// Heroes 1..=4 on the field (3 dead), 5 benched; enemies -1..=-3
+    fn entities() -> HashMap<i64, FightEntityInfo> {
+        [
+            summoning(unit(1, 1, 1, 100), 11),
+            unit(2, 1, 2, 30),
+            unit(3, 1, 3, 0),
+            unit(4, 1, 4, 50),
+            unit(5, 1, 0, 100),
+            unit(-1, 2, 1, 80),
+            summoning(unit(-2, 2, 2, 20), -10),
+            unit(-3, 2, 3, 60),
+        ]
+        .into_iter()
+        .map(|e| (e.uid.unwrap(), e))
+        .collect()
+    }
+
+    fn resolve(caster: i64, target: i64, code: &str, last_attackers: &[(i64, i64)]) -> Vec<i64> {
+        let entities = entities();
+        let last_attackers = last_attackers.iter().copied().collect();
+        let pool = TargetPool {
+            entities: &entities,
+            last_attackers: &last_attackers,
+        };
+        let mut rng = StdRng::seed_from_u64(7);
+        pool.resolve(caster, target, Target::parse(code), &mut rng)
+    }
+
+    #[test]
+    fn parse_count() {
+        assert_eq!(
+            Target::parse("203#3"),
+            Target {
+                code: 203,
+                count: 3
+            }
+        );
+        assert_eq!(
+            Target::parse("203"),
+            Target {
+                code: 203,
+                count: 1
+            }
+        );
+        assert_eq!(Target::parse(""), Target { code: 0, count: 1 });
+    }
+
+    #[test]
+    fn caster_and_selected() {
+        assert_eq!(resolve(1, -2, "0", &[]), vec![1]);
+        assert_eq!(resolve(1, -2, "102", &[]), vec![1]);
+        assert_eq!(resolve(1, -2, "1", &[]), vec![-2]);
+        assert_eq!(resolve(1, -2, "2", &[]), vec![-2]);
+    }
+
+    #[test]
+    fn whole_sides() {
+        assert_eq!(resolve(1, -2, "101", &[]), vec![1, 2, 3, 4, 5]);
+        assert_eq!(resolve(1, -2, "103", &[]), vec![1, 2, 3, 4, 5]);
+        assert_eq!(resolve(1, -2, "201", &[]), vec![-3, -2, -1]);
+        assert_eq!(resolve(-1, 1, "202", &[]), vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn allies_without_caster() {
+        // 3 is dead and 5 benched
+        assert_eq!(resolve(1, -2, "104", &[]), vec![2, 4]);
+    }
+
+    #[test]
+    fn lowest_hp_share() {
+        assert_eq!(resolve(1, -2, "105", &[]), vec![2]);
+        assert_eq!(resolve(1, -1, "204", &[]), vec![-2]);
+    }
+
+    #[test]
+    fn caster_neighbours() {
+        // the dead 3 is skipped for 4
+        assert_eq!(resolve(2, -2, "106", &[]), vec![1, 4]);
+        assert_eq!(resolve(1, -2, "106", &[]), vec![2]);
+    }
+
+    #[test]
+    fn target_neighbours() {
+        assert_eq!(resolve(1, -2, "205", &[]), vec![-1, -3]);
+        assert_eq!(resolve(1, -2, "206", &[]), vec![-2, -1, -3]);
+        assert_eq!(resolve(1, -1, "206", &[]), vec![-1, -2]);
+    }
+
+    #[test]
+    fn summoned_units() {
+        assert_eq!(resolve(1, -2, "107", &[]), vec![11]);
+        assert_eq!(resolve(1, -2, "207", &[]), vec![-10]);
+    }
+
+    #[test]
+    fn random_enemies() {
+        let picked = resolve(1, -2, "203#2", &[]);
+        assert_eq!(picked.len(), 2);
+        assert!(picked.iter().all(|uid| [-1, -2, -3].contains(uid)));
+        assert_ne!(picked[0], picked[1]);
+        // the same seed picks the same units
+        assert_eq!(resolve(1, -2, "203#2", &[]), picked);
+
+        let mut all = resolve(1, -2, "203#5", &[]);
+        all.sort();
+        assert_eq!(all, vec![-3, -2, -1]);
+    }
+
+    #[test]
+    fn last_attacker() {
+        assert_eq!(resolve(1, -2, "301", &[(1, -3)]), vec![-3]);
+        assert_eq!(resolve(1, -2, "301", &[]), Vec::<i64>::new());
+        // the attacker died since
+        assert_eq!(resolve(4, -2, "301", &[(4, 3)]), Vec::<i64>::new());
+    }
+
+    #[test]
+    fn inherit_and_unknown() {
+        assert_eq!(resolve(1, -2, "999", &[]), vec![-2]);
+        assert_eq!(resolve(1, 0, "999", &[]), vec![1]);
+        assert_eq!(resolve(1, -2, "12345", &[]), vec![1]);
+    }
+}
@@ -0,0 +1,93 @@
+//! Uids of the buffs and cards a fight creates
+//!
+//! Every fight counts its own uids from a fixed start, so a replay or a resumed fight
+//! hands out the same uids as the original one. A clone (like the buffs a round works
+//! on) carries on from the same value, and `observe` moves a counter past the uids a
+//! clone gave out once its changes are applied.
+
+use std::sync::atomic::{AtomicI64, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct UidCounter(AtomicI64);
+
+impl UidCounter {
+    pub const fn starting_at(first: i64) -> Self {
+        Self(AtomicI64::new(first))
+    }
+
+    pub fn next(&self) -> i64 {
+        self.0.fetch_add(1, Ordering::SeqCst)
+    }
+
+    /// Keep the uids handed out after `uid`
+    pub fn observe(&self, uid: i64) {
+        self.0.fetch_max(uid + 1, Ordering::SeqCst);
+    }
+
+    /// Keep the uids a clone of this counter handed out
+    pub fn catch_up(&self, clone: &UidCounter) {
+        self.0.fetch_max(clone.peek(), Ordering::SeqCst);
+    }
+
+    /// The uid `next` hands out
+    pub fn peek(&self) -> i64 {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+impl Default for UidCounter {
+    fn default() -> Self {
+        Self::starting_at(1)
+    }
+}
+
+impl Clone for UidCounter {
+    fn clone(&self) -> Self {
+        Self::starting_at(self.peek())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_fight_counts_from_the_same_start() {
+        let first = UidCounter::starting_at(2);
+        let second = UidCounter::starting_at(2);
+
+        assert_eq!((first.next(), first.next()), (2, 3));
+        assert_eq!(second.next(), 2);
+    }
+
+    #[test]
+    fn a_counter_keeps_the_uids_of_its_clones() {
+        let counter = UidCounter::default();
+        let round = counter.clone();
+        assert_eq!(round.next(), 1);
+        assert_eq!(round.next(), 2);
+
+        counter.catch_up(&round);
+        assert_eq!(counter.next(), 3);
+
+        counter.observe(10);
+        assert_eq!(counter.next(), 11);
+        counter.observe(4);
+        assert_eq!(counter.next(), 12);
+    }
+
+    #[test]
+    fn a_saved_counter_carries_on_where_it_stopped() {
+        let counter = UidCounter::starting_at(2);
+        counter.next();
+
+        let json = serde_json::to_string(&counter).unwrap();
+        assert_eq!(json, "3");
+
+        let restored: UidCounter = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.next(), 3);
+    }
+}
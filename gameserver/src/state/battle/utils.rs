@@ -1,8 +1,7 @@
-use super::BUFF_UID_COUNTER;
+use super::manager::buff_mgr::BuffMgr;
 use sonettobuf::{
     ActEffect, BuffInfo, Fight, FightEntityInfo, FightHurtInfo, effect_type_enum::EffectType,
 };
-use std::sync::atomic::Ordering;
 
 //skill_behaviour table
 pub enum VfxConfig {
@@ -11,28 +10,34 @@ pub enum VfxConfig {
     Moxie = 20002,
 }
 
-pub fn buff_add(target_uid: i64, from_uid: i64, buff_id: i32) -> ActEffect {
+pub fn buff_add(buff_mgr: &BuffMgr, target_uid: i64, from_uid: i64, buff_id: i32) -> ActEffect {
     ActEffect {
         effect_type: Some(EffectType::Buffadd as i32),
         target_id: Some(target_uid),
         effect_num: Some(buff_id),
-        buff: Some(create_buff(buff_id, from_uid, 0, "")),
+        buff: Some(create_buff(buff_mgr, buff_id, from_uid, 0, "")),
         ..Default::default()
     }
 }
 
 #[allow(dead_code)]
-pub fn buff_add_with_count(target_uid: i64, buff_id: i32, count: i32) -> ActEffect {
+pub fn buff_add_with_count(
+    buff_mgr: &BuffMgr,
+    target_uid: i64,
+    buff_id: i32,
+    count: i32,
+) -> ActEffect {
     ActEffect {
         effect_type: Some(EffectType::Buffadd as i32),
         target_id: Some(target_uid),
         effect_num: Some(buff_id),
-        buff: Some(create_buff(buff_id, target_uid, count, "")),
+        buff: Some(create_buff(buff_mgr, buff_id, target_uid, count, "")),
         ..Default::default()
     }
 }
 
 pub fn buff_add_with_params(
+    buff_mgr: &BuffMgr,
     target_uid: i64,
     from_uid: i64,
     buff_id: i32,
@@ -42,7 +47,7 @@ pub fn buff_add_with_params(
         effect_type: Some(EffectType::Buffadd as i32),
         target_id: Some(target_uid),
         effect_num: Some(buff_id),
-        buff: Some(create_buff(buff_id, from_uid, 0, params)),
+        buff: Some(create_buff(buff_mgr, buff_id, from_uid, 0, params)),
         ..Default::default()
     }
 }
@@ -194,11 +199,17 @@ pub fn effect_none(target_uid: i64) -> ActEffect {
     }
 }
 
-fn create_buff(buff_id: i32, from_uid: i64, count: i32, params: &str) -> BuffInfo {
+fn create_buff(
+    buff_mgr: &BuffMgr,
+    buff_id: i32,
+    from_uid: i64,
+    count: i32,
+    params: &str,
+) -> BuffInfo {
     BuffInfo {
         buff_id: Some(buff_id),
         duration: Some(0),
-        uid: Some(BUFF_UID_COUNTER.fetch_add(1, Ordering::SeqCst)),
+        uid: Some(buff_mgr.next_uid()),
         ex_info: Some(0),
         from_uid: Some(from_uid),
         count: Some(count),
@@ -0,0 +1,165 @@
+//! Replay verification
+//!
+//! Every fight is recorded with the state it started from: the fight data as the opening
+//! round left it and both decks. The verifier loads that state, feeds the recorded opers
+//! back round by round with the fight's seed and compares the result with what was
+//! recorded when the fight was played. Heroes leveled up since don't change the replay.
+//! Fights recorded before start states were stored are skipped.
+
+use anyhow::Result;
+use database::db::game::battle::{load_recorded_battles, load_round_operations, save_battle_start};
+use database::models::game::battle::{BattleOutcome, BattleResult, EntityOutcome, RecordedBattle};
+use serde::{Deserialize, Serialize};
+use sonettobuf::{CardInfo, Fight, FightGroup};
+use sqlx::SqlitePool;
+
+use super::manager::fight_data_mgr::FightDataMgr;
+use super::simulator::BattleSimulator;
+
+/// What a fight started from
+#[derive(Serialize, Deserialize)]
+pub struct BattleStart {
+    pub fight_data: FightDataMgr,
+    pub deck: Vec<CardInfo>,
+    pub ai_deck: Vec<CardInfo>,
+}
+
+/// Record a fight as it starts, `fight_id` is the id its seed and rounds are saved under
+pub async fn record_battle_start(
+    pool: &SqlitePool,
+    user_id: i64,
+    fight_id: i64,
+    episode_id: i32,
+    chapter_id: i32,
+    fight_group: &FightGroup,
+    start: &BattleStart,
+) -> Result<()> {
+    let result = BattleResult {
+        user_id,
+        battle_id: fight_id,
+        episode_id,
+        chapter_id,
+        fight_group: fight_group.clone(),
+        outcome: battle_outcome(start.fight_data.fight(), 0),
+    };
+
+    save_battle_start(pool, &result, &serde_json::to_string(start)?).await
+}
+
+pub fn battle_outcome(fight: &Fight, rounds: i32) -> BattleOutcome {
+    let mut entities: Vec<EntityOutcome> = [&fight.attacker, &fight.defender]
+        .into_iter()
+        .flatten()
+        .flat_map(|team| team.entitys.iter())
+        .map(|e| {
+            let hp = e.current_hp.unwrap_or(0);
+            EntityOutcome {
+                uid: e.uid.unwrap_or(0),
+                hp,
+                dead: hp <= 0,
+            }
+        })
+        .collect();
+
+    entities.sort_by_key(|e| e.uid);
+
+    BattleOutcome { rounds, entities }
+}
+
+/// Run a recorded fight again from its start state and return how it ended this time
+pub async fn resimulate(pool: &SqlitePool, battle: &RecordedBattle) -> Result<BattleOutcome> {
+    let result = &battle.result;
+    let start: BattleStart = serde_json::from_str(&battle.start_state)?;
+
+    let mut simulator = BattleSimulator::new(start.fight_data, battle.seed);
+    let mut deck = start.deck;
+    let mut ai_deck = start.ai_deck;
+    let mut rounds = 0;
+
+    for (round_num, record) in load_round_operations(pool, result.user_id, result.battle_id).await?
+    {
+        let round = simulator
+            .replay_round(round_num, record, deck, ai_deck)
+            .await?;
+
+        deck = round.team_a_cards1;
+        ai_deck = round.ai_use_cards;
+        rounds = round.cur_round.unwrap_or(round_num);
+    }
+
+    Ok(battle_outcome(&simulator.fight_snapshot(), rounds))
+}
+
+/// Differences between the recorded and the re-simulated outcome, empty when they match
+pub fn compare_outcomes(recorded: &BattleOutcome, replayed: &BattleOutcome) -> Vec<String> {
+    let mut diffs = Vec::new();
+
+    if recorded.rounds != replayed.rounds {
+        diffs.push(format!(
+            "rounds: recorded {}, replayed {}",
+            recorded.rounds, replayed.rounds
+        ));
+    }
+
+    for entity in &recorded.entities {
+        let Some(other) = replayed.entities.iter().find(|e| e.uid == entity.uid) else {
+            diffs.push(format!("entity {}: missing from replay", entity.uid));
+            continue;
+        };
+
+        if entity.dead != other.dead {
+            diffs.push(format!(
+                "entity {}: recorded {}, replayed {}",
+                entity.uid,
+                if entity.dead { "dead" } else { "alive" },
+                if other.dead { "dead" } else { "alive" }
+            ));
+        } else if entity.hp != other.hp {
+            diffs.push(format!(
+                "entity {}: recorded hp {}, replayed hp {}",
+                entity.uid, entity.hp, other.hp
+            ));
+        }
+    }
+
+    for entity in &replayed.entities {
+        if !recorded.entities.iter().any(|e| e.uid == entity.uid) {
+            diffs.push(format!("entity {}: only in replay", entity.uid));
+        }
+    }
+
+    diffs
+}
+
+pub struct VerifyReport {
+    pub checked: usize,
+    /// (user_id, battle_id, differences or the error that stopped the replay)
+    pub failures: Vec<(i64, i64, Vec<String>)>,
+}
+
+pub async fn verify_battles(
+    pool: &SqlitePool,
+    user_id: Option<i64>,
+    episode_id: Option<i32>,
+) -> Result<VerifyReport> {
+    let battles = load_recorded_battles(pool, user_id, episode_id).await?;
+    let mut failures = Vec::new();
+
+    for battle in &battles {
+        let result = &battle.result;
+
+        let diffs = match resimulate(pool, battle).await {
+            Ok(replayed) => compare_outcomes(&result.outcome, &replayed),
+            Err(e) => vec![format!("replay failed: {:#}", e)],
+        };
+
+        if !diffs.is_empty() {
+            failures.push((result.user_id, result.battle_id, diffs));
+        }
+    }
+
+    Ok(VerifyReport {
+        checked: battles.len(),
+        failures,
+    })
+}
@@ -3,7 +3,7 @@ use prost::Message;
 use std::collections::VecDeque;
 use std::sync::Arc;
 use tokio::net::TcpStream;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Notify};
 
 use crate::error::AppError;
 
@@ -28,6 +28,9 @@ pub struct ConnectionContext {
     pub active_battle: Option<ActiveBattle>,
     pub bot_welcome_sent: bool,
     pub bot_msg_counter: u64,
+
+    // wakes the client loop so packets queued outside a request get flushed
+    pub flush_signal: Arc<Notify>,
 }
 
 #[allow(dead_code)]
@@ -41,6 +44,7 @@ pub struct ActiveBattle {
     pub difficulty: Option<i32>,
     pub talent_plan_id: Option<i32>,
     pub fight: Option<sonettobuf::Fight>, // Current battle state
+    pub last_round: Option<sonettobuf::FightRound>, // Last round sent to the client
     pub current_round: i32,
     pub act_point: i32, //  Remaining action points
     pub power: i32,
@@ -48,10 +52,12 @@ pub struct ActiveBattle {
     pub fight_group: Option<sonettobuf::FightGroup>,
     pub fight_id: Option<i64>,
     pub is_replay: Option<bool>,
-    pub replay_episode_id: Option<i32>,
+    pub replay_battle_id: Option<i64>, // recorded fight a replay plays back
     pub multiplication: Option<i32>,
     pub ai_deck: Vec<sonettobuf::CardInfo>,
+    pub cloth_opers: Vec<sonettobuf::UseClothSkillOperRecord>, // Cloth skills used this round
     pub fight_data_mgr: Option<FightDataMgr>,
+    pub seed: u64, // every random roll of the fight derives from it
 }
 
 #[allow(dead_code)]
@@ -68,6 +74,7 @@ impl ConnectionContext {
             active_battle: None,
             bot_welcome_sent: false,
             bot_msg_counter: 0,
+            flush_signal: Arc::new(Notify::new()),
         }
     }
 
@@ -344,6 +351,11 @@ impl ConnectionContext {
         Ok(())
     }
 
+    /// Ask the client loop to flush queued packets without waiting for a request
+    pub fn request_flush(&self) {
+        self.flush_signal.notify_one();
+    }
+
     pub async fn flush_send_queue(&mut self) -> Result<(), AppError> {
         let mut socket = self.socket.lock().await;
 
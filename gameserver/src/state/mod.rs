@@ -8,9 +8,17 @@ mod player;
 
 pub use app::AppState;
 pub use battle::{
-    BattleContext, create_battle, default_max_ap, end_fight::send_end_fight_push,
-    generate_auto_opers, generate_initial_deck, rewards::generate_dungeon_rewards,
+    BattleContext,
+    conditions::report_unknown_conditions,
+    create_battle, default_max_ap,
+    end_fight::send_end_fight_push,
+    generate_auto_opers, generate_initial_deck,
+    headless::{Policy, TeamSpec, seed_team, simulate},
+    resume::{forget_battle, persist_battle, restore_battle},
+    rewards::generate_dungeon_rewards,
+    rng::new_battle_seed,
     simulator::BattleSimulator,
+    verify::{BattleStart, battle_outcome, record_battle_start, verify_battles},
 };
 pub use connection::{ActiveBattle, ConnectionContext};
 pub use gacha::{
@@ -2,3 +2,4 @@ pub mod common;
 pub mod data_loader;
 pub mod inventory;
 pub mod push;
+pub mod reset;
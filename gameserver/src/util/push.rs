@@ -237,6 +237,23 @@ pub async fn send_end_dungeon_push(
     Ok(())
 }
 
+pub async fn send_fight_wave_push(
+    ctx: Arc<Mutex<ConnectionContext>>,
+    fight: sonettobuf::Fight,
+) -> Result<(), AppError> {
+    tracing::info!(
+        "Sent FightWavePush for wave {}",
+        fight.cur_wave.unwrap_or(1)
+    );
+
+    let push = sonettobuf::FightWavePush { fight: Some(fight) };
+
+    let mut conn = ctx.lock().await;
+    conn.notify(CmdId::FightWavePushCmd, push).await?;
+
+    Ok(())
+}
+
 pub async fn send_dungeon_update_push(
     ctx: Arc<Mutex<ConnectionContext>>,
     chapter_id: i32,
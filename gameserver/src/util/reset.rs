@@ -0,0 +1,160 @@
+use crate::error::AppError;
+use crate::handlers::sign_in::build_sign_in_info_reply;
+use crate::handlers::store::build_store_infos;
+use crate::state::{AppState, ConnectionContext};
+use crate::util::push::send_red_dot_push;
+use common::time::ServerTime;
+use database::db::game::sign_in;
+use sonettobuf::{CmdId, GetServerTimeReply, GetStoreInfosReply};
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+/// Run the daily / weekly / monthly resets that are due for a player
+/// returns (is_new_day, is_new_week, is_new_month)
+pub async fn apply_period_resets(
+    ctx: &Arc<Mutex<ConnectionContext>>,
+    user_id: i64,
+) -> Result<(bool, bool, bool), AppError> {
+    let db = {
+        let conn = ctx.lock().await;
+        conn.state.db.clone()
+    };
+
+    let (is_new_day, is_new_week, is_new_month) =
+        sign_in::process_daily_login(&db, user_id).await?;
+    if is_new_day {
+        sign_in::reset_daily_counters(&db, user_id).await?;
+    }
+    if is_new_week {
+        sign_in::reset_weekly_counters(&db, user_id).await?;
+    }
+    if is_new_month {
+        sign_in::reset_monthly_counters(&db, user_id).await?;
+    }
+
+    let mut conn = ctx.lock().await;
+    let now = ServerTime::now_ms();
+    let today = ServerTime::server_day(now);
+
+    conn.update_and_save_player_state(|state| {
+        if state.is_new_server_day(now) {
+            state.initial_login_complete = false;
+            state.last_sign_in_day = today;
+            state.last_daily_reset_time = Some(now);
+            state.month_card_claimed = false;
+            state.last_month_card_claim_timestamp = None;
+        }
+
+        if state.is_new_week(now) {
+            state.last_weekly_reset_time = Some(now);
+        }
+
+        if state.is_new_month(now) {
+            state.last_monthly_reset_time = Some(now);
+        }
+
+        state.last_sign_in_time = Some(now);
+    })
+    .await?;
+
+    Ok((is_new_day, is_new_week, is_new_month))
+}
+
+/// Fires at every server reset boundary and resets all online players
+pub async fn run_reset_scheduler(state: Arc<AppState>) {
+    loop {
+        let now = ServerTime::now_ms();
+        let next_reset = ServerTime::next_reset_ms(now);
+        tracing::info!("Next server reset in {}s", (next_reset - now) / 1000);
+
+        tokio::time::sleep(Duration::from_millis((next_reset - now).max(0) as u64)).await;
+
+        let sessions = state.sessions();
+        tracing::info!(
+            "Server reset reached, resetting {} sessions",
+            sessions.len()
+        );
+
+        for (player_id, ctx) in sessions {
+            if let Err(e) = reset_online_player(ctx, player_id).await {
+                tracing::error!("Failed to reset player {}: {}", player_id, e);
+            }
+        }
+    }
+}
+
+async fn reset_online_player(
+    ctx: Arc<Mutex<ConnectionContext>>,
+    player_id: i64,
+) -> Result<(), AppError> {
+    let (is_new_day, is_new_week, is_new_month) = apply_period_resets(&ctx, player_id).await?;
+
+    if !is_new_day && !is_new_week && !is_new_month {
+        return Ok(());
+    }
+
+    let pool = {
+        let conn = ctx.lock().await;
+        conn.state.db.clone()
+    };
+
+    let sign_in_info = build_sign_in_info_reply(&pool, player_id).await?;
+    let store_ids = refreshed_store_ids(is_new_day, is_new_week, is_new_month);
+    let store_infos = build_store_infos(&pool, player_id, &store_ids).await?;
+
+    {
+        let mut conn = ctx.lock().await;
+
+        // lets the client roll over to the new day
+        conn.notify(
+            CmdId::GetServerTimeCmd,
+            GetServerTimeReply {
+                server_time: Some(ServerTime::now_ms() as u64),
+                offset_time: Some(ServerTime::reset_shift_ms()),
+            },
+        )
+        .await?;
+
+        conn.notify(CmdId::GetSignInInfoCmd, sign_in_info).await?;
+
+        if !store_infos.is_empty() {
+            conn.notify(CmdId::GetStoreInfosCmd, GetStoreInfosReply { store_infos })
+                .await?;
+        }
+    }
+
+    send_red_dot_push(Arc::clone(&ctx), player_id, None).await?;
+
+    ctx.lock().await.request_flush();
+
+    tracing::info!(
+        "Reset online player {} (day: {}, week: {}, month: {})",
+        player_id,
+        is_new_day,
+        is_new_week,
+        is_new_month
+    );
+
+    Ok(())
+}
+
+/// Stores holding goods whose buy limits were just reset
+fn refreshed_store_ids(is_new_day: bool, is_new_week: bool, is_new_month: bool) -> Vec<i32> {
+    let game_data = config::configs::get();
+
+    game_data
+        .store_goods
+        .iter()
+        .filter(|g| match g.refresh_time {
+            1 => is_new_day,
+            2 => is_new_week,
+            3 => is_new_month,
+            _ => false,
+        })
+        .filter_map(|g| g.store_id.parse::<i32>().ok())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
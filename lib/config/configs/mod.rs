@@ -51,7 +51,9 @@ pub mod store_charge_optional;
 pub mod store_goods;
 pub mod summon;
 pub mod summon_pool;
+pub mod talent_cube_attr;
 pub mod talent_scheme;
+pub mod talent_style;
 pub mod talent_style_cost;
 
 use std::sync::OnceLock;
@@ -108,7 +110,9 @@ pub struct GameDB {
     pub store_goods: store_goods::StoreGoodsTable,
     pub summon: summon::SummonTable,
     pub summon_pool: summon_pool::SummonPoolTable,
+    pub talent_cube_attr: talent_cube_attr::TalentCubeAttrTable,
     pub talent_scheme: talent_scheme::TalentSchemeTable,
+    pub talent_style: talent_style::TalentStyleTable,
     pub talent_style_cost: talent_style_cost::TalentStyleCostTable,
 }
 
@@ -267,9 +271,15 @@ impl GameDB {
         let summon_pool = summon_pool::SummonPoolTable::load(
             &format!("{}/summon_pool.json", data_dir)
         ).map_err(|e| anyhow::anyhow!("Failed to load summon_pool.json: {}", e))?;
+        let talent_cube_attr = talent_cube_attr::TalentCubeAttrTable::load(
+            &format!("{}/talent_cube_attr.json", data_dir)
+        ).map_err(|e| anyhow::anyhow!("Failed to load talent_cube_attr.json: {}", e))?;
         let talent_scheme = talent_scheme::TalentSchemeTable::load(
             &format!("{}/talent_scheme.json", data_dir)
         ).map_err(|e| anyhow::anyhow!("Failed to load talent_scheme.json: {}", e))?;
+        let talent_style = talent_style::TalentStyleTable::load(
+            &format!("{}/talent_style.json", data_dir)
+        ).map_err(|e| anyhow::anyhow!("Failed to load talent_style.json: {}", e))?;
         let talent_style_cost = talent_style_cost::TalentStyleCostTable::load(
             &format!("{}/talent_style_cost.json", data_dir)
         ).map_err(|e| anyhow::anyhow!("Failed to load talent_style_cost.json: {}", e))?;
@@ -326,7 +336,9 @@ impl GameDB {
             store_goods,
             summon,
             summon_pool,
+            talent_cube_attr,
             talent_scheme,
+            talent_style,
             talent_style_cost,
         })
     }
@@ -35,6 +35,8 @@ pub const FILTER_TABLES: &[&str] = &[
     "store_goods",
     "character_cosume",
     "talent_style_cost",
+    "talent_style",
+    "talent_cube_attr",
     "skill_effect",
     "skill_behavior",
     "character_rank_replace",
@@ -0,0 +1,58 @@
+use crate::models::request::AdminGmReq;
+use crate::models::response::AdminGmRsp;
+use anyhow::{Result, bail};
+use axum::http::HeaderMap;
+use axum::response::Json;
+use common::{config, constant_time_eq, host};
+use gameserver::handlers::gm::api::{ApiCommand, ApiReply};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+/// Checks the key and forwards the command to the gameserver, which holds the sessions
+/// the command's pushes go to
+pub async fn post(headers: HeaderMap, axum::Json(req): axum::Json<AdminGmReq>) -> Json<AdminGmRsp> {
+    let api_key = &config().gm.admin_api_key;
+    let provided = headers
+        .get("x-admin-key")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+
+    if api_key.is_empty() || !constant_time_eq(provided.as_bytes(), api_key.as_bytes()) {
+        tracing::warn!("Rejected admin gm request");
+        return Json(AdminGmRsp::error(401, "unauthorized"));
+    }
+
+    let command = ApiCommand {
+        key: provided.to_string(),
+        user_id: req.user_id,
+        command: req.command,
+    };
+
+    match forward(&command).await {
+        Ok(reply) if reply.code == 200 => Json(AdminGmRsp::ok(reply.msg)),
+        Ok(reply) => Json(AdminGmRsp::error(reply.code, &reply.msg)),
+        Err(e) => {
+            tracing::error!("Failed to forward gm command: {}", e);
+            Json(AdminGmRsp::error(502, "gameserver unreachable"))
+        }
+    }
+}
+
+/// Send a command to the gameserver's GM api and wait for its reply
+async fn forward(command: &ApiCommand) -> Result<ApiReply> {
+    let addr = format!("{}:{}", host(), config().gm.admin_port);
+    let socket = TcpStream::connect(&addr).await?;
+    let (reader, mut writer) = socket.into_split();
+
+    let mut out = serde_json::to_string(command)?;
+    out.push('\n');
+    writer.write_all(out.as_bytes()).await?;
+
+    let mut line = String::new();
+    BufReader::new(reader).read_line(&mut line).await?;
+    if line.is_empty() {
+        bail!("GM api on {} closed the connection", addr);
+    }
+
+    Ok(serde_json::from_str(&line)?)
+}
@@ -1,4 +1,5 @@
 mod account;
+mod admin;
 mod game;
 mod index;
 mod jsp;
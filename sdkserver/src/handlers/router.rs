@@ -1,5 +1,5 @@
 use crate::AppState;
-use crate::handlers::{account, game, index, jsp, trade};
+use crate::handlers::{account, admin, game, index, jsp, trade};
 use axum::Router;
 use axum::routing::{get, post};
 use paste::paste;
@@ -70,6 +70,11 @@ router! {
 
 }
 
+router! {
+    admin;
+    "/admin/gm" post gm;
+}
+
 router! {
     index;
     "/" get home;
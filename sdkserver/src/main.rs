@@ -78,6 +78,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let without_encryption = handlers::router::game_router()
         .merge(handlers::router::jsp_router())
         .merge(handlers::router::index_router())
+        .merge(handlers::router::admin_router())
         .layer(axum::middleware::from_fn(full_logger));
 
     let app = with_encryption.merge(without_encryption).with_state(state);
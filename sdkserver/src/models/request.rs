@@ -233,3 +233,10 @@ pub struct CallbackQuery {
     pub foreign_invoice: Option<String>,
     pub invoice_id: Option<String>,
 }
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdminGmReq {
+    pub user_id: Option<i64>,
+    pub command: String,
+}
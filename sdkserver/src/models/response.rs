@@ -451,3 +451,23 @@ pub struct PaymentMethod {
     pub other_payment_methods: Option<String>,
     pub ext_payment_method_params: Option<String>,
 }
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdminGmRsp {
+    pub code: u16,
+    pub msg: String,
+}
+
+impl AdminGmRsp {
+    pub fn ok(msg: String) -> Self {
+        Self { code: 200, msg }
+    }
+
+    pub fn error(code: u16, msg: &str) -> Self {
+        Self {
+            code,
+            msg: msg.to_string(),
+        }
+    }
+}